@@ -0,0 +1,196 @@
+use crate::timeout::Timeout;
+
+/// Maximum value of a 12 bit conversion
+pub const ADC_MAX: u16 = 4095;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum AdcError {
+    /// No new conversions since the previous read
+    NoConversion,
+    /// The internal reference reads outside of its plausible range
+    Reference,
+    /// A channel is at or close to either end of the converter range
+    Railed,
+    /// A channel has returned exactly the same value for too long
+    Stuck,
+}
+
+/// Millivolt calibration based on the internal voltage reference (VREFINT). The
+/// factory calibration value `VREFINT_CAL` is the 12 bit reading of the reference
+/// at a known analog supply voltage, which makes it possible to calculate the
+/// actual supply voltage and with that the voltage of any other channel.
+///
+/// All readings are sums of the same number of oversampled conversions.
+///
+/// # Example
+/// ```
+/// use common::adc::VrefCalibration;
+/// // Calibrated at 3.3 V
+/// let vref = VrefCalibration::new(1500, 3300, (3000, 3600));
+///
+/// // Supply has dropped to 3.0 V, VREFINT reads higher than calibrated
+/// assert_eq!(vref.vdda(1650, 1), Ok(3000));
+/// assert_eq!(vref.millivolt(2048, 1650), Ok(1500));
+/// ```
+pub struct VrefCalibration {
+    vrefint_cal: u16,
+    cal_millivolt: u16,
+    vdda_range: (u16, u16),
+}
+
+impl VrefCalibration {
+    /// Create a new calibration.
+    ///
+    /// `vrefint_cal: u16` the factory calibrated VREFINT reading
+    ///
+    /// `cal_millivolt: u16` the supply voltage used during factory calibration
+    ///
+    /// `vdda_range: (u16, u16)` min/max plausible supply voltage in millivolts
+    pub fn new(vrefint_cal: u16, cal_millivolt: u16, vdda_range: (u16, u16)) -> Self {
+        Self {
+            vrefint_cal,
+            cal_millivolt,
+            vdda_range,
+        }
+    }
+
+    /// Calculate the analog supply voltage in millivolts from the sum of `samples` VREFINT
+    /// conversions, checked against the plausible range.
+    pub fn vdda(&self, vrefint_sum: u32, samples: u32) -> Result<u16, AdcError> {
+        if vrefint_sum == 0 {
+            return Err(AdcError::Reference);
+        }
+
+        let vdda = self.cal_millivolt as u64 * self.vrefint_cal as u64 * samples as u64
+            / vrefint_sum as u64;
+
+        if vdda < self.vdda_range.0 as u64 || vdda > self.vdda_range.1 as u64 {
+            return Err(AdcError::Reference);
+        }
+
+        Ok(vdda as u16)
+    }
+
+    /// Convert the sum of channel conversions to millivolts using the sum of the same
+    /// number of VREFINT conversions. The reference is not range checked here, use
+    /// `vdda()` for that.
+    pub fn millivolt(&self, raw_sum: u32, vrefint_sum: u32) -> Result<u16, AdcError> {
+        if vrefint_sum == 0 {
+            return Err(AdcError::Reference);
+        }
+
+        let millivolt = self.cal_millivolt as u64 * self.vrefint_cal as u64 * raw_sum as u64
+            / (vrefint_sum as u64 * ADC_MAX as u64);
+
+        u16::try_from(millivolt).map_err(|_| AdcError::Reference)
+    }
+}
+
+/// Plausibility check of the oversampled conversions of one ADC channel. Any single
+/// conversion within `rail_margin` of either end of the converter range is reported as
+/// railed. A channel with an analog filter in front of it will always have some noise,
+/// so a sum that is exactly the same for more than `stuck_limit` reads is reported as
+/// a stuck converter.
+///
+/// # Example
+/// ```
+/// use common::adc::{AdcChannelCheck, AdcError};
+/// let mut check = AdcChannelCheck::new(8, 1);
+///
+/// assert_eq!(check.check(&[1000, 1001, 1002, 1001]), Ok(4004));
+/// assert_eq!(check.check(&[1000, 1001, 1002, 1001]), Ok(4004));
+/// assert_eq!(check.check(&[1000, 1001, 1002, 1001]), Err(AdcError::Stuck));
+/// assert_eq!(check.check(&[1000, 4095, 1002, 1001]), Err(AdcError::Railed));
+/// ```
+pub struct AdcChannelCheck {
+    rail_margin: u16,
+    stuck: Timeout,
+    last: Option<u32>,
+}
+
+impl AdcChannelCheck {
+    /// Create a new check with a margin to the converter rails in raw counts and the number
+    /// of reads an unchanged value is accepted.
+    pub fn new(rail_margin: u16, stuck_limit: usize) -> Self {
+        Self {
+            rail_margin,
+            stuck: Timeout::new(stuck_limit),
+            last: None,
+        }
+    }
+
+    /// Check a set of oversampled conversions and return their sum
+    pub fn check(&mut self, samples: &[u16]) -> Result<u32, AdcError> {
+        let low = self.rail_margin;
+        let high = ADC_MAX.saturating_sub(self.rail_margin);
+
+        if samples
+            .iter()
+            .any(|&sample| sample <= low || sample >= high)
+        {
+            return Err(AdcError::Railed);
+        }
+
+        let sum = samples.iter().map(|&sample| sample as u32).sum();
+
+        if self.last == Some(sum) {
+            self.stuck.tick().map_err(|_| AdcError::Stuck)?;
+        } else {
+            self.stuck.reset();
+        }
+        self.last = Some(sum);
+
+        Ok(sum)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vdda() {
+        let vref = VrefCalibration::new(1500, 3300, (3000, 3600));
+
+        assert_eq!(vref.vdda(1500, 1), Ok(3300));
+        assert_eq!(vref.vdda(1500 * 16, 16), Ok(3300));
+        assert_eq!(vref.vdda(1400 * 16, 16), Ok(3535));
+        assert_eq!(vref.vdda(1300 * 16, 16), Err(AdcError::Reference));
+        assert_eq!(vref.vdda(1700 * 16, 16), Err(AdcError::Reference));
+        assert_eq!(vref.vdda(0, 16), Err(AdcError::Reference));
+    }
+
+    #[test]
+    fn millivolt() {
+        let vref = VrefCalibration::new(1500, 3300, (3000, 3600));
+
+        assert_eq!(vref.millivolt(0, 1500), Ok(0));
+        assert_eq!(vref.millivolt(4095, 1500), Ok(3300));
+        assert_eq!(vref.millivolt(2048 * 16, 1500 * 16), Ok(1650));
+        assert_eq!(vref.millivolt(2048, 0), Err(AdcError::Reference));
+    }
+
+    #[test]
+    fn railed() {
+        let mut check = AdcChannelCheck::new(8, 10);
+
+        assert_eq!(check.check(&[9, 10, 11]), Ok(30));
+        assert_eq!(check.check(&[9, 8, 11]), Err(AdcError::Railed));
+        assert_eq!(check.check(&[4086, 4085, 4086]), Ok(12257));
+        assert_eq!(check.check(&[4086, 4087, 4086]), Err(AdcError::Railed));
+    }
+
+    #[test]
+    fn stuck() {
+        let mut check = AdcChannelCheck::new(8, 2);
+
+        assert_eq!(check.check(&[2000, 2000]), Ok(4000));
+        assert_eq!(check.check(&[2000, 2000]), Ok(4000));
+        assert_eq!(check.check(&[2000, 2000]), Ok(4000));
+        assert_eq!(check.check(&[2000, 2000]), Err(AdcError::Stuck));
+
+        // Any change resets the check
+        assert_eq!(check.check(&[2000, 2001]), Ok(4001));
+        assert_eq!(check.check(&[2000, 2001]), Ok(4001));
+    }
+}
@@ -0,0 +1,169 @@
+use serde::{Deserialize, Serialize};
+
+use crate::crc32::{calc_crc32, generate_lookup};
+
+const CRC32_LOOKUP: [u32; 256] = generate_lookup(0x04c1_1db7);
+
+/// Layout version of `Calibration`, a block with another version is rejected by the monitor
+pub const CALIBRATION_VERSION: u16 = 2;
+
+/// Serialized size of a version and a calibration with the largest values
+const CALIBRATION_SIZE: usize = 48;
+
+/// Calibration of the monitor, owned by main. Sensor voltages are in millivolts and timeouts
+/// in monitor cycles of 1 ms, except for `torque_error_timeout`.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
+pub struct Calibration {
+    /// Throttle sensor 1 as `(released, pressed)`
+    pub throttle_sensor1: (u16, u16),
+    /// Throttle sensor 2 as `(released, pressed)`
+    pub throttle_sensor2: (u16, u16),
+    /// Allowed difference between the sensors in the full `0..=u16::MAX` range
+    pub throttle_tolerance: u16,
+    /// Cycles an invalid throttle reading is tolerated
+    pub throttle_timeout: u16,
+    /// Longest time without a torque request
+    pub torque_frame_timeout: u16,
+    /// `TorqueRequest` frames in a row that may be invalid, main sends one every 10 ms
+    pub torque_error_timeout: u16,
+    /// Throttle position in the full `0..=u16::MAX` range up to which the pedal counts as
+    /// released for the unintended acceleration check, which runs while the brake is applied
+    pub ua_pedal_released: u16,
+    /// Rise of the motor speed above its lowest value since braking, in rpm
+    pub ua_rpm_rise: u16,
+    /// Torque accelerating the motor, in the units of the torque request
+    pub ua_drive_torque: u16,
+    /// Cycles a rising speed or drive torque is tolerated
+    pub ua_time: u16,
+}
+
+/// Calibration of the car, main pushes it to the monitor and uses the same throttle sensor
+/// ranges. The ranges are those of the ratiometric dual track pedal on the bench and have to be
+/// checked on the car.
+pub const CALIBRATION: Calibration = Calibration {
+    throttle_sensor1: (800, 4200),
+    throttle_sensor2: (400, 2100),
+    throttle_tolerance: 3277,
+    throttle_timeout: 50,
+    torque_frame_timeout: 50,
+    torque_error_timeout: 5,
+    ua_pedal_released: 3277,
+    ua_rpm_rise: 200,
+    ua_drive_torque: 50,
+    ua_time: 200,
+};
+
+/// Calibration as it is sent to the monitor. Main pushes the block at startup by putting it
+/// in every message until the monitor echoes its CRC, the monitor stays out of the
+/// operational state until it has accepted a block.
+///
+/// # Example
+///
+/// ```
+/// use common::calibration::{Calibration, CalibrationBlock};
+///
+/// let block = CalibrationBlock::new(Calibration {
+///     throttle_sensor1: (1000, 2000),
+///     throttle_sensor2: (3000, 4000),
+///     throttle_tolerance: 1500,
+///     throttle_timeout: 10,
+///     torque_frame_timeout: 10,
+///     torque_error_timeout: 10,
+///     ua_pedal_released: 3277,
+///     ua_rpm_rise: 200,
+///     ua_drive_torque: 50,
+///     ua_time: 20,
+/// });
+/// assert!(block.valid());
+///
+/// // Keep sending until the monitor has echoed the CRC
+/// let echo = None;
+/// assert_eq!(block.pending(echo), Some(block));
+/// assert_eq!(block.pending(Some(block.crc)), None);
+/// ```
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
+pub struct CalibrationBlock {
+    pub version: u16,
+    pub calibration: Calibration,
+    /// CRC32 of the serialized version and calibration
+    pub crc: u32,
+}
+
+impl CalibrationBlock {
+    /// Block of the current version
+    pub fn new(calibration: Calibration) -> Self {
+        Self {
+            version: CALIBRATION_VERSION,
+            calibration,
+            crc: crc(CALIBRATION_VERSION, &calibration),
+        }
+    }
+
+    /// The block has the current version and an intact CRC
+    pub fn valid(&self) -> bool {
+        self.version == CALIBRATION_VERSION && self.crc == crc(self.version, &self.calibration)
+    }
+
+    /// The block to send to the monitor, `None` once the monitor echoes its CRC
+    pub fn pending(&self, echo: Option<u32>) -> Option<Self> {
+        (echo != Some(self.crc)).then_some(*self)
+    }
+}
+
+fn crc(version: u16, calibration: &Calibration) -> u32 {
+    let mut buffer = [0u8; CALIBRATION_SIZE];
+    // Any calibration fits the buffer
+    let bytes = postcard::to_slice(&(version, calibration), &mut buffer).unwrap_or_default();
+    calc_crc32(bytes, &CRC32_LOOKUP)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CALIBRATION: Calibration = Calibration {
+        throttle_sensor1: (1000, 2000),
+        throttle_sensor2: (3000, 4000),
+        throttle_tolerance: 1500,
+        throttle_timeout: 10,
+        torque_frame_timeout: 10,
+        torque_error_timeout: 10,
+        ua_pedal_released: 3277,
+        ua_rpm_rise: 200,
+        ua_drive_torque: 50,
+        ua_time: 20,
+    };
+
+    #[test]
+    fn valid() {
+        let block = CalibrationBlock::new(CALIBRATION);
+        assert!(block.valid());
+
+        let mut corrupt = block;
+        corrupt.calibration.throttle_sensor2.1 = 4100;
+        assert!(!corrupt.valid());
+
+        let mut old = block;
+        old.version = 0;
+        assert!(!old.valid());
+    }
+
+    #[test]
+    fn largest_calibration() {
+        let calibration = Calibration {
+            throttle_sensor1: (u16::MAX, u16::MAX),
+            throttle_sensor2: (u16::MAX, u16::MAX),
+            throttle_tolerance: u16::MAX,
+            throttle_timeout: u16::MAX,
+            torque_frame_timeout: u16::MAX,
+            torque_error_timeout: u16::MAX,
+            ua_pedal_released: u16::MAX,
+            ua_rpm_rise: u16::MAX,
+            ua_drive_torque: u16::MAX,
+            ua_time: u16::MAX,
+        };
+
+        let mut buffer = [0u8; CALIBRATION_SIZE];
+        assert!(postcard::to_slice(&(u16::MAX, calibration), &mut buffer).is_ok());
+    }
+}
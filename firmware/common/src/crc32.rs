@@ -0,0 +1,84 @@
+/// Initial value of a CRC32 calculation
+pub const CRC32_INIT: u32 = 0xffff_ffff;
+
+/// `const` function to generate a CRC32 lookuptable at compile time for a given polynomial.
+///
+/// # Example
+///
+/// ```
+/// use common::crc32::generate_lookup;
+/// const CRC32_LOOKUP: [u32; 256] = generate_lookup(0x04c1_1db7);
+/// ```
+pub const fn generate_lookup(polynomial: u32) -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut value = (i as u32) << 24;
+        let mut bit = 0;
+        while bit < 8 {
+            if (value & 0x8000_0000) != 0 {
+                value <<= 1;
+                value ^= polynomial;
+            } else {
+                value <<= 1;
+            }
+            bit += 1;
+        }
+
+        table[i] = value;
+        i += 1;
+    }
+
+    table
+}
+
+/// Continue a CRC32 calculation with more bytes. The calculation is not reflected and has
+/// no final XOR, with the polynomial `0x04c11db7` this is the same CRC as calculated by the
+/// STM32 CRC peripheral.
+///
+/// # Example
+/// ```
+/// use common::crc32::{generate_lookup, update_crc32, calc_crc32, CRC32_INIT};
+///
+/// const CRC32_LOOKUP: [u32; 256] = generate_lookup(0x04c1_1db7);
+/// let crc = update_crc32(CRC32_INIT, &[0x31, 0x32], &CRC32_LOOKUP);
+/// let crc = update_crc32(crc, &[0x33], &CRC32_LOOKUP);
+/// assert_eq!(crc, calc_crc32(&[0x31, 0x32, 0x33], &CRC32_LOOKUP));
+/// ```
+pub fn update_crc32(mut crc: u32, bytes: &[u8], lookup: &[u32; 256]) -> u32 {
+    for &byte in bytes {
+        crc = (crc << 8) ^ lookup[((crc >> 24) as u8 ^ byte) as usize];
+    }
+
+    crc
+}
+
+/// Calculates the CRC32 of a byte slice using a pre-calculated lookup table.
+pub fn calc_crc32(bytes: &[u8], lookup: &[u32; 256]) -> u32 {
+    update_crc32(CRC32_INIT, bytes, lookup)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lookup() {
+        let crc32_lookup = generate_lookup(0x04c1_1db7);
+
+        assert_eq!(crc32_lookup[0], 0x0000_0000);
+        assert_eq!(crc32_lookup[1], 0x04c1_1db7);
+        assert_eq!(crc32_lookup[255], 0xb1f7_40b4);
+    }
+
+    #[test]
+    fn crc32() {
+        let data = [0x31, 0x32, 0x33, 0x34, 0x35, 0x36, 0x37, 0x38, 0x39];
+        let crc32_lookup = generate_lookup(0x04c1_1db7);
+
+        let crc = calc_crc32(&data, &crc32_lookup);
+
+        // CRC-32/MPEG-2
+        assert_eq!(crc, 0x0376_e6e7);
+    }
+}
@@ -0,0 +1,83 @@
+/// Find the segment of `breakpoints` that contains `value` and return its
+/// lower index together with the interpolation fraction within the segment as
+/// `(numerator, denominator)`. Values outside the breakpoints are clamped.
+pub(crate) fn segment<const N: usize>(breakpoints: &[u16; N], value: u16) -> (usize, u32, u32) {
+    if N < 2 || value <= breakpoints[0] {
+        return (0, 0, 1);
+    }
+
+    for i in 0..N - 1 {
+        let (low, high) = (breakpoints[i], breakpoints[i + 1]);
+        if value <= high {
+            let span = high.saturating_sub(low).max(1) as u32;
+            return (i, value.saturating_sub(low) as u32, span);
+        }
+    }
+
+    (N - 2, 1, 1)
+}
+
+/// Linear interpolation between `a` and `b` with the fraction `num / den`
+pub(crate) fn lerp(a: i32, b: i32, num: u32, den: u32) -> i32 {
+    a + ((b - a) as i64 * num as i64 / den as i64) as i32
+}
+
+/// Calibration curve, linearly interpolated between breakpoints and clamped outside of them
+///
+/// # Example
+/// ```
+/// use common::curve::Curve;
+/// let curve = Curve::new([0, 1000, 2000], [0, 100, 50]);
+///
+/// assert_eq!(curve.at(500), 50);
+/// assert_eq!(curve.at(1500), 75);
+/// assert_eq!(curve.at(3000), 50);
+/// ```
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Curve<const N: usize> {
+    x: [u16; N],
+    y: [i16; N],
+}
+
+impl<const N: usize> Curve<N> {
+    /// Create a new curve.
+    ///
+    /// `x: [u16; N]` breakpoints, strictly increasing
+    ///
+    /// `y: [i16; N]` value at each breakpoint
+    pub const fn new(x: [u16; N], y: [i16; N]) -> Self {
+        Self { x, y }
+    }
+
+    /// Get the interpolated value at `x`
+    pub fn at(&self, x: u16) -> i16 {
+        if N == 0 {
+            return 0;
+        }
+
+        let (i, num, den) = segment(&self.x, x);
+        let next = (i + 1).min(N - 1);
+
+        lerp(self.y[i] as i32, self.y[next] as i32, num, den) as i16
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interpolation() {
+        let curve = Curve::new([100, 200, 400], [-100, 100, 0]);
+
+        assert_eq!(curve.at(0), -100);
+        assert_eq!(curve.at(100), -100);
+        assert_eq!(curve.at(150), 0);
+        assert_eq!(curve.at(200), 100);
+        assert_eq!(curve.at(300), 50);
+        assert_eq!(curve.at(u16::MAX), 0);
+
+        assert_eq!(Curve::new([5], [42]).at(0), 42);
+        assert_eq!(Curve::new([], []).at(0), 0);
+    }
+}
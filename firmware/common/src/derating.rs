@@ -0,0 +1,238 @@
+use crate::curve::Curve;
+
+/// Temperature that derates torque
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ThermalSource {
+    Motor,
+    Inverter,
+}
+
+/// Derating of one temperature
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct DeratingTable<const N: usize> {
+    /// Share of the maximum torque in per mille over the temperature in °C
+    pub share: Curve<N>,
+    /// Temperature in °C the driver is warned at, below the start of derating
+    pub warning: u8,
+}
+
+/// Thermal derating calibration
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct DeratingConfig<const N: usize> {
+    pub motor: DeratingTable<N>,
+    pub inverter: DeratingTable<N>,
+    /// Drop in °C below the highest temperature before derating and the warnings ease off
+    pub hysteresis: u8,
+}
+
+/// Torque left after derating
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Derating {
+    /// Share of the maximum torque in per mille
+    pub share: u16,
+    /// Temperature that derates most, `None` while the full torque is available
+    pub source: Option<ThermalSource>,
+    pub motor_warning: bool,
+    pub inverter_warning: bool,
+}
+
+impl Derating {
+    /// The share of `max_torque` that is left
+    pub fn max_torque(&self, max_torque: i16) -> i16 {
+        (max_torque as i32 * self.share as i32 / 1000) as i16
+    }
+}
+
+/// Cuts the maximum torque as the motor and inverter temperatures approach their limits.
+///
+/// Each temperature goes through a hysteresis: it follows a rising temperature at once and a
+/// falling one only after it has dropped by `hysteresis`, so derating doesn't chatter at a
+/// breakpoint. The tables give the share of the maximum torque over the temperature, the lower
+/// share of the two applies. The driver is warned from the warning temperature of a table on,
+/// which is below the start of its derating. Without temperatures the last ones are kept,
+/// missing frames are reported by the monitor.
+///
+/// # Example
+/// ```
+/// use common::{
+///     curve::Curve,
+///     derating::{DeratingConfig, DeratingTable, ThermalDerating, ThermalSource},
+/// };
+/// let mut derating = ThermalDerating::new(DeratingConfig {
+///     motor: DeratingTable {
+///         share: Curve::new([120, 150], [1000, 0]),
+///         warning: 110,
+///     },
+///     inverter: DeratingTable {
+///         share: Curve::new([70, 90], [1000, 0]),
+///         warning: 65,
+///     },
+///     hysteresis: 5,
+/// });
+///
+/// let cold = derating.tick(Some((40, 30)));
+/// assert_eq!((cold.share, cold.source), (1000, None));
+///
+/// let hot = derating.tick(Some((135, 66)));
+/// assert_eq!((hot.share, hot.source), (500, Some(ThermalSource::Motor)));
+/// assert!(hot.motor_warning && hot.inverter_warning);
+/// assert_eq!(hot.max_torque(2000), 1000);
+/// ```
+pub struct ThermalDerating<const N: usize> {
+    config: DeratingConfig<N>,
+    motor: Option<u8>,
+    inverter: Option<u8>,
+}
+
+impl<const N: usize> ThermalDerating<N> {
+    pub const fn new(config: DeratingConfig<N>) -> Self {
+        Self {
+            config,
+            motor: None,
+            inverter: None,
+        }
+    }
+
+    /// Derate for the motor and inverter temperatures in °C, `None` while they are not received
+    pub fn tick(&mut self, temperatures: Option<(u8, u8)>) -> Derating {
+        let hysteresis = self.config.hysteresis;
+        if let Some((motor, inverter)) = temperatures {
+            self.motor = Some(follow(self.motor, motor, hysteresis));
+            self.inverter = Some(follow(self.inverter, inverter, hysteresis));
+        }
+
+        let (motor_share, motor_warning) = derate(&self.config.motor, self.motor);
+        let (inverter_share, inverter_warning) = derate(&self.config.inverter, self.inverter);
+        let (share, source) = if motor_share >= 1000 && inverter_share >= 1000 {
+            (1000, None)
+        } else if motor_share <= inverter_share {
+            (motor_share, Some(ThermalSource::Motor))
+        } else {
+            (inverter_share, Some(ThermalSource::Inverter))
+        };
+
+        Derating {
+            share,
+            source,
+            motor_warning,
+            inverter_warning,
+        }
+    }
+}
+
+/// Temperature after the hysteresis, `held` is the previous one
+fn follow(held: Option<u8>, temperature: u8, hysteresis: u8) -> u8 {
+    match held {
+        Some(held) => temperature.max(held.min(temperature.saturating_add(hysteresis))),
+        None => temperature,
+    }
+}
+
+/// Share in per mille and warning for a temperature
+fn derate<const N: usize>(table: &DeratingTable<N>, temperature: Option<u8>) -> (u16, bool) {
+    match temperature {
+        Some(temperature) => (
+            table.share.at(temperature as u16).clamp(0, 1000) as u16,
+            temperature >= table.warning,
+        ),
+        None => (1000, false),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: DeratingConfig<3> = DeratingConfig {
+        motor: DeratingTable {
+            share: Curve::new([120, 140, 150], [1000, 400, 0]),
+            warning: 110,
+        },
+        inverter: DeratingTable {
+            share: Curve::new([70, 80, 85], [1000, 400, 0]),
+            warning: 65,
+        },
+        hysteresis: 5,
+    };
+
+    #[test]
+    fn tables() {
+        for (motor, share) in [(20, 1000), (120, 1000), (130, 700), (140, 400), (145, 200)] {
+            let derating = ThermalDerating::new(CONFIG).tick(Some((motor, 20)));
+            assert_eq!(derating.share, share, "{motor}");
+        }
+        let derating = ThermalDerating::new(CONFIG).tick(Some((200, 20)));
+        assert_eq!(derating.share, 0);
+
+        // The lower share of the two applies
+        let derating = ThermalDerating::new(CONFIG).tick(Some((130, 75)));
+        assert_eq!(derating.share, 700);
+        assert_eq!(derating.source, Some(ThermalSource::Motor));
+        let derating = ThermalDerating::new(CONFIG).tick(Some((130, 77)));
+        assert_eq!(derating.share, 580);
+        assert_eq!(derating.source, Some(ThermalSource::Inverter));
+        assert_eq!(derating.max_torque(2000), 1160);
+        assert_eq!(derating.max_torque(-2000), -1160);
+    }
+
+    #[test]
+    fn hysteresis() {
+        let mut derating = ThermalDerating::new(CONFIG);
+        assert_eq!(derating.tick(Some((20, 80))).share, 400);
+
+        // Falling temperatures only ease off 5 °C below the highest one
+        assert_eq!(derating.tick(Some((20, 78))).share, 400);
+        assert_eq!(derating.tick(Some((20, 75))).share, 400);
+        assert_eq!(derating.tick(Some((20, 74))).share, 460);
+        assert_eq!(derating.tick(Some((20, 72))).share, 580);
+        // And rise again only above the held temperature
+        assert_eq!(derating.tick(Some((20, 74))).share, 580);
+        assert_eq!(derating.tick(Some((20, 78))).share, 520);
+
+        // The warning ends 5 °C below the warning temperature
+        let mut derating = ThermalDerating::new(CONFIG);
+        assert!(derating.tick(Some((110, 20))).motor_warning);
+        assert!(derating.tick(Some((106, 20))).motor_warning);
+        assert!(!derating.tick(Some((104, 20))).motor_warning);
+        assert!(!derating.tick(Some((109, 20))).motor_warning);
+    }
+
+    #[test]
+    fn warning_before_derating() {
+        let mut derating = ThermalDerating::new(CONFIG);
+        let mut warned = (false, false);
+
+        for temperature in 20..=150 {
+            let inverter = temperature.min(90);
+            let result = derating.tick(Some((temperature, inverter)));
+
+            if result.share < 1000 {
+                match result.source {
+                    Some(ThermalSource::Motor) => assert!(warned.0, "{temperature}"),
+                    Some(ThermalSource::Inverter) => assert!(warned.1, "{temperature}"),
+                    None => panic!("{temperature}"),
+                }
+            }
+            warned = (result.motor_warning, result.inverter_warning);
+        }
+        assert_eq!(warned, (true, true));
+    }
+
+    #[test]
+    fn missing_temperatures() {
+        let mut derating = ThermalDerating::new(CONFIG);
+        assert_eq!(
+            derating.tick(None),
+            Derating {
+                share: 1000,
+                source: None,
+                motor_warning: false,
+                inverter_warning: false,
+            }
+        );
+
+        // The last temperatures are kept
+        let hot = derating.tick(Some((140, 66)));
+        assert_eq!(derating.tick(None), hot);
+    }
+}
@@ -0,0 +1,166 @@
+/// Diagnostic trouble codes of main. An active DTC blocks the vehicle from getting ready
+/// until it is cleared.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Dtc {
+    /// The DC link stays charged with both contactors open
+    ContactorsWelded,
+    /// The DC link charges with only the precharge relay closed
+    LowerContactorWelded,
+    /// Precharge is too fast, the upper contactor is welded or the resistor shorted
+    UpperContactorWelded,
+    /// The DC link drops once the precharge relay opens, the upper contactor didn't close
+    ContactorStuckOpen,
+    /// The DC link drops while HV is connected, a coil dropped out or its driver shut down
+    ContactorDropout,
+}
+
+impl Dtc {
+    const ALL: [Dtc; 5] = [
+        Dtc::ContactorsWelded,
+        Dtc::LowerContactorWelded,
+        Dtc::UpperContactorWelded,
+        Dtc::ContactorStuckOpen,
+        Dtc::ContactorDropout,
+    ];
+
+    fn bit(self) -> u32 {
+        1 << self as u32
+    }
+}
+
+/// Set of active DTCs
+///
+/// # Example
+///
+/// ```
+/// use common::dtc::{Dtc, DtcSet};
+///
+/// let mut dtcs = DtcSet::new();
+/// assert!(dtcs.raise(Dtc::ContactorStuckOpen));
+/// assert!(!dtcs.raise(Dtc::ContactorStuckOpen));
+/// assert_eq!(dtcs.first(), Some(Dtc::ContactorStuckOpen));
+///
+/// dtcs.clear();
+/// assert!(dtcs.is_empty());
+/// ```
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct DtcSet(u32);
+
+impl DtcSet {
+    pub const fn new() -> Self {
+        Self(0)
+    }
+
+    /// Set a DTC active, returns `true` if it wasn't active before
+    pub fn raise(&mut self, dtc: Dtc) -> bool {
+        let new = !self.contains(dtc);
+        self.0 |= dtc.bit();
+        new
+    }
+
+    pub fn contains(&self, dtc: Dtc) -> bool {
+        self.0 & dtc.bit() != 0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// The active DTC that was declared first
+    pub fn first(&self) -> Option<Dtc> {
+        self.iter().next()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Dtc> + '_ {
+        Dtc::ALL.into_iter().filter(|&dtc| self.contains(dtc))
+    }
+
+    pub fn clear(&mut self) {
+        self.0 = 0;
+    }
+
+    /// One bit per DTC in declaration order, for storing the set
+    pub fn bits(&self) -> u32 {
+        self.0
+    }
+
+    /// Set from `bits()`, bits without a DTC are dropped
+    pub fn from_bits(bits: u32) -> Self {
+        let all = Dtc::ALL.iter().fold(0, |all, dtc| all | dtc.bit());
+        Self(bits & all)
+    }
+}
+
+/// Qualifies the request to clear the DTCs, which has to be held for `time` cycles.
+///
+/// # Example
+///
+/// ```
+/// use common::dtc::ClearRequest;
+///
+/// let mut request = ClearRequest::new(2);
+/// assert!(!request.tick(true));
+/// assert!(request.tick(true));
+/// // Only once per request
+/// assert!(!request.tick(true));
+/// ```
+pub struct ClearRequest {
+    time: usize,
+    held: usize,
+}
+
+impl ClearRequest {
+    pub const fn new(time: usize) -> Self {
+        Self { time, held: 0 }
+    }
+
+    /// Returns `true` in the cycle the request has been held long enough
+    pub fn tick(&mut self, request: bool) -> bool {
+        self.held = match request {
+            true => self.held.saturating_add(1),
+            false => 0,
+        };
+        self.held == self.time
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set() {
+        let mut dtcs = DtcSet::new();
+        assert_eq!(dtcs.first(), None);
+
+        dtcs.raise(Dtc::ContactorStuckOpen);
+        dtcs.raise(Dtc::LowerContactorWelded);
+        assert!(dtcs.contains(Dtc::LowerContactorWelded));
+        assert!(!dtcs.contains(Dtc::ContactorsWelded));
+        assert_eq!(dtcs.first(), Some(Dtc::LowerContactorWelded));
+
+        let mut iter = dtcs.iter();
+        assert_eq!(iter.next(), Some(Dtc::LowerContactorWelded));
+        assert_eq!(iter.next(), Some(Dtc::ContactorStuckOpen));
+        assert_eq!(iter.next(), None);
+
+        assert_eq!(DtcSet::from_bits(dtcs.bits()), dtcs);
+        assert_eq!(DtcSet::from_bits(u32::MAX).iter().count(), Dtc::ALL.len());
+    }
+
+    #[test]
+    fn clear_request() {
+        let mut request = ClearRequest::new(3);
+        assert!(!request.tick(true));
+        assert!(!request.tick(true));
+        // Releasing starts over
+        assert!(!request.tick(false));
+        assert!(!request.tick(true));
+        assert!(!request.tick(true));
+        assert!(request.tick(true));
+        assert!(!request.tick(true));
+
+        assert!(!request.tick(false));
+        assert!(!request.tick(true));
+    }
+}
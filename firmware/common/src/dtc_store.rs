@@ -0,0 +1,327 @@
+//! Active DTCs kept in a flash sector across power cycles.
+//!
+//! Every change of the set appends a record with the set and a CRC to the sector, the latest
+//! valid record holds the set. The sector is only erased by `compact` once it is nearly full,
+//! which blocks for as long as the erase takes and is left to the owner to call when nothing
+//! else has to run. A record that is interrupted by a reset fails its CRC and the previous set
+//! is kept. An interrupted erase loses the set, the contactor checks raise the DTCs again on the
+//! next start.
+
+use crate::{
+    crc32::{calc_crc32, generate_lookup},
+    dtc::DtcSet,
+};
+
+const CRC32_LOOKUP: [u32; 256] = generate_lookup(0x04c1_1db7);
+
+/// Set and CRC32
+const RECORD_SIZE: usize = 8;
+/// Free records needed for the writes of one boot, `compact` erases the sector when there are
+/// fewer
+const RESERVE: usize = 16;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum FlashError {
+    Program,
+    Erase,
+}
+
+/// One flash sector, offsets are relative to its start
+pub trait SectorFlash {
+    /// Size of the sector in bytes
+    const SIZE: usize;
+
+    fn read(&mut self, offset: usize, bytes: &mut [u8]);
+
+    fn erase(&mut self) -> Result<(), FlashError>;
+
+    /// Program words at an offset aligned to 4, the flash has to be erased
+    fn program(&mut self, offset: usize, bytes: &[u8]) -> Result<(), FlashError>;
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum DtcStoreError {
+    Flash(FlashError),
+    /// The sector has no room left until it is compacted
+    Full,
+}
+
+impl From<FlashError> for DtcStoreError {
+    fn from(value: FlashError) -> Self {
+        Self::Flash(value)
+    }
+}
+
+/// Store of the active DTCs
+///
+/// # Example
+///
+/// ```
+/// use common::{
+///     dtc::{Dtc, DtcSet},
+///     dtc_store::{DtcStore, FlashError, SectorFlash},
+/// };
+///
+/// struct Ram([u8; 64]);
+///
+/// impl SectorFlash for &mut Ram {
+///     const SIZE: usize = 64;
+///
+///     fn read(&mut self, offset: usize, bytes: &mut [u8]) {
+///         bytes.copy_from_slice(&self.0[offset..offset + bytes.len()]);
+///     }
+///
+///     fn erase(&mut self) -> Result<(), FlashError> {
+///         self.0.fill(0xff);
+///         Ok(())
+///     }
+///
+///     fn program(&mut self, offset: usize, bytes: &[u8]) -> Result<(), FlashError> {
+///         self.0[offset..offset + bytes.len()].copy_from_slice(bytes);
+///         Ok(())
+///     }
+/// }
+///
+/// let mut flash = Ram([0xff; 64]);
+/// let mut dtcs = DtcSet::new();
+/// dtcs.raise(Dtc::ContactorsWelded);
+/// DtcStore::new(&mut flash).store(dtcs).unwrap();
+///
+/// // After the next power up
+/// assert_eq!(DtcStore::new(&mut flash).dtcs(), dtcs);
+/// ```
+pub struct DtcStore<F: SectorFlash> {
+    flash: F,
+    dtcs: DtcSet,
+    /// Index of the next free record
+    next: usize,
+}
+
+impl<F: SectorFlash> DtcStore<F> {
+    const RECORDS: usize = F::SIZE / RECORD_SIZE;
+
+    /// Read the stored set
+    pub fn new(mut flash: F) -> Self {
+        let next = (0..Self::RECORDS)
+            .find(|&index| blank(&read_record(&mut flash, index)))
+            .unwrap_or(Self::RECORDS);
+        let dtcs = (0..next)
+            .rev()
+            .find_map(|index| decode(&read_record(&mut flash, index)))
+            .unwrap_or_default();
+
+        Self { flash, dtcs, next }
+    }
+
+    /// Erase the sector when it is nearly full and write the set again. The caller is blocked
+    /// for as long as the erase takes.
+    pub fn compact(&mut self) -> Result<(), DtcStoreError> {
+        if Self::RECORDS - self.next >= RESERVE {
+            return Ok(());
+        }
+
+        self.flash.erase()?;
+        self.next = 0;
+        if self.dtcs.is_empty() {
+            return Ok(());
+        }
+        self.write(self.dtcs)
+    }
+
+    /// The stored set
+    pub fn dtcs(&self) -> DtcSet {
+        self.dtcs
+    }
+
+    /// Store the set if it changed
+    pub fn store(&mut self, dtcs: DtcSet) -> Result<(), DtcStoreError> {
+        if dtcs == self.dtcs {
+            return Ok(());
+        }
+        self.write(dtcs)
+    }
+
+    fn write(&mut self, dtcs: DtcSet) -> Result<(), DtcStoreError> {
+        if self.next >= Self::RECORDS {
+            return Err(DtcStoreError::Full);
+        }
+
+        // The record is used even if programming fails, it can't be programmed again
+        let offset = self.next * RECORD_SIZE;
+        self.next += 1;
+        self.flash.program(offset, &encode(dtcs))?;
+        self.dtcs = dtcs;
+
+        Ok(())
+    }
+}
+
+fn read_record<F: SectorFlash>(flash: &mut F, index: usize) -> [u8; RECORD_SIZE] {
+    let mut record = [0u8; RECORD_SIZE];
+    flash.read(index * RECORD_SIZE, &mut record);
+    record
+}
+
+fn blank(record: &[u8; RECORD_SIZE]) -> bool {
+    record.iter().all(|&byte| byte == 0xff)
+}
+
+fn encode(dtcs: DtcSet) -> [u8; RECORD_SIZE] {
+    let bits = dtcs.bits().to_le_bytes();
+    let crc = calc_crc32(&bits, &CRC32_LOOKUP).to_le_bytes();
+
+    let mut record = [0u8; RECORD_SIZE];
+    record[..4].copy_from_slice(&bits);
+    record[4..].copy_from_slice(&crc);
+    record
+}
+
+fn decode(record: &[u8; RECORD_SIZE]) -> Option<DtcSet> {
+    let (bits, crc) = record.split_at(4);
+    (calc_crc32(bits, &CRC32_LOOKUP).to_le_bytes() == crc)
+        .then(|| DtcSet::from_bits(u32::from_le_bytes(bits.try_into().unwrap())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dtc::Dtc;
+
+    const SIZE: usize = 32 * RECORD_SIZE;
+
+    struct MockFlash {
+        bytes: [u8; SIZE],
+        erases: usize,
+    }
+
+    impl MockFlash {
+        fn new() -> Self {
+            Self {
+                bytes: [0xff; SIZE],
+                erases: 0,
+            }
+        }
+    }
+
+    impl SectorFlash for &mut MockFlash {
+        const SIZE: usize = SIZE;
+
+        fn read(&mut self, offset: usize, bytes: &mut [u8]) {
+            bytes.copy_from_slice(&self.bytes[offset..offset + bytes.len()]);
+        }
+
+        fn erase(&mut self) -> Result<(), FlashError> {
+            self.bytes.fill(0xff);
+            self.erases += 1;
+            Ok(())
+        }
+
+        fn program(&mut self, offset: usize, bytes: &[u8]) -> Result<(), FlashError> {
+            // Programming only clears bits
+            for (byte, new) in self.bytes[offset..].iter_mut().zip(bytes) {
+                *byte &= new;
+            }
+            Ok(())
+        }
+    }
+
+    fn set(dtcs: &[Dtc]) -> DtcSet {
+        let mut set = DtcSet::new();
+        for &dtc in dtcs {
+            set.raise(dtc);
+        }
+        set
+    }
+
+    #[test]
+    fn blank_sector() {
+        let mut flash = MockFlash::new();
+        let store = DtcStore::new(&mut flash);
+        assert!(store.dtcs().is_empty());
+        assert_eq!(flash.erases, 0);
+    }
+
+    #[test]
+    fn latest_set() {
+        let mut flash = MockFlash::new();
+        let mut store = DtcStore::new(&mut flash);
+        store.store(set(&[Dtc::ContactorDropout])).unwrap();
+        store
+            .store(set(&[Dtc::ContactorDropout, Dtc::LowerContactorWelded]))
+            .unwrap();
+        // An unchanged set isn't written again
+        store
+            .store(set(&[Dtc::ContactorDropout, Dtc::LowerContactorWelded]))
+            .unwrap();
+        assert_eq!(store.next, 2);
+
+        let mut store = DtcStore::new(&mut flash);
+        assert_eq!(
+            store.dtcs(),
+            set(&[Dtc::ContactorDropout, Dtc::LowerContactorWelded])
+        );
+
+        // Cleared
+        store.store(DtcSet::new()).unwrap();
+        assert!(DtcStore::new(&mut flash).dtcs().is_empty());
+    }
+
+    #[test]
+    fn interrupted_record() {
+        let mut flash = MockFlash::new();
+        let mut store = DtcStore::new(&mut flash);
+        store.store(set(&[Dtc::ContactorStuckOpen])).unwrap();
+
+        // Only the set of the second record made it to flash
+        let record = encode(set(&[Dtc::ContactorsWelded]));
+        flash.bytes[RECORD_SIZE..RECORD_SIZE + 4].copy_from_slice(&record[..4]);
+
+        let mut store = DtcStore::new(&mut flash);
+        assert_eq!(store.dtcs(), set(&[Dtc::ContactorStuckOpen]));
+        assert_eq!(store.next, 2);
+
+        store.store(set(&[Dtc::ContactorsWelded])).unwrap();
+        let store = DtcStore::new(&mut flash);
+        assert_eq!(store.dtcs(), set(&[Dtc::ContactorsWelded]));
+    }
+
+    #[test]
+    fn compact() {
+        let mut flash = MockFlash::new();
+        let mut store = DtcStore::new(&mut flash);
+        let records = SIZE / RECORD_SIZE;
+
+        let dtcs = [set(&[Dtc::ContactorDropout]), DtcSet::new()];
+        for index in 0..records - RESERVE {
+            store.store(dtcs[index % 2]).unwrap();
+        }
+        // Enough room left for one boot
+        store.compact().unwrap();
+        assert_eq!(store.next, records - RESERVE);
+
+        for index in records - RESERVE..records {
+            store.store(dtcs[index % 2]).unwrap();
+        }
+        assert_eq!(
+            store.store(set(&[Dtc::UpperContactorWelded])),
+            Err(DtcStoreError::Full)
+        );
+
+        // Erased, there is nothing to write for the empty set
+        store.compact().unwrap();
+        assert_eq!(store.dtcs(), DtcSet::new());
+        assert_eq!(store.next, 0);
+        assert_eq!(flash.erases, 1);
+
+        // The latest set is kept
+        let mut flash = MockFlash::new();
+        let mut store = DtcStore::new(&mut flash);
+        for index in 0..records - RESERVE + 1 {
+            store.store(dtcs[index % 2]).unwrap();
+        }
+        store.compact().unwrap();
+        assert_eq!(store.next, 1);
+        assert_eq!(flash.erases, 1);
+        assert_eq!(DtcStore::new(&mut flash).dtcs(), dtcs[0]);
+    }
+}
@@ -1,4 +1,5 @@
-use bxcan::{Data, Frame, Id, StandardId};
+use bxcan::{Frame, Id, StandardId};
+use heapless::Vec;
 
 use crate::crc8::{calc_crc8, generate_lookup};
 const NISSAN_CRC_LOOKUP: [u8;256] = generate_lookup(0x85);
@@ -13,13 +14,30 @@ pub enum EvCanError {
 }
 
 // VCM -> Inverter
-const VCM_KEEPALIVE1_ID: StandardId = unsafe {StandardId::new_unchecked(0x11a)};
-const TORQUE_REQUEST_ID: StandardId = unsafe {StandardId::new_unchecked(0x14d)};
-const VCM_KEEPALIVE2_ID: StandardId = unsafe {StandardId::new_unchecked(0x50b)};
+pub const VCM_KEEPALIVE1_ID: u16 = 0x11a;
+pub const TORQUE_REQUEST_ID: u16 = 0x14d;
+pub const VCM_KEEPALIVE2_ID: u16 = 0x50b;
+
+// VCM -> dash, not used by the inverter
+pub const DRIVER_WARNING_ID: u16 = 0x5f0;
 
 // Inverter -> VCM
-const INVERTER_STATUS_ID: StandardId = unsafe {StandardId::new_unchecked(0x1da)};
-const INVERTER_TEMPERATURE_ID: StandardId = unsafe {StandardId::new_unchecked(0x55a)};
+pub const INVERTER_STATUS_ID: u16 = 0x1da;
+pub const INVERTER_TEMPERATURE_ID: u16 = 0x55a;
+
+/// Only used in constants, an identifier that doesn't fit 11 bits fails the build
+const fn standard_id(id: u16) -> StandardId {
+    match StandardId::new(id) {
+        Some(id) => id,
+        None => panic!("not an 11 bit identifier"),
+    }
+}
+
+// Identifiers of the frames main sends
+const VCM_KEEPALIVE1: StandardId = standard_id(VCM_KEEPALIVE1_ID);
+const TORQUE_REQUEST: StandardId = standard_id(TORQUE_REQUEST_ID);
+const VCM_KEEPALIVE2: StandardId = standard_id(VCM_KEEPALIVE2_ID);
+const DRIVER_WARNING: StandardId = standard_id(DRIVER_WARNING_ID);
 
 pub enum EvCanFrame {
     VcmKeepalive1 {counter: u8},
@@ -27,6 +45,8 @@ pub enum EvCanFrame {
     TorqueRequest {torque: i16, counter: u8},
     InverterStatus { millivolt: u32, rpm: i16, current: i16, error: u8},
     InverterTemperature {motor_temperature: u8, inverter_temperature: u8},
+    /// Warnings for the driver, temperatures approaching their derating and torque derated
+    DriverWarning {motor_temperature: bool, inverter_temperature: bool, derating: bool},
 }
 
 impl EvCanFrame
@@ -53,7 +73,68 @@ impl EvCanFrame
         [0x00, 0x00, 0x06, 0xc0, 0x00, 0x00, 0x00]
     }
 
-    fn from_torque_request_data(data: &Data) -> Result<Self, EvCanError> {
+    fn to_driver_warning_data(motor_temperature: bool, inverter_temperature: bool, derating: bool) -> [u8; 1] {
+        [motor_temperature as u8 | (inverter_temperature as u8) << 1 | (derating as u8) << 2]
+    }
+
+    /// Parse a received frame from its raw standard identifier and data. This makes it possible
+    /// to use `EvCanFrame` with CAN drivers that don't share the `bxcan` version used here.
+    pub fn from_raw(id: u16, data: &[u8]) -> Result<Self, EvCanError> {
+        match id {
+            VCM_KEEPALIVE1_ID => EvCanFrame::from_vcm_keepalive1_data(data),
+            TORQUE_REQUEST_ID => EvCanFrame::from_torque_request_data(data),
+            VCM_KEEPALIVE2_ID => EvCanFrame::from_vcm_keepalive2_data(data),
+            INVERTER_STATUS_ID => EvCanFrame::from_inverter_status_data(data),
+            INVERTER_TEMPERATURE_ID => EvCanFrame::from_inverter_temperature_data(data),
+            DRIVER_WARNING_ID => EvCanFrame::from_driver_warning_data(data),
+            _ => Err(EvCanError::UnknownFrame)
+        }
+    }
+
+    /// Raw standard identifier and data of a frame to send, the counterpart of `from_raw()`.
+    pub fn to_raw(&self) -> Result<(u16, Vec<u8, 8>), EvCanError> {
+        let (id, data): (u16, &[u8]) = match *self {
+            EvCanFrame::TorqueRequest { torque, counter } => (TORQUE_REQUEST_ID, &EvCanFrame::to_torque_request_data(torque, counter)),
+            EvCanFrame::VcmKeepalive1 { counter } => (VCM_KEEPALIVE1_ID, &EvCanFrame::to_vcm_keepalive1_data(counter)),
+            EvCanFrame::VcmKeepalive2 => (VCM_KEEPALIVE2_ID, &EvCanFrame::to_vcm_keepalive2_data()),
+            EvCanFrame::DriverWarning { motor_temperature, inverter_temperature, derating } => (DRIVER_WARNING_ID, &EvCanFrame::to_driver_warning_data(motor_temperature, inverter_temperature, derating)),
+            _ => return Err(EvCanError::ReceiveOnly)
+        };
+
+        Ok((id, Vec::from_slice(data).map_err(|_| EvCanError::BadDlc)?))
+    }
+
+    fn check_dlc(data: &[u8], dlc: usize) -> Result<(), EvCanError> {
+        if data.len() < dlc {
+            Err(EvCanError::BadDlc)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn from_vcm_keepalive1_data(data: &[u8]) -> Result<Self, EvCanError> {
+        EvCanFrame::check_dlc(data, 8)?;
+
+        let counter = data[6];
+
+        let crc = calc_crc8(&data[..7], &NISSAN_CRC_LOOKUP);
+
+        if crc != data[7] {
+            Err(EvCanError::BadCrc)
+        } else {
+            Ok(EvCanFrame::VcmKeepalive1 { counter })
+        }
+    }
+
+    fn from_vcm_keepalive2_data(data: &[u8]) -> Result<Self, EvCanError> {
+        EvCanFrame::check_dlc(data, 7)?;
+
+        Ok(EvCanFrame::VcmKeepalive2)
+    }
+
+    fn from_torque_request_data(data: &[u8]) -> Result<Self, EvCanError> {
+        EvCanFrame::check_dlc(data, 8)?;
+
         let torque = i16::from_le_bytes([data[2], data[3]]);
         let counter = data[4] >> 6;
 
@@ -66,7 +147,9 @@ impl EvCanFrame
         }
     }
 
-    fn from_inverter_status_data(data: &Data) -> Result<Self, EvCanError> {
+    fn from_inverter_status_data(data: &[u8]) -> Result<Self, EvCanError> {
+        EvCanFrame::check_dlc(data, 7)?;
+
         let millivolt = u16::from_le_bytes([data[0], data[1]]) as u32 * 500;
         let current = i16::from_le_bytes([data[2], data[3]]);
         let rpm = i16::from_le_bytes([data[4], data[5]]);
@@ -75,12 +158,24 @@ impl EvCanFrame
         Ok(EvCanFrame::InverterStatus { millivolt, rpm, current, error })
     }
 
-    fn from_inverter_temperature_data(data: &Data) -> Result<Self, EvCanError> {
+    fn from_inverter_temperature_data(data: &[u8]) -> Result<Self, EvCanError> {
+        EvCanFrame::check_dlc(data, 2)?;
+
         let motor_temperature = data[0];
         let inverter_temperature = data[1];
 
         Ok(EvCanFrame::InverterTemperature { motor_temperature, inverter_temperature })
     }
+
+    fn from_driver_warning_data(data: &[u8]) -> Result<Self, EvCanError> {
+        EvCanFrame::check_dlc(data, 1)?;
+
+        let motor_temperature = data[0] & 0x01 != 0;
+        let inverter_temperature = data[0] & 0x02 != 0;
+        let derating = data[0] & 0x04 != 0;
+
+        Ok(EvCanFrame::DriverWarning { motor_temperature, inverter_temperature, derating })
+    }
 }
 
 impl TryFrom<EvCanFrame> for Frame {
@@ -88,9 +183,10 @@ impl TryFrom<EvCanFrame> for Frame {
 
     fn try_from(value: EvCanFrame) -> Result<Self, Self::Error> {
         match value {
-            EvCanFrame::TorqueRequest { torque, counter } => Ok(Frame::new_data(TORQUE_REQUEST_ID, EvCanFrame::to_torque_request_data(torque, counter))),
-            EvCanFrame::VcmKeepalive1 { counter } => Ok(Frame::new_data(VCM_KEEPALIVE1_ID, EvCanFrame::to_vcm_keepalive1_data(counter))),
-            EvCanFrame::VcmKeepalive2 => Ok(Frame::new_data(VCM_KEEPALIVE2_ID, EvCanFrame::to_vcm_keepalive2_data())),
+            EvCanFrame::TorqueRequest { torque, counter } => Ok(Frame::new_data(TORQUE_REQUEST, EvCanFrame::to_torque_request_data(torque, counter))),
+            EvCanFrame::VcmKeepalive1 { counter } => Ok(Frame::new_data(VCM_KEEPALIVE1, EvCanFrame::to_vcm_keepalive1_data(counter))),
+            EvCanFrame::VcmKeepalive2 => Ok(Frame::new_data(VCM_KEEPALIVE2, EvCanFrame::to_vcm_keepalive2_data())),
+            EvCanFrame::DriverWarning { motor_temperature, inverter_temperature, derating } => Ok(Frame::new_data(DRIVER_WARNING, EvCanFrame::to_driver_warning_data(motor_temperature, inverter_temperature, derating))),
             _ => Err(EvCanError::ReceiveOnly)
         }
     }
@@ -102,12 +198,7 @@ impl TryFrom<Frame> for EvCanFrame {
     fn try_from(value: Frame) -> Result<Self, Self::Error> {
         if let Id::Standard(id) = value.id() {
             let data = value.data().ok_or(EvCanError::NoData)?;
-            match id {
-                TORQUE_REQUEST_ID => Ok(EvCanFrame::from_torque_request_data(data)?),
-                INVERTER_STATUS_ID => Ok(EvCanFrame::from_inverter_status_data(data)?),
-                INVERTER_TEMPERATURE_ID => Ok(EvCanFrame::from_inverter_temperature_data(data)?),
-                _ => Err(EvCanError::UnknownFrame)
-            }
+            EvCanFrame::from_raw(id.as_raw(), data)
         } else {
             Err(EvCanError::UnknownFrame)
         }
@@ -124,7 +215,7 @@ mod tests {
     fn torque_request() {
         let frame: Frame = EvCanFrame::TorqueRequest { torque: 1000, counter: 2 }.try_into().unwrap();
 
-        assert_eq!(frame.id(), Id::Standard(TORQUE_REQUEST_ID));
+        assert_eq!(frame.id(), Id::Standard(TORQUE_REQUEST));
 
         if let EvCanFrame::TorqueRequest { torque, counter } = EvCanFrame::from_torque_request_data(frame.data().unwrap()).unwrap() {
             assert_eq!(torque, 1000);
@@ -133,4 +224,60 @@ mod tests {
             panic!("Frame should be a TorqueRequest")
         }
     }
+
+    #[test]
+    fn vcm_keepalive1() {
+        let frame: Frame = EvCanFrame::VcmKeepalive1 { counter: 3 }.try_into().unwrap();
+
+        if let Ok(EvCanFrame::VcmKeepalive1 { counter }) = EvCanFrame::try_from(frame) {
+            assert_eq!(counter, 3);
+        } else {
+            panic!("Frame should be a VcmKeepalive1")
+        }
+    }
+
+    #[test]
+    fn driver_warning() {
+        let (id, data) = EvCanFrame::DriverWarning { motor_temperature: true, inverter_temperature: false, derating: true }.to_raw().unwrap();
+        assert_eq!((id, data.as_slice()), (DRIVER_WARNING_ID, &[0x05][..]));
+
+        assert!(matches!(
+            EvCanFrame::from_raw(id, &data),
+            Ok(EvCanFrame::DriverWarning { motor_temperature: true, inverter_temperature: false, derating: true })
+        ));
+        assert!(matches!(
+            EvCanFrame::from_raw(id, &[0x02]),
+            Ok(EvCanFrame::DriverWarning { motor_temperature: false, inverter_temperature: true, derating: false })
+        ));
+    }
+
+    #[test]
+    fn from_raw() {
+        let frame: Frame = EvCanFrame::TorqueRequest { torque: -200, counter: 1 }.try_into().unwrap();
+        let mut data = [0u8; 8];
+        data.copy_from_slice(frame.data().unwrap());
+
+        assert!(matches!(
+            EvCanFrame::from_raw(TORQUE_REQUEST_ID, &data),
+            Ok(EvCanFrame::TorqueRequest { torque: -200, counter: 1 })
+        ));
+
+        data[2] ^= 0x01;
+        assert!(matches!(EvCanFrame::from_raw(TORQUE_REQUEST_ID, &data), Err(EvCanError::BadCrc)));
+        assert!(matches!(EvCanFrame::from_raw(TORQUE_REQUEST_ID, &data[..6]), Err(EvCanError::BadDlc)));
+        assert!(matches!(EvCanFrame::from_raw(0x123, &data), Err(EvCanError::UnknownFrame)));
+    }
+
+    #[test]
+    fn to_raw() {
+        let (id, data) = EvCanFrame::VcmKeepalive1 { counter: 5 }.to_raw().unwrap();
+        assert_eq!(id, VCM_KEEPALIVE1_ID);
+        assert!(matches!(EvCanFrame::from_raw(id, &data), Ok(EvCanFrame::VcmKeepalive1 { counter: 5 })));
+
+        let (id, data) = EvCanFrame::VcmKeepalive2.to_raw().unwrap();
+        assert_eq!((id, data.len()), (VCM_KEEPALIVE2_ID, 7));
+
+        let status = EvCanFrame::InverterStatus { millivolt: 350_000, rpm: 0, current: 0, error: 0 };
+        assert!(matches!(status.to_raw(), Err(EvCanError::ReceiveOnly)));
+    }
 }
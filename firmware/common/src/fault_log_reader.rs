@@ -0,0 +1,228 @@
+use heapless::Vec;
+
+use crate::monitor_message::{
+    FaultLogCommand, FaultLogEntry, FaultLogReply, FaultLogRequest, FaultLogResponse,
+    FAULT_LOG_ENTRIES,
+};
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum FaultLogReaderState {
+    Idle,
+    Reading,
+    Clearing,
+    Done,
+    Failed,
+}
+
+/// Reads or clears the fault log of the monitor over the serial link, one request at a time.
+/// `request()` goes into every message to the monitor and every received response is passed
+/// to `response()`.
+///
+/// # Example
+///
+/// ```
+/// use common::fault_log_reader::{FaultLogReader, FaultLogReaderState};
+/// use common::monitor_message::{FaultLogReply, FaultLogResponse};
+///
+/// let mut reader = FaultLogReader::new();
+/// reader.read();
+///
+/// let request = reader.request().unwrap();
+/// reader.response(Some(&FaultLogResponse { id: request.id, reply: FaultLogReply::Empty }));
+///
+/// assert_eq!(reader.state(), FaultLogReaderState::Done);
+/// assert!(reader.entries().is_empty());
+/// ```
+pub struct FaultLogReader {
+    id: u8,
+    state: FaultLogReaderState,
+    entries: Vec<FaultLogEntry, { FAULT_LOG_ENTRIES as usize }>,
+}
+
+impl FaultLogReader {
+    pub fn new() -> Self {
+        Self {
+            id: 0,
+            state: FaultLogReaderState::Idle,
+            entries: Vec::new(),
+        }
+    }
+
+    /// Start reading the whole log, latest entry first
+    pub fn read(&mut self) {
+        self.entries.clear();
+        self.start(FaultLogReaderState::Reading);
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.start(FaultLogReaderState::Clearing);
+    }
+
+    pub fn state(&self) -> FaultLogReaderState {
+        self.state
+    }
+
+    /// Entries read so far, latest first
+    pub fn entries(&self) -> &[FaultLogEntry] {
+        &self.entries
+    }
+
+    /// The request to send to the monitor
+    pub fn request(&self) -> Option<FaultLogRequest> {
+        let command = match self.state {
+            FaultLogReaderState::Reading => FaultLogCommand::Read(self.entries.len() as u8),
+            FaultLogReaderState::Clearing => FaultLogCommand::Clear,
+            _ => return None,
+        };
+
+        Some(FaultLogRequest {
+            id: self.id,
+            command,
+        })
+    }
+
+    /// Handle the fault log response of a message from the monitor. Responses to earlier
+    /// requests are ignored.
+    pub fn response(&mut self, response: Option<&FaultLogResponse>) {
+        let Some(response) = response.filter(|response| response.id == self.id) else {
+            return;
+        };
+
+        match (self.state, response.reply) {
+            (FaultLogReaderState::Reading, FaultLogReply::Entry(entry)) => {
+                if self.entries.push(entry).is_err() || self.entries.is_full() {
+                    self.state = FaultLogReaderState::Done;
+                } else {
+                    self.id = self.id.wrapping_add(1);
+                }
+            }
+            (FaultLogReaderState::Reading, FaultLogReply::Empty)
+            | (FaultLogReaderState::Clearing, FaultLogReply::Cleared) => {
+                self.state = FaultLogReaderState::Done;
+            }
+            (FaultLogReaderState::Reading | FaultLogReaderState::Clearing, _) => {
+                self.state = FaultLogReaderState::Failed;
+            }
+            _ => {}
+        }
+    }
+
+    fn start(&mut self, state: FaultLogReaderState) {
+        self.id = self.id.wrapping_add(1);
+        self.state = state;
+    }
+}
+
+impl Default for FaultLogReader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::monitor_message::{FaultEvent, MonitorError, ResetCause};
+
+    fn entry(millis: u32) -> FaultLogEntry {
+        FaultLogEntry {
+            boot: 3,
+            millis,
+            event: FaultEvent::Error(MonitorError::PingError),
+            count: 1,
+        }
+    }
+
+    /// Answer the request like the monitor would with a log of `log`
+    fn answer(request: FaultLogRequest, log: &[FaultLogEntry]) -> FaultLogResponse {
+        let reply = match request.command {
+            FaultLogCommand::Read(index) => match log.get(index as usize) {
+                Some(entry) => FaultLogReply::Entry(*entry),
+                None => FaultLogReply::Empty,
+            },
+            FaultLogCommand::Clear => FaultLogReply::Cleared,
+        };
+
+        FaultLogResponse {
+            id: request.id,
+            reply,
+        }
+    }
+
+    #[test]
+    fn read() {
+        let log = [
+            entry(300),
+            FaultLogEntry {
+                boot: 2,
+                millis: 0,
+                event: FaultEvent::Reset(ResetCause::WindowWatchdog),
+                count: 4,
+            },
+            entry(100),
+        ];
+        let mut reader = FaultLogReader::new();
+        assert_eq!(reader.request(), None);
+
+        reader.read();
+        while let Some(request) = reader.request() {
+            // Every request is sent twice before the response arrives
+            reader.response(None);
+            let response = answer(request, &log);
+            reader.response(Some(&response));
+            reader.response(Some(&response));
+        }
+
+        assert_eq!(reader.state(), FaultLogReaderState::Done);
+        assert_eq!(reader.entries(), log);
+    }
+
+    #[test]
+    fn full_log() {
+        let log = [entry(7); FAULT_LOG_ENTRIES as usize];
+        let mut reader = FaultLogReader::new();
+
+        reader.read();
+        while let Some(request) = reader.request() {
+            reader.response(Some(&answer(request, &log)));
+        }
+
+        assert_eq!(reader.state(), FaultLogReaderState::Done);
+        assert_eq!(reader.entries().len(), FAULT_LOG_ENTRIES as usize);
+    }
+
+    #[test]
+    fn clear() {
+        let mut reader = FaultLogReader::new();
+        reader.read();
+        let stale = answer(reader.request().unwrap(), &[entry(1)]);
+
+        reader.clear();
+        let request = reader.request().unwrap();
+        assert_eq!(request.command, FaultLogCommand::Clear);
+
+        // The answer to the aborted read doesn't count
+        reader.response(Some(&stale));
+        assert_eq!(reader.state(), FaultLogReaderState::Clearing);
+
+        reader.response(Some(&answer(request, &[])));
+        assert_eq!(reader.state(), FaultLogReaderState::Done);
+        assert_eq!(reader.request(), None);
+    }
+
+    #[test]
+    fn failed() {
+        let mut reader = FaultLogReader::new();
+        reader.clear();
+        let id = reader.request().unwrap().id;
+
+        reader.response(Some(&FaultLogResponse {
+            id,
+            reply: FaultLogReply::Failed,
+        }));
+
+        assert_eq!(reader.state(), FaultLogReaderState::Failed);
+        assert_eq!(reader.request(), None);
+    }
+}
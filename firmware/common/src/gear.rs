@@ -0,0 +1,30 @@
+use serde::{Deserialize, Serialize};
+
+/// Gear selected by the driver. Positive torque and rpm are forward.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
+pub enum Gear {
+    Park,
+    Reverse,
+    Neutral,
+    Drive,
+}
+
+impl Gear {
+    /// Direction of travel, `1` forward, `-1` reverse and `0` for no drive torque
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use common::gear::Gear;
+    ///
+    /// assert_eq!(Gear::Reverse.direction(), -1);
+    /// assert_eq!(Gear::Neutral.direction(), 0);
+    /// ```
+    pub fn direction(&self) -> i8 {
+        match self {
+            Gear::Drive => 1,
+            Gear::Reverse => -1,
+            Gear::Park | Gear::Neutral => 0,
+        }
+    }
+}
@@ -5,4 +5,21 @@ pub mod ev_can;
 pub mod crc8;
 pub mod monitor_serial;
 pub mod throttle;
-pub mod timeout;
\ No newline at end of file
+pub mod timeout;
+pub mod torque_envelope;
+pub mod adc;
+pub mod crc32;
+pub mod fault_log_reader;
+pub mod scheduler;
+pub mod calibration;
+pub mod gear;
+pub mod vehicle_state;
+pub mod precharge;
+pub mod dtc;
+pub mod dtc_store;
+pub mod curve;
+pub mod pedal_map;
+pub mod regen;
+pub mod torque_shaping;
+pub mod torque_arbiter;
+pub mod derating;
@@ -1,57 +1,225 @@
 use serde::{Deserialize, Serialize};
 
+use crate::{calibration::CalibrationBlock, gear::Gear};
+
 /// The size of buffeer needed to recieve a full struct, should be
-/// big enough to hold a worst case postcard serialization of both structs
-pub const MONITOR_MESSAGE_BUFFER_SIZE: usize = 32;
+/// big enough to hold a worst case postcard serialization of both structs.
+/// A full buffer takes about 7 ms at 115200 baud, within the 10 ms link period.
+pub const MONITOR_MESSAGE_BUFFER_SIZE: usize = 80;
+
+/// Number of entries kept in the fault log of the monitor
+pub const FAULT_LOG_ENTRIES: u8 = 16;
 
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
 pub enum MonitorError {
     PingError,
     AcceleratorError,
     TorqueRequestError,
+    SelfTestError(SelfTest),
+    OverspeedError,
+    DcVoltageError,
+    MotorTemperatureError,
+    InverterTemperatureError,
+    InverterTimeoutError,
+    /// The high side output doesn't match the state claimed by main
+    HighSideError,
+    /// The calibration block from main has a bad version or CRC or is out of bounds
+    CalibrationError,
+    /// The torque request drives against the selected gear
+    DirectionError,
+    /// The motor accelerates while the brake is applied and the pedal is released
+    UnintendedAccelerationError,
+    /// The EV CAN controller went bus off and recovered
+    EvCanBusOffError,
+}
+
+/// Reaction to a fault that the monitor requires from main, from the least to the most severe.
+/// The monitor disconnects HV itself by disabling the high side drive, the other reactions
+/// are applied by main.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub enum Reaction {
+    Warn,
+    TorqueLimited,
+    ZeroTorque,
+    DisconnectHv,
+}
+
+/// Class B self test of the monitor MCU
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
+pub enum SelfTest {
+    CpuRegisters,
+    Ram,
+    FlashCrc,
+    Clock,
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
 pub enum MainError {
     AcceleratorError
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+/// Cause of the latest reset of an MCU
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
+pub enum ResetCause {
+    PowerOn,
+    Pin,
+    Software,
+    IndependentWatchdog,
+    WindowWatchdog,
+    LowPower,
+    Unknown,
+}
+
+/// Event stored in the fault log of the monitor
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
+pub enum FaultEvent {
+    Reset(ResetCause),
+    Error(MonitorError),
+}
+
+/// Entry of the fault log, stamped with the boot it happened in and the time since that boot.
+/// `count` is the number of times the event has happened since the log was cleared.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
+pub struct FaultLogEntry {
+    pub boot: u16,
+    pub millis: u32,
+    pub event: FaultEvent,
+    pub count: u16,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
+pub enum FaultLogCommand {
+    /// Read an entry, 0 is the latest
+    Read(u8),
+    Clear,
+}
+
+/// Request to the fault log of the monitor. Main repeats the request in every message until
+/// the response with the same `id` is received, the monitor handles every `id` once.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
+pub struct FaultLogRequest {
+    pub id: u8,
+    pub command: FaultLogCommand,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
+pub enum FaultLogReply {
+    Entry(FaultLogEntry),
+    /// There is no entry at the requested index
+    Empty,
+    Cleared,
+    /// The log could not be read or written
+    Failed,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
+pub struct FaultLogResponse {
+    pub id: u8,
+    pub reply: FaultLogReply,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
 pub enum MonitorState {
+    /// Waiting for a valid calibration block from main, the outputs are disabled
+    Uncalibrated,
     Operational,
-    Error(MonitorError),
+    /// The most severe fault so far and the reaction it requires, until the next reset
+    Fault(MonitorError, Reaction),
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
 pub enum MainState {
     Operational,
     Error(MainError),
 }
 
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Frames dropped by the receiver of a serial link
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Copy, Default)]
+pub struct SerialStats {
+    /// Frames with a bad CRC or that could not be decoded
+    pub crc_errors: u16,
+    /// Frames that were incomplete because the receiver lost data or too long for the buffer
+    pub overruns: u16,
+}
+
+/// Diagnostic values of the monitor. They don't fit a single message, every message carries
+/// the next kind in turn.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
+pub enum Diagnostics {
+    /// Messages from main dropped by the monitor
+    Serial(SerialStats),
+    /// Error counters of the EV CAN controller and the number of times it went bus off
+    Can { tec: u8, rec: u8, bus_off: u16 },
+    /// Timing of the monitor loop in core cycles. `jitter` is the longest delay of the 1 ms
+    /// monitor task by the tasks before it, `overruns` counts deadlines missed by a task or
+    /// a whole tick.
+    Loop {
+        max: u32,
+        jitter: u32,
+        overruns: u32,
+    },
+    /// Number of times a fault started to qualify in each monitor, whether or not it became
+    /// an error
+    Qualifications {
+        throttle: u16,
+        torque: u16,
+        inverter: u16,
+        high_side: u16,
+        direction: u16,
+        acceleration: u16,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
 pub struct MonitorToMain {
     pub ping: u64,
     pub state: MonitorState,
+    pub reset_cause: ResetCause,
+    pub fault_log: Option<FaultLogResponse>,
+    /// CRC of the calibration block in use
+    pub calibration_crc: Option<u32>,
+    pub diagnostics: Diagnostics,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
 pub struct MainToMonitor {
     pub pong: u64,
     pub state: MainState,
     pub accelerator: u16,
-    pub high_side_on: bool,
+    /// Main drives the high side output, checked by the monitor against HIGH_SIDE_SENSE.
+    /// `None` while main doesn't drive the output.
+    pub high_side_on: Option<bool>,
+    /// Main has closed the contactors and precharged the DC link, the monitor only checks the
+    /// DC link for undervoltage while it is set
+    pub hv_connected: bool,
+    /// Checked by the monitor against the sign of the torque request
+    pub gear: Gear,
+    /// Reaction main has applied, the monitor escalates a reaction that isn't acknowledged
+    pub reaction_ack: Option<Reaction>,
+    pub fault_log: Option<FaultLogRequest>,
+    /// Sent at startup until the monitor echoes its CRC
+    pub calibration: Option<CalibrationBlock>,
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{calibration::Calibration, monitor_serial::encode_frame};
 
     #[test]
     fn monitor_to_main() {
         let msg = MonitorToMain {
             ping: 12345,
-            state: MonitorState::Error(MonitorError::PingError),
+            state: MonitorState::Fault(MonitorError::PingError, Reaction::DisconnectHv),
+            reset_cause: ResetCause::WindowWatchdog,
+            fault_log: None,
+            calibration_crc: Some(0x1234_5678),
+            diagnostics: Diagnostics::Can {
+                tec: 0,
+                rec: 12,
+                bus_off: 1,
+            },
         };
 
         let mut buf = [0u8; MONITOR_MESSAGE_BUFFER_SIZE];
@@ -62,6 +230,9 @@ mod tests {
 
         assert_eq!(msg.ping, msg_out.ping);
         assert_eq!(msg.state, msg_out.state);
+        assert_eq!(msg.reset_cause, msg_out.reset_cause);
+        assert_eq!(msg.calibration_crc, msg_out.calibration_crc);
+        assert_eq!(msg.diagnostics, msg_out.diagnostics);
     }
 
     #[test]
@@ -72,7 +243,15 @@ mod tests {
                 MainError::AcceleratorError
             ),
             accelerator: 54321,
-            high_side_on: true,
+            high_side_on: Some(true),
+            hv_connected: true,
+            gear: Gear::Reverse,
+            reaction_ack: Some(Reaction::Warn),
+            fault_log: Some(FaultLogRequest {
+                id: 1,
+                command: FaultLogCommand::Read(3),
+            }),
+            calibration: None,
         };
 
         let mut buf = [0u8; MONITOR_MESSAGE_BUFFER_SIZE];
@@ -85,5 +264,76 @@ mod tests {
         assert_eq!(msg.state, msg_out.state);
         assert_eq!(msg.accelerator, msg_out.accelerator);
         assert_eq!(msg.high_side_on, msg_out.high_side_on);
+        assert_eq!(msg.hv_connected, msg_out.hv_connected);
+        assert_eq!(msg.gear, msg_out.gear);
+        assert_eq!(msg.reaction_ack, msg_out.reaction_ack);
+        assert_eq!(msg.fault_log, msg_out.fault_log);
+    }
+
+    #[test]
+    fn worst_case_size() {
+        let msg = MonitorToMain {
+            ping: u64::MAX,
+            state: MonitorState::Fault(
+                MonitorError::SelfTestError(SelfTest::Clock),
+                Reaction::DisconnectHv,
+            ),
+            reset_cause: ResetCause::Unknown,
+            fault_log: Some(FaultLogResponse {
+                id: u8::MAX,
+                reply: FaultLogReply::Entry(FaultLogEntry {
+                    boot: u16::MAX,
+                    millis: u32::MAX,
+                    event: FaultEvent::Error(MonitorError::SelfTestError(SelfTest::Clock)),
+                    count: u16::MAX,
+                }),
+            }),
+            calibration_crc: Some(u32::MAX),
+            // The largest diagnostics, 6 varints of 3 bytes
+            diagnostics: Diagnostics::Qualifications {
+                throttle: u16::MAX,
+                torque: u16::MAX,
+                inverter: u16::MAX,
+                high_side: u16::MAX,
+                direction: u16::MAX,
+                acceleration: u16::MAX,
+            },
+        };
+
+        let frame: Result<heapless::Vec<u8, MONITOR_MESSAGE_BUFFER_SIZE>, _> = encode_frame(&msg);
+        assert!(frame.is_ok());
+
+        let msg = MainToMonitor {
+            pong: u64::MAX,
+            state: MainState::Error(MainError::AcceleratorError),
+            accelerator: u16::MAX,
+            high_side_on: Some(true),
+            hv_connected: true,
+            gear: Gear::Drive,
+            reaction_ack: Some(Reaction::DisconnectHv),
+            fault_log: Some(FaultLogRequest {
+                id: u8::MAX,
+                command: FaultLogCommand::Read(u8::MAX),
+            }),
+            calibration: Some(CalibrationBlock {
+                version: u16::MAX,
+                calibration: Calibration {
+                    throttle_sensor1: (u16::MAX, u16::MAX),
+                    throttle_sensor2: (u16::MAX, u16::MAX),
+                    throttle_tolerance: u16::MAX,
+                    throttle_timeout: u16::MAX,
+                    torque_frame_timeout: u16::MAX,
+                    torque_error_timeout: u16::MAX,
+                    ua_pedal_released: u16::MAX,
+                    ua_rpm_rise: u16::MAX,
+                    ua_drive_torque: u16::MAX,
+                    ua_time: u16::MAX,
+                },
+                crc: u32::MAX,
+            }),
+        };
+
+        let frame: Result<heapless::Vec<u8, MONITOR_MESSAGE_BUFFER_SIZE>, _> = encode_frame(&msg);
+        assert!(frame.is_ok());
     }
 }
\ No newline at end of file
@@ -1,17 +1,92 @@
-use embedded_io::{Read, Write};
+use embedded_io::{Read, ReadReady, Write};
 use heapless::Vec;
+use serde::{de::DeserializeOwned, Serialize};
 
-use crate::monitor_message::{MainToMonitor, MonitorToMain};
+use crate::{
+    crc8::{calc_crc8, generate_lookup},
+    monitor_message::{MainToMonitor, MonitorToMain, SerialStats},
+};
+
+const CRC8_LOOKUP: [u8; 256] = generate_lookup(0x07);
+
+#[derive(Debug)]
+pub enum MonitorSerialError {
+    Postcard(postcard::Error),
+    Io,
+    /// The frame is larger than the buffer
+    Overflow,
+}
+
+impl From<postcard::Error> for MonitorSerialError {
+    fn from(value: postcard::Error) -> Self {
+        Self::Postcard(value)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum FrameError {
+    /// Bad COBS encoding or CRC
+    Crc,
+    Postcard(postcard::Error),
+}
+
+/// Encode a message into a frame, the postcard bytes followed by their CRC8, COBS encoded and
+/// terminated by a zero byte.
+///
+/// # Example
+///
+/// ```
+/// use common::monitor_serial::{decode_frame, encode_frame};
+/// use heapless::Vec;
+///
+/// let mut frame: Vec<u8, 16> = encode_frame(&(7u8, 300u16)).unwrap();
+/// assert_eq!(frame.pop(), Some(0));
+/// assert_eq!(decode_frame::<(u8, u16)>(&mut frame), Ok((7, 300)));
+/// ```
+pub fn encode_frame<T: Serialize, const N: usize>(
+    msg: &T,
+) -> Result<Vec<u8, N>, MonitorSerialError> {
+    let mut payload = [0u8; N];
+    let len = postcard::to_slice(msg, &mut payload)?.len();
+    if len >= N {
+        return Err(MonitorSerialError::Overflow);
+    }
+    payload[len] = calc_crc8(&payload[..len], &CRC8_LOOKUP);
+
+    let mut frame: Vec<u8, N> = Vec::new();
+    frame.resize_default(N).ok();
+    let len = cobs::try_encode(&payload[..len + 1], &mut frame)
+        .map_err(|_| MonitorSerialError::Overflow)?;
+    frame.truncate(len);
+    frame.push(0).map_err(|_| MonitorSerialError::Overflow)?;
+
+    Ok(frame)
+}
+
+/// Decode a frame without its terminating zero byte, the frame is decoded in place
+pub fn decode_frame<T: DeserializeOwned>(frame: &mut [u8]) -> Result<T, FrameError> {
+    let len = cobs::decode_in_place(frame).map_err(|_| FrameError::Crc)?;
+    let (crc, payload) = frame[..len].split_last().ok_or(FrameError::Crc)?;
+    if calc_crc8(payload, &CRC8_LOOKUP) != *crc {
+        return Err(FrameError::Crc);
+    }
+
+    postcard::from_bytes(payload).map_err(FrameError::Postcard)
+}
 
 // look into this blog: https://ferrous-systems.com/blog/test-embedded-app/
 
+/// Serial link between the monitor and main MCU. Messages are sent in frames, see
+/// `encode_frame()`. Frames that are dropped by the receiver are counted in the statistics.
 pub struct MonitorSerialPort<const N: usize, TX, RX>
 where
     TX: Write,
-    RX: Read,
+    RX: Read + ReadReady,
 {
     buffer: Vec<u8, N>,
-    to_read: Option<usize>,
+    /// Set when a frame did not fit in the buffer, the rest of it is dropped
+    discard: bool,
+    stats: SerialStats,
     tx: TX,
     rx: RX,
 }
@@ -19,28 +94,192 @@ where
 impl<const N: usize, TX, RX> MonitorSerialPort<N, TX, RX>
 where
     TX: Write,
-    RX: Read,
+    RX: Read + ReadReady,
 {
     pub fn new(tx: TX, rx: RX) -> Self {
         MonitorSerialPort {
             buffer: Vec::new(),
-            to_read: None,
+            discard: false,
+            stats: SerialStats::default(),
             tx,
             rx,
         }
     }
 
+    /// Read everything that has been received without blocking and return the latest complete
+    /// message, if any. Frames that are too long or can't be decoded are dropped.
     pub fn poll(&mut self) -> Option<MainToMonitor> {
-        None
+        let mut message = None;
+
+        loop {
+            match self.rx.read_ready() {
+                Ok(true) => {}
+                Ok(false) => break,
+                // The receiver lost data, the frame in progress is incomplete
+                Err(_) => {
+                    self.overrun();
+                    break;
+                }
+            }
+
+            let mut byte = [0u8];
+            if !matches!(self.rx.read(&mut byte), Ok(1)) {
+                break;
+            }
+
+            if byte[0] == 0 {
+                if !self.discard {
+                    match decode_frame(&mut self.buffer) {
+                        Ok(msg) => message = Some(msg),
+                        Err(_) => {
+                            self.stats.crc_errors = self.stats.crc_errors.saturating_add(1)
+                        }
+                    }
+                }
+                self.buffer.clear();
+                self.discard = false;
+            } else if !self.discard && self.buffer.push(byte[0]).is_err() {
+                self.overrun();
+            }
+        }
+
+        message
+    }
+
+    /// Frames dropped by the receiver since startup
+    pub fn stats(&self) -> SerialStats {
+        self.stats
+    }
+
+    /// Transmit side, for transmitters that need to be serviced
+    pub fn tx(&mut self) -> &mut TX {
+        &mut self.tx
+    }
+
+    pub fn send(&mut self, msg: &MonitorToMain) -> Result<(), MonitorSerialError> {
+        let bytes: Vec<u8, N> = encode_frame(msg)?;
+
+        self.tx.write_all(&bytes).map_err(|_| MonitorSerialError::Io)
+    }
+
+    /// Drop the frame in progress
+    fn overrun(&mut self) {
+        self.stats.overruns = self.stats.overruns.saturating_add(1);
+        self.buffer.clear();
+        self.discard = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{gear::Gear, monitor_message::*};
+
+    /// Receive side that hands out the bytes of a slice
+    struct MockRx<'a>(&'a [u8]);
+
+    impl embedded_io::ErrorType for MockRx<'_> {
+        type Error = embedded_io::ErrorKind;
     }
 
-    pub fn send(&mut self, msg: &MonitorToMain) -> Result<(), postcard::Error> {
-        let bytes: Vec<u8, N> = postcard::to_vec(msg)?;
+    impl Read for MockRx<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+            self.0.read(buf).map_err(|_| embedded_io::ErrorKind::Other)
+        }
+    }
+
+    impl ReadReady for MockRx<'_> {
+        fn read_ready(&mut self) -> Result<bool, Self::Error> {
+            Ok(!self.0.is_empty())
+        }
+    }
 
-        for byte in bytes {
-            nb::block!(self.tx.write(buf))
+    fn main_to_monitor(pong: u64) -> MainToMonitor {
+        MainToMonitor {
+            pong,
+            state: MainState::Operational,
+            accelerator: 0,
+            high_side_on: None,
+            hv_connected: false,
+            gear: Gear::Park,
+            reaction_ack: None,
+            fault_log: None,
+            calibration: None,
         }
+    }
+
+    #[test]
+    fn send() {
+        let mut tx = [0u8; 32];
+        let mut port: MonitorSerialPort<32, _, _> =
+            MonitorSerialPort::new(&mut tx[..], MockRx(&[]));
+
+        let msg = MonitorToMain {
+            ping: 1,
+            state: MonitorState::Operational,
+            reset_cause: ResetCause::PowerOn,
+            fault_log: None,
+            calibration_crc: None,
+            diagnostics: Diagnostics::Serial(SerialStats::default()),
+        };
+        port.send(&msg).unwrap();
+
+        // Postcard bytes and CRC without zeros, then the terminating zero
+        let crc = calc_crc8(&[0x01, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], &CRC8_LOOKUP);
+        assert_eq!(
+            tx[..11],
+            [0x03, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x02, crc, 0x00]
+        );
+        assert_eq!(decode_frame(&mut tx[..10]), Ok(msg));
+    }
+
+    #[test]
+    fn poll() {
+        let frame: Vec<u8, 32> = encode_frame(&main_to_monitor(7)).unwrap();
+        let mut bytes: Vec<u8, 64> = Vec::new();
+        bytes.extend_from_slice(&frame).unwrap();
+        bytes.extend_from_slice(&frame[..3]).unwrap();
+
+        let mut port: MonitorSerialPort<32, _, _> =
+            MonitorSerialPort::new(&mut [0u8; 0][..], MockRx(&bytes));
+        assert_eq!(port.poll(), Some(main_to_monitor(7)));
+
+        // The rest of a frame is received later
+        port.rx = MockRx(&frame[3..]);
+        assert_eq!(port.poll(), Some(main_to_monitor(7)));
+        assert_eq!(port.poll(), None);
+    }
+
+    #[test]
+    fn bad_frames() {
+        let frame: Vec<u8, 32> = encode_frame(&main_to_monitor(7)).unwrap();
+        let mut bytes: Vec<u8, 64> = Vec::new();
+        // Too long, garbage and a valid frame
+        bytes.extend_from_slice(&[0x55; 40]).unwrap();
+        bytes.push(0).unwrap();
+        bytes.extend_from_slice(&[0x02, 0x17, 0x00]).unwrap();
+        bytes.extend_from_slice(&frame).unwrap();
+
+        let mut port: MonitorSerialPort<32, _, _> =
+            MonitorSerialPort::new(&mut [0u8; 0][..], MockRx(&bytes));
+        assert_eq!(port.poll(), Some(main_to_monitor(7)));
+        assert_eq!(
+            port.stats(),
+            SerialStats {
+                crc_errors: 1,
+                overruns: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn corrupted_frame() {
+        let mut frame: Vec<u8, 32> = encode_frame(&main_to_monitor(7)).unwrap();
+        frame[2] ^= 0x10;
 
-        Ok(())
+        let mut port: MonitorSerialPort<32, _, _> =
+            MonitorSerialPort::new(&mut [0u8; 0][..], MockRx(&frame));
+        assert_eq!(port.poll(), None);
+        assert_eq!(port.stats().crc_errors, 1);
     }
 }
@@ -0,0 +1,165 @@
+use crate::{curve::Curve, gear::Gear};
+
+/// Driver torque request from the accelerator pedal, in the units of
+/// `EvCanFrame::TorqueRequest`.
+///
+/// The pedal curve maps the throttle position (`0..=u16::MAX`) to a share of the maximum
+/// torque in per mille, the maximum torque curve gives the drive torque available at the
+/// absolute motor speed in rpm. Creep torque is requested below `creep_rpm` with the pedal
+/// released, it fades out linearly towards `creep_rpm` and the pedal torque takes over as soon
+/// as it is larger. The result gets the direction of the selected gear, park and neutral
+/// request no torque.
+///
+/// # Example
+/// ```
+/// use common::{curve::Curve, gear::Gear, pedal_map::PedalMap};
+/// let map = PedalMap::new(
+///     Curve::new([0, 65535], [0, 1000]),
+///     Curve::new([0, 5000, 10000], [2000, 2000, 1000]),
+///     40,
+///     200,
+/// );
+///
+/// // Half pedal at 7500 rpm
+/// assert_eq!(map.torque(32768, 7500, Gear::Drive), 750);
+/// // Creep at standstill, halved at 100 rpm
+/// assert_eq!(map.torque(0, 0, Gear::Reverse), -40);
+/// assert_eq!(map.torque(0, -100, Gear::Reverse), -20);
+/// assert_eq!(map.torque(65535, 0, Gear::Neutral), 0);
+/// ```
+pub struct PedalMap<const P: usize, const R: usize> {
+    pedal: Curve<P>,
+    max_torque: Curve<R>,
+    creep_torque: i16,
+    creep_rpm: u16,
+}
+
+/// Driver torque request of the car, a progressive pedal with a flat maximum torque up to the
+/// base speed and constant power above. Every corner of the pedal and maximum torque curves stays
+/// below the torque envelope of the monitor, creep stays below the drive torque its unintended
+/// acceleration check allows while braking.
+pub const PEDAL_MAP: PedalMap<4, 4> = PedalMap::new(
+    Curve::new([0, 6554, 32768, 65535], [0, 150, 550, 1000]),
+    Curve::new([0, 3000, 6000, 10000], [2000, 1800, 1200, 800]),
+    40,
+    300,
+);
+
+impl<const P: usize, const R: usize> PedalMap<P, R> {
+    /// Create a new pedal map.
+    ///
+    /// `pedal: Curve<P>` share of the maximum torque in per mille over the throttle position
+    ///
+    /// `max_torque: Curve<R>` maximum drive torque over the absolute motor speed in rpm
+    ///
+    /// `creep_torque: i16` torque at standstill with the pedal released
+    ///
+    /// `creep_rpm: u16` absolute motor speed at which creep torque has faded out
+    pub const fn new(
+        pedal: Curve<P>,
+        max_torque: Curve<R>,
+        creep_torque: i16,
+        creep_rpm: u16,
+    ) -> Self {
+        Self {
+            pedal,
+            max_torque,
+            creep_torque,
+            creep_rpm,
+        }
+    }
+
+    /// Torque request for a throttle position, the motor speed reported by the inverter and
+    /// the selected gear
+    pub fn torque(&self, position: u16, rpm: i16, gear: Gear) -> i16 {
+        let speed = rpm.unsigned_abs();
+
+        let max_torque = self.max_torque.at(speed).max(0) as i32;
+        let share = self.pedal.at(position).clamp(0, 1000) as i32;
+        let torque = (max_torque * share / 1000).max(self.creep(speed));
+
+        (torque * gear.direction() as i32) as i16
+    }
+
+    fn creep(&self, speed: u16) -> i32 {
+        if speed >= self.creep_rpm {
+            return 0;
+        }
+
+        self.creep_torque.max(0) as i32 * (self.creep_rpm - speed) as i32 / self.creep_rpm as i32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::torque_envelope::TORQUE_ENVELOPE;
+
+    #[test]
+    fn pedal_curve() {
+        // Above the creep speed, full torque at 3000 rpm is 1800
+        assert_eq!(PEDAL_MAP.torque(0, 3000, Gear::Drive), 0);
+        assert_eq!(PEDAL_MAP.torque(6554, 3000, Gear::Drive), 270);
+        assert_eq!(PEDAL_MAP.torque(32768, 3000, Gear::Drive), 990);
+        assert_eq!(PEDAL_MAP.torque(65535, 3000, Gear::Drive), 1800);
+        assert_eq!(PEDAL_MAP.torque(19661, 3000, Gear::Drive), 630);
+
+        // Torque never decreases with more pedal
+        let mut previous = 0;
+        for position in (0..=u16::MAX).step_by(64) {
+            let torque = PEDAL_MAP.torque(position, 4000, Gear::Drive);
+            assert!(torque >= previous, "{position}");
+            previous = torque;
+        }
+    }
+
+    #[test]
+    fn max_torque_curve() {
+        assert_eq!(PEDAL_MAP.torque(65535, 0, Gear::Drive), 2000);
+        assert_eq!(PEDAL_MAP.torque(65535, 1500, Gear::Drive), 1900);
+        assert_eq!(PEDAL_MAP.torque(65535, 6000, Gear::Drive), 1200);
+        assert_eq!(PEDAL_MAP.torque(65535, 8000, Gear::Drive), 1000);
+        // Clamped above the last breakpoint and the same in both directions of rotation
+        assert_eq!(PEDAL_MAP.torque(65535, 12000, Gear::Drive), 800);
+        assert_eq!(PEDAL_MAP.torque(65535, -8000, Gear::Drive), 1000);
+        assert_eq!(PEDAL_MAP.torque(65535, i16::MIN, Gear::Drive), 800);
+    }
+
+    #[test]
+    fn creep() {
+        assert_eq!(PEDAL_MAP.torque(0, 0, Gear::Drive), 40);
+        assert_eq!(PEDAL_MAP.torque(0, 150, Gear::Drive), 20);
+        assert_eq!(PEDAL_MAP.torque(0, -150, Gear::Drive), 20);
+        assert_eq!(PEDAL_MAP.torque(0, 300, Gear::Drive), 0);
+        assert_eq!(PEDAL_MAP.torque(0, 1000, Gear::Drive), 0);
+
+        // A little pedal doesn't drop below creep, more pedal takes over
+        assert_eq!(PEDAL_MAP.torque(655, 0, Gear::Drive), 40);
+        assert_eq!(PEDAL_MAP.torque(1311, 0, Gear::Drive), 60);
+    }
+
+    #[test]
+    fn gear_sign() {
+        for (position, rpm) in [(0, 0), (32768, -2000), (65535, 9000)] {
+            let drive = PEDAL_MAP.torque(position, rpm, Gear::Drive);
+            assert!(drive > 0);
+            assert_eq!(PEDAL_MAP.torque(position, rpm, Gear::Reverse), -drive);
+            assert_eq!(PEDAL_MAP.torque(position, rpm, Gear::Park), 0);
+            assert_eq!(PEDAL_MAP.torque(position, rpm, Gear::Neutral), 0);
+        }
+    }
+
+    #[test]
+    fn inside_envelope() {
+        // The calibration of the car against the envelope the monitor checks it against
+        for position in (0..=u16::MAX).step_by(257) {
+            for rpm in (-11000..=11000).step_by(50) {
+                let torque = PEDAL_MAP.torque(position, rpm, Gear::Drive);
+                assert!(
+                    torque <= TORQUE_ENVELOPE.max_torque(position, rpm),
+                    "{position} {rpm} {torque}"
+                );
+            }
+        }
+    }
+}
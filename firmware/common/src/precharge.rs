@@ -0,0 +1,682 @@
+use crate::{
+    dtc::{Dtc, DtcSet},
+    vehicle_state::PrechargeStatus,
+};
+
+/// Precharge sequence timing and thresholds, times are in cycles of the caller
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct PrechargeConfig {
+    /// DC link voltage that completes precharge, in percent of the pack voltage
+    pub target_percent: u8,
+    /// Reaching the target faster than this means there is no load behind the contactors or
+    /// the precharge resistor is shorted
+    pub min_time: usize,
+    /// Not reaching the target within this time means the circuit is open or loaded
+    pub max_time: usize,
+    /// Time both the upper contactor and the precharge relay are closed before the relay opens
+    pub overlap: usize,
+    /// The DC link is disconnected below this voltage, in percent of the pack voltage
+    pub open_percent: u8,
+    /// Time the DC link has to fall below `open_percent` with everything open, before closing
+    /// and after opening
+    pub open_time: usize,
+    /// Time the precharge relay is closed on its own before the lower contactor closes
+    pub weld_check_time: usize,
+    /// Rise of the DC link voltage in millivolt during the weld check that means the lower
+    /// contactor is welded
+    pub weld_millivolt: u32,
+    /// Time the DC link has to stay at the target through the upper contactor once the
+    /// precharge relay opens
+    pub verify_time: usize,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum PrechargeError {
+    /// No pack voltage to precharge to
+    PackVoltageMissing,
+    /// No DC link voltage from the inverter
+    DcVoltageMissing,
+    /// The target was reached after the given number of cycles, before `min_time`
+    TooFast(usize),
+    /// The DC link voltage in millivolt at `max_time`
+    TooSlow(u32),
+    /// The DC link voltage in millivolt at `open_time` with everything open
+    NotDischarged(u32),
+    /// The DC link voltage in millivolt that rose during the weld check
+    LowerWelded(u32),
+    /// The DC link voltage in millivolt that dropped below the target after the precharge
+    /// relay opened
+    StuckOpen(u32),
+    /// The DC link voltage in millivolt that fell below `open_percent` while closed
+    Dropout(u32),
+}
+
+impl PrechargeError {
+    /// DTC raised for the error, errors of the contactors themselves keep HV from being
+    /// connected again
+    pub fn dtc(&self) -> Option<Dtc> {
+        match self {
+            PrechargeError::PackVoltageMissing
+            | PrechargeError::DcVoltageMissing
+            | PrechargeError::TooSlow(_) => None,
+            PrechargeError::TooFast(_) => Some(Dtc::UpperContactorWelded),
+            PrechargeError::NotDischarged(_) => Some(Dtc::ContactorsWelded),
+            PrechargeError::LowerWelded(_) => Some(Dtc::LowerContactorWelded),
+            PrechargeError::StuckOpen(_) => Some(Dtc::ContactorStuckOpen),
+            PrechargeError::Dropout(_) => Some(Dtc::ContactorDropout),
+        }
+    }
+}
+
+/// Levels of the contactor and precharge relay enables
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct ContactorOutputs {
+    pub precharge: bool,
+    pub upper: bool,
+    pub lower: bool,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum State {
+    Open,
+    /// Everything open, waiting for the DC link to be disconnected before closing anything
+    Discharging(usize),
+    /// Only the precharge relay closed, the DC link has to stay at the voltage it started at
+    WeldCheck {
+        cycles: usize,
+        millivolt: u32,
+    },
+    /// Lower contactor and precharge relay closed, charging the DC link through the resistor
+    Precharging(usize),
+    /// Upper contactor closed, the precharge relay is still closed
+    Closing(usize),
+    /// Precharge relay open, the DC link has to stay at the target
+    Verifying(usize),
+    Closed,
+    /// Everything open after HV was connected, the DC link has to fall below `open_percent`
+    Opening(usize),
+    Failed(PrechargeError),
+}
+
+/// Closes the HV contactors through the precharge resistor and checks them for welded and
+/// stuck open contacts. The sequence starts when HV is requested:
+///
+/// 1. With everything open the DC link voltage reported by the inverter has to fall below
+///    `open_percent` of the pack voltage within `open_time`, otherwise both contactors are
+///    welded.
+/// 2. The precharge relay closes on its own for `weld_check_time`. The DC link voltage rising
+///    by `weld_millivolt` means the lower contactor is welded.
+/// 3. The lower contactor closes and the DC link voltage has to reach `target_percent` of the
+///    pack voltage between `min_time` and `max_time`. Reaching it too fast means the upper
+///    contactor is welded or the precharge resistor shorted.
+/// 4. The upper contactor closes and the precharge relay opens after `overlap`. The DC link
+///    voltage dropping below the target within `verify_time` means the upper contactor is
+///    stuck open, the sequence is complete after that.
+/// 5. While closed, the DC link voltage falling below `open_percent` means a contactor
+///    dropped out. Missing voltages are left to the vehicle state machine here.
+///
+/// A failed sequence opens everything and stays failed until HV is no longer requested.
+/// Opening after HV was connected checks that the DC link voltage falls below `open_percent`
+/// within `open_time` again, a new sequence only starts once that check is done.
+///
+/// Contactor faults raise a DTC. The DTCs are kept by the caller, the controller doesn't
+/// refuse to start with an active DTC itself.
+///
+/// # Example
+///
+/// ```
+/// use common::dtc::DtcSet;
+/// use common::precharge::{PrechargeConfig, PrechargeController};
+/// use common::vehicle_state::PrechargeStatus;
+///
+/// let mut precharge = PrechargeController::new(PrechargeConfig {
+///     target_percent: 95,
+///     min_time: 2,
+///     max_time: 10,
+///     overlap: 1,
+///     open_percent: 90,
+///     open_time: 10,
+///     weld_check_time: 1,
+///     weld_millivolt: 10_000,
+///     verify_time: 1,
+/// });
+/// let mut dtcs = DtcSet::new();
+///
+/// precharge.tick(true, Some(360_000), Some(0), &mut dtcs);
+/// let outputs = precharge.tick(true, Some(360_000), Some(0), &mut dtcs);
+/// assert!(outputs.precharge && !outputs.lower && !outputs.upper);
+///
+/// let outputs = precharge.tick(true, Some(360_000), Some(0), &mut dtcs);
+/// assert!(outputs.lower && outputs.precharge && !outputs.upper);
+///
+/// for dc_millivolt in [100_000, 250_000, 345_000, 360_000, 360_000] {
+///     precharge.tick(true, Some(360_000), Some(dc_millivolt), &mut dtcs);
+/// }
+/// assert_eq!(precharge.status(), PrechargeStatus::Complete);
+/// assert!(dtcs.is_empty());
+/// ```
+pub struct PrechargeController {
+    config: PrechargeConfig,
+    state: State,
+}
+
+impl PrechargeController {
+    pub fn new(config: PrechargeConfig) -> Self {
+        Self {
+            config,
+            state: State::Open,
+        }
+    }
+
+    /// Progress of the sequence for the vehicle state machine
+    pub fn status(&self) -> PrechargeStatus {
+        match self.state {
+            State::Closed => PrechargeStatus::Complete,
+            State::Failed(error) => PrechargeStatus::Failed(error),
+            _ => PrechargeStatus::Pending,
+        }
+    }
+
+    /// Advance the sequence, called once every cycle with the pack voltage and the DC link
+    /// voltage from the inverter. Contactor faults are raised in `dtcs`. Returns the outputs
+    /// to apply.
+    pub fn tick(
+        &mut self,
+        request: bool,
+        pack_millivolt: Option<u32>,
+        dc_millivolt: Option<u32>,
+        dtcs: &mut DtcSet,
+    ) -> ContactorOutputs {
+        self.state = match self.state {
+            State::Opening(cycles) => self.opening(cycles, pack_millivolt, dc_millivolt, dtcs),
+            State::Closing(_) | State::Verifying(_) | State::Closed if !request => {
+                State::Opening(0)
+            }
+            _ if !request => State::Open,
+            _ => self
+                .next(pack_millivolt, dc_millivolt)
+                .unwrap_or_else(|error| {
+                    if let Some(dtc) = error.dtc() {
+                        dtcs.raise(dtc);
+                    }
+                    State::Failed(error)
+                }),
+        };
+
+        self.outputs()
+    }
+
+    fn next(
+        &self,
+        pack_millivolt: Option<u32>,
+        dc_millivolt: Option<u32>,
+    ) -> Result<State, PrechargeError> {
+        let voltages = || {
+            let pack = pack_millivolt.ok_or(PrechargeError::PackVoltageMissing)?;
+            let dc = dc_millivolt.ok_or(PrechargeError::DcVoltageMissing)?;
+            Ok((pack, dc))
+        };
+
+        let state = match self.state {
+            State::Open => State::Discharging(0),
+            State::Discharging(cycles) => {
+                let (pack, dc) = voltages()?;
+                let cycles = cycles + 1;
+                if self.disconnected(pack, dc) {
+                    State::WeldCheck {
+                        cycles: 0,
+                        millivolt: dc,
+                    }
+                } else if cycles >= self.config.open_time {
+                    return Err(PrechargeError::NotDischarged(dc));
+                } else {
+                    State::Discharging(cycles)
+                }
+            }
+            State::WeldCheck { cycles, millivolt } => {
+                let (_, dc) = voltages()?;
+                let cycles = cycles + 1;
+                if dc >= millivolt.saturating_add(self.config.weld_millivolt) {
+                    return Err(PrechargeError::LowerWelded(dc));
+                } else if cycles >= self.config.weld_check_time {
+                    State::Precharging(0)
+                } else {
+                    State::WeldCheck { cycles, millivolt }
+                }
+            }
+            State::Precharging(cycles) => {
+                let (pack, dc) = voltages()?;
+                let cycles = cycles + 1;
+
+                if self.precharged(pack, dc) {
+                    if cycles < self.config.min_time {
+                        return Err(PrechargeError::TooFast(cycles));
+                    }
+                    State::Closing(0)
+                } else if cycles >= self.config.max_time {
+                    return Err(PrechargeError::TooSlow(dc));
+                } else {
+                    State::Precharging(cycles)
+                }
+            }
+            State::Closing(cycles) if cycles + 1 >= self.config.overlap => State::Verifying(0),
+            State::Closing(cycles) => State::Closing(cycles + 1),
+            State::Verifying(cycles) => {
+                let (pack, dc) = voltages()?;
+                if !self.precharged(pack, dc) {
+                    return Err(PrechargeError::StuckOpen(dc));
+                } else if cycles + 1 >= self.config.verify_time {
+                    State::Closed
+                } else {
+                    State::Verifying(cycles + 1)
+                }
+            }
+            State::Closed => match (pack_millivolt, dc_millivolt) {
+                (Some(pack), Some(dc)) if self.disconnected(pack, dc) => {
+                    return Err(PrechargeError::Dropout(dc));
+                }
+                _ => State::Closed,
+            },
+            State::Opening(_) | State::Failed(_) => self.state,
+        };
+
+        Ok(state)
+    }
+
+    /// Watch the DC link decay after opening. Without voltages the check can't be done and is
+    /// skipped.
+    fn opening(
+        &self,
+        cycles: usize,
+        pack_millivolt: Option<u32>,
+        dc_millivolt: Option<u32>,
+        dtcs: &mut DtcSet,
+    ) -> State {
+        let (Some(pack), Some(dc)) = (pack_millivolt, dc_millivolt) else {
+            return State::Open;
+        };
+
+        let cycles = cycles + 1;
+        if self.disconnected(pack, dc) {
+            State::Open
+        } else if cycles >= self.config.open_time {
+            dtcs.raise(Dtc::ContactorsWelded);
+            State::Open
+        } else {
+            State::Opening(cycles)
+        }
+    }
+
+    fn precharged(&self, pack: u32, dc: u32) -> bool {
+        dc as u64 * 100 >= pack as u64 * self.config.target_percent as u64
+    }
+
+    fn disconnected(&self, pack: u32, dc: u32) -> bool {
+        (dc as u64 * 100) < pack as u64 * self.config.open_percent as u64
+    }
+
+    fn outputs(&self) -> ContactorOutputs {
+        match self.state {
+            State::Open | State::Discharging(_) | State::Opening(_) | State::Failed(_) => {
+                ContactorOutputs::default()
+            }
+            State::WeldCheck { .. } => ContactorOutputs {
+                precharge: true,
+                upper: false,
+                lower: false,
+            },
+            State::Precharging(_) => ContactorOutputs {
+                precharge: true,
+                upper: false,
+                lower: true,
+            },
+            State::Closing(_) => ContactorOutputs {
+                precharge: true,
+                upper: true,
+                lower: true,
+            },
+            State::Verifying(_) | State::Closed => ContactorOutputs {
+                precharge: false,
+                upper: true,
+                lower: true,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 10 ms cycles
+    const CYCLE_MS: f32 = 10.0;
+    const PACK_MILLIVOLT: u32 = 360_000;
+
+    const CONFIG: PrechargeConfig = PrechargeConfig {
+        target_percent: 95,
+        // 100 ms to 2 s
+        min_time: 10,
+        max_time: 200,
+        overlap: 5,
+        open_percent: 90,
+        open_time: 300,
+        weld_check_time: 5,
+        weld_millivolt: 10_000,
+        verify_time: 20,
+    };
+
+    /// Cycles before the lower contactor closes: one with everything open, one to see the DC
+    /// link disconnected and the weld check
+    const CHECK_CYCLES: usize = 2 + CONFIG.weld_check_time;
+
+    /// DC link capacitance charged from the pack through the precharge resistor, with a load
+    /// in parallel to the capacitance
+    #[derive(Default)]
+    struct Rc {
+        /// Time constant of the precharge resistor and the DC link capacitance in ms
+        tau_ms: f32,
+        /// Ratio of the load to the precharge resistor, `None` for no load
+        load_ratio: Option<f32>,
+        millivolt: f32,
+        lower_welded: bool,
+        upper_welded: bool,
+        upper_stuck_open: bool,
+    }
+
+    impl Rc {
+        fn new(tau_ms: f32) -> Self {
+            Self {
+                tau_ms,
+                ..Default::default()
+            }
+        }
+
+        fn step(&mut self, outputs: ContactorOutputs) -> u32 {
+            let pack = PACK_MILLIVOLT as f32;
+            let alpha = (CYCLE_MS / self.tau_ms).min(1.0);
+            let lower = outputs.lower || self.lower_welded;
+            let upper = (outputs.upper && !self.upper_stuck_open) || self.upper_welded;
+
+            if lower && upper {
+                self.millivolt = pack;
+            } else if lower && outputs.precharge {
+                // The load divides the voltage the capacitance charges to
+                let settled = match self.load_ratio {
+                    Some(ratio) => pack * ratio / (1.0 + ratio),
+                    None => pack,
+                };
+                self.millivolt += (settled - self.millivolt) * alpha;
+            } else {
+                // Without a load the discharge resistor is much larger than the precharge
+                // resistor
+                self.millivolt -= self.millivolt * alpha / self.load_ratio.unwrap_or(100.0);
+            }
+
+            self.millivolt as u32
+        }
+    }
+
+    /// Run the sequence until it completes or fails, returns the status and the cycles it took
+    fn run(rc: &mut Rc, dtcs: &mut DtcSet) -> (PrechargeStatus, usize) {
+        let mut precharge = PrechargeController::new(CONFIG);
+        let mut dc_millivolt = rc.step(ContactorOutputs::default());
+
+        for cycle in 1..1000 {
+            let outputs = precharge.tick(true, Some(PACK_MILLIVOLT), Some(dc_millivolt), dtcs);
+            dc_millivolt = rc.step(outputs);
+
+            if precharge.status() != PrechargeStatus::Pending {
+                return (precharge.status(), cycle);
+            }
+        }
+        panic!("Precharge did not finish");
+    }
+
+    /// Run the sequence and return the DTCs it raised
+    fn failed(rc: &mut Rc, error: PrechargeError) -> DtcSet {
+        let mut dtcs = DtcSet::new();
+        assert_eq!(run(rc, &mut dtcs).0, PrechargeStatus::Failed(error));
+        dtcs
+    }
+
+    #[test]
+    fn sequence() {
+        let mut precharge = PrechargeController::new(CONFIG);
+        let mut dtcs = DtcSet::new();
+        let mut rc = Rc::new(200.0);
+        let mut dc_millivolt = 0;
+        let mut previous = ContactorOutputs::default();
+
+        for _ in 0..300 {
+            let outputs = precharge.tick(true, Some(PACK_MILLIVOLT), Some(dc_millivolt), &mut dtcs);
+            dc_millivolt = rc.step(outputs);
+
+            // The precharge relay closes before the lower contactor, the upper contactor only
+            // closes once precharged and the lower contactor never opens
+            if outputs.lower && !previous.lower {
+                assert!(previous.precharge && !previous.upper);
+            }
+            if outputs.upper && !previous.upper {
+                assert!(previous.lower && previous.precharge);
+                assert!(dc_millivolt >= PACK_MILLIVOLT * 95 / 100);
+            }
+            if previous.lower {
+                assert!(outputs.lower);
+            }
+            previous = outputs;
+        }
+
+        assert_eq!(precharge.status(), PrechargeStatus::Complete);
+        assert_eq!(
+            previous,
+            ContactorOutputs {
+                precharge: false,
+                upper: true,
+                lower: true,
+            }
+        );
+
+        // Everything opens when HV is no longer requested and the DC link discharges
+        for _ in 0..CONFIG.open_time {
+            assert_eq!(
+                precharge.tick(false, Some(PACK_MILLIVOLT), Some(dc_millivolt), &mut dtcs),
+                ContactorOutputs::default()
+            );
+            assert_eq!(precharge.status(), PrechargeStatus::Pending);
+            dc_millivolt = rc.step(ContactorOutputs::default());
+        }
+        assert_eq!(precharge.state, State::Open);
+        assert!(dtcs.is_empty());
+    }
+
+    #[test]
+    fn rc_time() {
+        // 95 % after three time constants plus the checks, the overlap and the verification
+        let overhead = CHECK_CYCLES + CONFIG.overlap + CONFIG.verify_time;
+        let mut dtcs = DtcSet::new();
+
+        let (status, cycles) = run(&mut Rc::new(200.0), &mut dtcs);
+        assert_eq!(status, PrechargeStatus::Complete);
+        assert!((59..=62).contains(&(cycles - overhead)), "{cycles}");
+
+        let (status, cycles) = run(&mut Rc::new(600.0), &mut dtcs);
+        assert_eq!(status, PrechargeStatus::Complete);
+        assert!((179..=182).contains(&(cycles - overhead)), "{cycles}");
+
+        assert!(dtcs.is_empty());
+    }
+
+    #[test]
+    fn too_fast() {
+        // Shorted precharge resistor or too little DC link capacitance
+        let mut dtcs = DtcSet::new();
+        let (status, cycles) = run(&mut Rc::new(25.0), &mut dtcs);
+        assert_eq!(status, PrechargeStatus::Failed(PrechargeError::TooFast(6)));
+        assert_eq!(cycles, CHECK_CYCLES + 6);
+        assert_eq!(dtcs.first(), Some(Dtc::UpperContactorWelded));
+
+        // The DC link charges as soon as the lower contactor closes
+        let mut rc = Rc::new(200.0);
+        rc.upper_welded = true;
+        let dtcs = failed(&mut rc, PrechargeError::TooFast(1));
+        assert_eq!(dtcs.first(), Some(Dtc::UpperContactorWelded));
+    }
+
+    #[test]
+    fn too_slow() {
+        // Open precharge circuit
+        let mut dtcs = DtcSet::new();
+        let (status, cycles) = run(&mut Rc::new(1e9), &mut dtcs);
+        assert_eq!(status, PrechargeStatus::Failed(PrechargeError::TooSlow(0)));
+        assert_eq!(cycles, CHECK_CYCLES + CONFIG.max_time);
+
+        // A load that keeps the DC link below the target
+        let mut rc = Rc::new(200.0);
+        rc.load_ratio = Some(10.0);
+        let (status, _) = run(&mut rc, &mut dtcs);
+        assert!(matches!(
+            status,
+            PrechargeStatus::Failed(PrechargeError::TooSlow(millivolt))
+                if (320_000..330_000).contains(&millivolt)
+        ));
+
+        // Neither is a contactor fault
+        assert!(dtcs.is_empty());
+    }
+
+    #[test]
+    fn welded() {
+        // Both contactors welded, the DC link stays at the pack voltage
+        let mut rc = Rc::new(200.0);
+        rc.lower_welded = true;
+        rc.upper_welded = true;
+        let mut dtcs = DtcSet::new();
+        let (status, cycles) = run(&mut rc, &mut dtcs);
+        assert_eq!(
+            status,
+            PrechargeStatus::Failed(PrechargeError::NotDischarged(PACK_MILLIVOLT))
+        );
+        assert_eq!(cycles, CONFIG.open_time + 1);
+        assert_eq!(dtcs.first(), Some(Dtc::ContactorsWelded));
+
+        // The precharge relay alone charges the DC link
+        let mut rc = Rc::new(200.0);
+        rc.lower_welded = true;
+        let dtcs = failed(&mut rc, PrechargeError::LowerWelded(18_000));
+        assert_eq!(dtcs.first(), Some(Dtc::LowerContactorWelded));
+
+        // A DC link still charged from the last drive has to discharge first
+        let mut rc = Rc::new(200.0);
+        rc.millivolt = PACK_MILLIVOLT as f32;
+        let mut dtcs = DtcSet::new();
+        assert_eq!(run(&mut rc, &mut dtcs).0, PrechargeStatus::Complete);
+        assert!(dtcs.is_empty());
+    }
+
+    #[test]
+    fn stuck_open() {
+        // The load discharges the DC link once the precharge relay opens
+        let mut rc = Rc::new(200.0);
+        rc.load_ratio = Some(50.0);
+        rc.upper_stuck_open = true;
+        let mut dtcs = DtcSet::new();
+        let (status, _) = run(&mut rc, &mut dtcs);
+        assert!(matches!(
+            status,
+            PrechargeStatus::Failed(PrechargeError::StuckOpen(millivolt))
+                if millivolt < PACK_MILLIVOLT * 95 / 100
+        ));
+        assert_eq!(dtcs.first(), Some(Dtc::ContactorStuckOpen));
+
+        // The same load with a working upper contactor
+        let mut rc = Rc::new(200.0);
+        rc.load_ratio = Some(50.0);
+        assert_eq!(run(&mut rc, &mut dtcs).0, PrechargeStatus::Complete);
+    }
+
+    #[test]
+    fn dropout() {
+        let mut precharge = PrechargeController::new(CONFIG);
+        precharge.state = State::Closed;
+        let mut dtcs = DtcSet::new();
+
+        // Missing voltages and a DC link sagging under load
+        precharge.tick(true, Some(PACK_MILLIVOLT), None, &mut dtcs);
+        precharge.tick(true, Some(PACK_MILLIVOLT), Some(330_000), &mut dtcs);
+        assert_eq!(precharge.status(), PrechargeStatus::Complete);
+
+        // The upper contactor drops out and the load discharges the DC link
+        let outputs = precharge.tick(true, Some(PACK_MILLIVOLT), Some(320_000), &mut dtcs);
+        assert_eq!(outputs, ContactorOutputs::default());
+        assert_eq!(
+            precharge.status(),
+            PrechargeStatus::Failed(PrechargeError::Dropout(320_000))
+        );
+        assert_eq!(dtcs.first(), Some(Dtc::ContactorDropout));
+    }
+
+    #[test]
+    fn opening() {
+        let mut precharge = PrechargeController::new(CONFIG);
+        let mut dtcs = DtcSet::new();
+        let mut rc = Rc::new(200.0);
+        let mut dc_millivolt = 0;
+
+        while precharge.status() != PrechargeStatus::Complete {
+            let outputs = precharge.tick(true, Some(PACK_MILLIVOLT), Some(dc_millivolt), &mut dtcs);
+            dc_millivolt = rc.step(outputs);
+        }
+
+        // The contactors weld while closed, HV is requested again during the check
+        rc.lower_welded = true;
+        rc.upper_welded = true;
+        precharge.tick(false, Some(PACK_MILLIVOLT), Some(dc_millivolt), &mut dtcs);
+        for _ in 0..CONFIG.open_time {
+            assert!(dtcs.is_empty());
+            let outputs = precharge.tick(true, Some(PACK_MILLIVOLT), Some(dc_millivolt), &mut dtcs);
+            assert_eq!(outputs, ContactorOutputs::default());
+            dc_millivolt = rc.step(outputs);
+        }
+        assert_eq!(dtcs.first(), Some(Dtc::ContactorsWelded));
+        assert_eq!(precharge.state, State::Open);
+
+        // Without voltages the check is skipped
+        let mut precharge = PrechargeController::new(CONFIG);
+        precharge.state = State::Closed;
+        let mut dtcs = DtcSet::new();
+        precharge.tick(false, Some(PACK_MILLIVOLT), None, &mut dtcs);
+        precharge.tick(false, Some(PACK_MILLIVOLT), None, &mut dtcs);
+        assert_eq!(precharge.state, State::Open);
+        assert!(dtcs.is_empty());
+    }
+
+    #[test]
+    fn missing_voltages() {
+        let mut precharge = PrechargeController::new(CONFIG);
+        let mut dtcs = DtcSet::new();
+        precharge.tick(true, None, Some(0), &mut dtcs);
+        assert_eq!(
+            precharge.tick(true, None, Some(0), &mut dtcs),
+            ContactorOutputs::default()
+        );
+        assert_eq!(
+            precharge.status(),
+            PrechargeStatus::Failed(PrechargeError::PackVoltageMissing)
+        );
+
+        // Failed until HV is no longer requested
+        precharge.tick(true, Some(PACK_MILLIVOLT), Some(0), &mut dtcs);
+        assert_eq!(
+            precharge.status(),
+            PrechargeStatus::Failed(PrechargeError::PackVoltageMissing)
+        );
+        precharge.tick(false, Some(PACK_MILLIVOLT), Some(0), &mut dtcs);
+        precharge.tick(true, Some(PACK_MILLIVOLT), Some(0), &mut dtcs);
+        precharge.tick(true, Some(PACK_MILLIVOLT), None, &mut dtcs);
+        assert_eq!(
+            precharge.status(),
+            PrechargeStatus::Failed(PrechargeError::DcVoltageMissing)
+        );
+        assert!(dtcs.is_empty());
+    }
+}
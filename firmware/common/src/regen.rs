@@ -0,0 +1,397 @@
+use crate::{curve::Curve, gear::Gear};
+
+/// Limits reported by the BMS
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct BatteryLimits {
+    pub millivolt: u32,
+    /// Charge current the pack accepts in A
+    pub charge_current: u16,
+    /// Discharge current the pack delivers in A
+    pub discharge_current: u16,
+    pub soc_percent: u8,
+}
+
+impl BatteryLimits {
+    /// Largest torque the discharge current allows at the absolute motor speed `speed` in rpm.
+    /// `power_torque` is the torque at 1000 rpm for 1 kW.
+    pub fn drive_torque(&self, power_torque: u32, speed: u16) -> i16 {
+        power_torque_at(power_torque, self.discharge_current, self.millivolt, speed)
+    }
+
+    /// Largest regen torque the charge current allows at `speed`, like `drive_torque()`
+    pub fn charge_torque(&self, power_torque: u32, speed: u16) -> i16 {
+        power_torque_at(power_torque, self.charge_current, self.millivolt, speed)
+    }
+}
+
+/// Torque for the power of `current` at `millivolt` and `speed`
+fn power_torque_at(power_torque: u32, current: u16, millivolt: u32, speed: u16) -> i16 {
+    let watt = current as u64 * millivolt as u64 / 1000;
+    let torque = power_torque as u64 * watt / speed.max(1) as u64;
+
+    torque.min(i16::MAX as u64) as i16
+}
+
+/// Regen calibration, torque is in the units of `EvCanFrame::TorqueRequest`
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct RegenConfig<const S: usize> {
+    /// Pedal position below which lift-off regen fades in
+    pub liftoff_position: u16,
+    /// Regen with the pedal fully released at a regen level of 100 %
+    pub liftoff_torque: i16,
+    /// Regen while the brake is applied
+    pub brake_torque: i16,
+    /// Share of the regen in per mille over the motor speed in the direction of the gear
+    pub taper: Curve<S>,
+    /// Torque at 1000 rpm for 1 kW
+    pub power_torque: u32,
+    /// Pack voltage in millivolt up to which full regen is allowed and at which it reaches zero
+    pub millivolt: (u32, u32),
+    /// State of charge in percent up to which full regen is allowed and at which it reaches zero
+    pub soc_percent: (u8, u8),
+}
+
+/// Everything regen depends on, sampled once every cycle
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct RegenInputs {
+    /// Throttle position `0..=u16::MAX`
+    pub position: u16,
+    pub brake: bool,
+    pub rpm: i16,
+    pub gear: Gear,
+    /// `None` while the BMS is not reporting, which allows no regen
+    pub battery: Option<BatteryLimits>,
+    /// Lift-off regen in percent of `liftoff_torque`, brake regen is not affected
+    pub level_percent: u8,
+}
+
+/// Regenerative braking when the driver lifts off the pedal and when the brake is applied.
+///
+/// Lift-off regen fades in linearly as the pedal goes from `liftoff_position` to released and
+/// is scaled by the regen level. The brake switch has no travel to blend with, while it is on
+/// regen rises to `brake_torque` and the friction brakes do the rest. Regen is tapered with the
+/// motor speed to zero before standstill and only applies while the motor turns in the
+/// direction of the gear, park and neutral have no regen.
+///
+/// The battery limits regen by the charge power it accepts at the current motor speed and
+/// by tapering it to zero over the top of the pack voltage and the state of charge.
+///
+/// # Example
+/// ```
+/// use common::{
+///     curve::Curve,
+///     gear::Gear,
+///     regen::{BatteryLimits, Regen, RegenConfig, RegenInputs},
+/// };
+/// let regen = Regen::new(RegenConfig {
+///     liftoff_position: 6554,
+///     liftoff_torque: 200,
+///     brake_torque: 600,
+///     taper: Curve::new([300, 1000], [0, 1000]),
+///     power_torque: 40,
+///     millivolt: (390_000, 400_000),
+///     soc_percent: (90, 95),
+/// });
+/// let mut inputs = RegenInputs {
+///     position: 0,
+///     brake: false,
+///     rpm: -3000,
+///     gear: Gear::Reverse,
+///     battery: Some(BatteryLimits {
+///         millivolt: 360_000,
+///         charge_current: 100,
+///         discharge_current: 300,
+///         soc_percent: 50,
+///     }),
+///     level_percent: 100,
+/// };
+///
+/// // Regen is positive torque in reverse
+/// assert_eq!(regen.torque(&inputs), 200);
+/// inputs.brake = true;
+/// assert_eq!(regen.torque(&inputs), 480);
+/// ```
+pub struct Regen<const S: usize> {
+    config: RegenConfig<S>,
+}
+
+impl<const S: usize> Regen<S> {
+    pub const fn new(config: RegenConfig<S>) -> Self {
+        Self { config }
+    }
+
+    /// Regen torque, against the direction of the gear
+    pub fn torque(&self, inputs: &RegenInputs) -> i16 {
+        let direction = inputs.gear.direction() as i32;
+        let speed = inputs.rpm as i32 * direction;
+        let Some(battery) = inputs.battery else {
+            return 0;
+        };
+        if direction == 0 || speed <= 0 {
+            return 0;
+        }
+
+        let regen =
+            self.demand(inputs) * self.config.taper.at(speed as u16).clamp(0, 1000) as i32 / 1000;
+        let regen = regen.min(battery.charge_torque(self.config.power_torque, speed as u16) as i32);
+        let regen = regen * self.battery_share(battery) as i32 / 1000;
+
+        (-regen * direction) as i16
+    }
+
+    /// Regen the driver asks for
+    fn demand(&self, inputs: &RegenInputs) -> i32 {
+        let config = &self.config;
+
+        let liftoff =
+            config.liftoff_torque.max(0) as i32 * inputs.level_percent.min(100) as i32 / 100;
+        let liftoff = if inputs.position >= config.liftoff_position {
+            0
+        } else {
+            liftoff * (config.liftoff_position - inputs.position) as i32
+                / config.liftoff_position as i32
+        };
+
+        if inputs.brake {
+            liftoff.max(config.brake_torque.max(0) as i32)
+        } else {
+            liftoff
+        }
+    }
+
+    /// Share of the regen in per mille that is left near the top of the pack voltage and the
+    /// state of charge
+    fn battery_share(&self, battery: BatteryLimits) -> u32 {
+        let (full, zero) = self.config.soc_percent;
+        let voltage = per_mille(battery.millivolt, self.config.millivolt);
+        let soc = per_mille(battery.soc_percent as u32, (full as u32, zero as u32));
+
+        voltage * soc / 1000
+    }
+}
+
+/// Share in per mille that is full up to `range.0` and falls linearly to zero at `range.1`
+fn per_mille(value: u32, range: (u32, u32)) -> u32 {
+    let (full, zero) = range;
+
+    if value <= full {
+        1000
+    } else if value >= zero {
+        0
+    } else {
+        ((zero - value) as u64 * 1000 / (zero - full) as u64) as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: RegenConfig<3> = RegenConfig {
+        liftoff_position: 6554,
+        liftoff_torque: 300,
+        brake_torque: 800,
+        taper: Curve::new([300, 1500, 8000], [0, 1000, 1000]),
+        power_torque: 40,
+        millivolt: (395_000, 403_000),
+        soc_percent: (90, 98),
+    };
+
+    const BATTERY: BatteryLimits = BatteryLimits {
+        millivolt: 360_000,
+        charge_current: 400,
+        discharge_current: 600,
+        soc_percent: 50,
+    };
+
+    fn inputs() -> RegenInputs {
+        RegenInputs {
+            position: 0,
+            brake: false,
+            rpm: 4000,
+            gear: Gear::Drive,
+            battery: Some(BATTERY),
+            level_percent: 100,
+        }
+    }
+
+    fn regen(inputs: RegenInputs) -> i16 {
+        Regen::new(CONFIG).torque(&inputs)
+    }
+
+    #[test]
+    fn liftoff() {
+        assert_eq!(regen(inputs()), -300);
+        for (position, torque) in [(3277, -150), (6553, 0), (6554, 0), (65535, 0)] {
+            assert_eq!(
+                regen(RegenInputs {
+                    position,
+                    ..inputs()
+                }),
+                torque
+            );
+        }
+
+        // The regen level scales lift-off regen
+        for (level_percent, torque) in [(0, 0), (50, -150), (100, -300), (200, -300)] {
+            let inputs = RegenInputs {
+                level_percent,
+                ..inputs()
+            };
+            assert_eq!(regen(inputs), torque);
+        }
+    }
+
+    #[test]
+    fn brake() {
+        let braking = RegenInputs {
+            brake: true,
+            ..inputs()
+        };
+        assert_eq!(regen(braking), -800);
+
+        // Independent of the pedal and the regen level
+        let inputs = RegenInputs {
+            position: 65535,
+            level_percent: 0,
+            ..braking
+        };
+        assert_eq!(regen(inputs), -800);
+    }
+
+    #[test]
+    fn speed_taper() {
+        for (rpm, torque) in [(0, 0), (300, 0), (900, -400), (1500, -800), (2000, -800)] {
+            let inputs = RegenInputs {
+                brake: true,
+                rpm,
+                ..inputs()
+            };
+            assert_eq!(regen(inputs), torque, "{rpm}");
+        }
+
+        // Regen never increases towards standstill
+        let mut previous = 0;
+        for rpm in 0..3000 {
+            let torque = regen(RegenInputs {
+                brake: true,
+                rpm,
+                ..inputs()
+            });
+            assert!(torque <= previous, "{rpm}");
+            previous = torque;
+        }
+    }
+
+    #[test]
+    fn direction() {
+        // Regen works against the direction of the gear, only while turning in it
+        let reverse = RegenInputs {
+            gear: Gear::Reverse,
+            rpm: -4000,
+            ..inputs()
+        };
+        assert_eq!(regen(reverse), 300);
+        assert_eq!(
+            regen(RegenInputs {
+                rpm: 4000,
+                ..reverse
+            }),
+            0
+        );
+        assert_eq!(
+            regen(RegenInputs {
+                rpm: -4000,
+                ..inputs()
+            }),
+            0
+        );
+
+        for gear in [Gear::Park, Gear::Neutral] {
+            let inputs = RegenInputs {
+                gear,
+                brake: true,
+                ..inputs()
+            };
+            assert_eq!(regen(inputs), 0);
+        }
+    }
+
+    #[test]
+    fn charge_power() {
+        // 10 kW allow 400 at 1000 rpm and 100 at 4000 rpm
+        let battery = BatteryLimits {
+            millivolt: 400_000,
+            charge_current: 25,
+            discharge_current: 100,
+            soc_percent: 50,
+        };
+        let mut inputs = RegenInputs {
+            brake: true,
+            battery: Some(BatteryLimits {
+                millivolt: 380_000,
+                ..battery
+            }),
+            ..inputs()
+        };
+        assert_eq!(regen(inputs), -95);
+
+        // Constant power, twice the torque at half the speed
+        inputs.rpm = 2000;
+        assert_eq!(regen(inputs), -190);
+
+        // No regen without the BMS or a charge current
+        inputs.battery = None;
+        assert_eq!(regen(inputs), 0);
+        inputs.battery = Some(BatteryLimits {
+            charge_current: 0,
+            ..BATTERY
+        });
+        assert_eq!(regen(inputs), 0);
+    }
+
+    #[test]
+    fn drive_torque() {
+        // 600 A at 360 V are 216 kW
+        assert_eq!(BATTERY.drive_torque(40, 4000), 2160);
+        assert_eq!(BATTERY.drive_torque(40, 8000), 1080);
+        assert_eq!(BATTERY.drive_torque(40, 0), i16::MAX);
+
+        let empty = BatteryLimits {
+            discharge_current: 0,
+            ..BATTERY
+        };
+        assert_eq!(empty.drive_torque(40, 4000), 0);
+    }
+
+    #[test]
+    fn voltage_and_soc() {
+        let braking = RegenInputs {
+            brake: true,
+            rpm: 2000,
+            ..inputs()
+        };
+
+        for (millivolt, torque) in [(395_000, -800), (399_000, -400), (403_000, 0), (410_000, 0)] {
+            let inputs = RegenInputs {
+                battery: Some(BatteryLimits {
+                    millivolt,
+                    ..BATTERY
+                }),
+                ..braking
+            };
+            assert_eq!(regen(inputs), torque, "{millivolt}");
+        }
+
+        for (soc_percent, torque) in [(90, -800), (94, -400), (98, 0), (100, 0)] {
+            let inputs = RegenInputs {
+                battery: Some(BatteryLimits {
+                    soc_percent,
+                    ..BATTERY
+                }),
+                ..braking
+            };
+            assert_eq!(regen(inputs), torque, "{soc_percent}");
+        }
+    }
+}
@@ -0,0 +1,246 @@
+/// Free running counter of CPU cycles used to measure execution times. Only differences are
+/// used, so the counter may wrap.
+pub trait CycleCounter {
+    fn cycles(&self) -> u32;
+}
+
+/// Timing of a task, periods and offsets are in ticks
+#[derive(Debug, Clone, Copy)]
+pub struct Task {
+    pub period: u32,
+    /// First tick the task runs in, spreads tasks with the same period over different ticks
+    pub offset: u32,
+    /// Longest execution time in cycles
+    pub deadline: u32,
+}
+
+/// Execution times in cycles
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct ExecutionStats {
+    pub runs: u32,
+    pub overruns: u32,
+    pub last: u32,
+    pub max: u32,
+}
+
+impl ExecutionStats {
+    fn record(&mut self, cycles: u32, deadline: u32) -> bool {
+        let overrun = cycles > deadline;
+
+        self.runs = self.runs.wrapping_add(1);
+        self.overruns = self.overruns.saturating_add(overrun as u32);
+        self.last = cycles;
+        self.max = self.max.max(cycles);
+
+        overrun
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Overrun {
+    /// The task ran longer than its deadline
+    Task(usize),
+    /// All tasks of the tick together ran longer than a tick
+    Tick,
+}
+
+/// Time-triggered cooperative scheduler. `dispatch()` is called once every tick and runs the
+/// tasks that are due in the order of the task table, every task runs to completion.
+///
+/// # Example
+///
+/// ```
+/// use common::scheduler::{CycleCounter, Scheduler, Task};
+///
+/// struct Counter;
+///
+/// impl CycleCounter for Counter {
+///     fn cycles(&self) -> u32 {
+///         0
+///     }
+/// }
+///
+/// let mut scheduler = Scheduler::new(
+///     [
+///         Task { period: 1, offset: 0, deadline: 100 },
+///         Task { period: 10, offset: 5, deadline: 100 },
+///     ],
+///     1000,
+/// );
+///
+/// let mut runs = [0, 0];
+/// for _ in 0..20 {
+///     scheduler.dispatch(&Counter, |task| runs[task] += 1).unwrap();
+/// }
+/// assert_eq!(runs, [20, 2]);
+/// ```
+pub struct Scheduler<const N: usize> {
+    tasks: [Task; N],
+    /// Ticks until each task is due
+    countdown: [u32; N],
+    stats: [ExecutionStats; N],
+    tick_cycles: u32,
+    tick_stats: ExecutionStats,
+}
+
+impl<const N: usize> Scheduler<N> {
+    /// `tick_cycles` is the length of a tick in cycles
+    pub fn new(tasks: [Task; N], tick_cycles: u32) -> Self {
+        Self {
+            tasks,
+            countdown: tasks.map(|task| task.offset),
+            stats: [ExecutionStats::default(); N],
+            tick_cycles,
+            tick_stats: ExecutionStats::default(),
+        }
+    }
+
+    /// Run the tasks that are due in this tick. All of them are run even if one overruns,
+    /// the first overrun is returned.
+    pub fn dispatch<C: CycleCounter>(
+        &mut self,
+        counter: &C,
+        mut run: impl FnMut(usize),
+    ) -> Result<(), Overrun> {
+        let mut result = Ok(());
+        let tick_start = counter.cycles();
+
+        for task in 0..N {
+            if self.countdown[task] > 0 {
+                self.countdown[task] -= 1;
+                continue;
+            }
+            self.countdown[task] = self.tasks[task].period.saturating_sub(1);
+
+            let start = counter.cycles();
+            run(task);
+            let cycles = counter.cycles().wrapping_sub(start);
+
+            if self.stats[task].record(cycles, self.tasks[task].deadline) && result.is_ok() {
+                result = Err(Overrun::Task(task));
+            }
+        }
+
+        let cycles = counter.cycles().wrapping_sub(tick_start);
+        if self.tick_stats.record(cycles, self.tick_cycles) && result.is_ok() {
+            result = Err(Overrun::Tick);
+        }
+
+        result
+    }
+
+    pub fn task_stats(&self, task: usize) -> ExecutionStats {
+        self.stats[task]
+    }
+
+    /// Execution time of all tasks in a tick
+    pub fn tick_stats(&self) -> ExecutionStats {
+        self.tick_stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::cell::Cell;
+
+    /// Counter that is advanced by the tasks
+    struct MockCounter(Cell<u32>);
+
+    impl MockCounter {
+        fn advance(&self, cycles: u32) {
+            self.0.set(self.0.get().wrapping_add(cycles));
+        }
+    }
+
+    impl CycleCounter for MockCounter {
+        fn cycles(&self) -> u32 {
+            self.0.get()
+        }
+    }
+
+    const TASKS: [Task; 3] = [
+        Task {
+            period: 1,
+            offset: 0,
+            deadline: 100,
+        },
+        Task {
+            period: 4,
+            offset: 1,
+            deadline: 300,
+        },
+        Task {
+            period: 4,
+            offset: 3,
+            deadline: 100,
+        },
+    ];
+
+    #[test]
+    fn periods_and_offsets() {
+        let counter = MockCounter(Cell::new(0));
+        let mut scheduler = Scheduler::new(TASKS, 1000);
+
+        let mut ticks: [heapless::Vec<u32, 16>; 3] = Default::default();
+        for tick in 0..12 {
+            scheduler
+                .dispatch(&counter, |task| ticks[task].push(tick).unwrap())
+                .unwrap();
+        }
+
+        assert_eq!(ticks[0], [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11]);
+        assert_eq!(ticks[1], [1, 5, 9]);
+        assert_eq!(ticks[2], [3, 7, 11]);
+    }
+
+    #[test]
+    fn stats() {
+        let counter = MockCounter(Cell::new(u32::MAX - 50));
+        let mut scheduler = Scheduler::new(TASKS, 1000);
+
+        for _ in 0..9 {
+            scheduler
+                .dispatch(&counter, |task| counter.advance(40 + 10 * task as u32))
+                .unwrap();
+        }
+
+        // The counter wrapped during the first tick
+        assert_eq!(
+            scheduler.task_stats(0),
+            ExecutionStats {
+                runs: 9,
+                overruns: 0,
+                last: 40,
+                max: 40,
+            }
+        );
+        assert_eq!(scheduler.task_stats(1).runs, 2);
+        assert_eq!(scheduler.task_stats(2).last, 60);
+        assert_eq!(scheduler.tick_stats().max, 100);
+        assert_eq!(scheduler.tick_stats().last, 40);
+    }
+
+    #[test]
+    fn overruns() {
+        let counter = MockCounter(Cell::new(0));
+        let mut scheduler = Scheduler::new(TASKS, 150);
+
+        let result = scheduler.dispatch(&counter, |_| counter.advance(90));
+        assert_eq!(result, Ok(()));
+
+        // Task 1 runs in the second tick and overruns its deadline
+        let result = scheduler.dispatch(&counter, |task| counter.advance(100 + 250 * task as u32));
+        assert_eq!(result, Err(Overrun::Task(1)));
+        assert_eq!(scheduler.task_stats(1).overruns, 1);
+        assert_eq!(scheduler.task_stats(0).overruns, 0);
+
+        // Task 2 runs in the fourth tick, both tasks are within their deadline but not the tick
+        let result = scheduler.dispatch(&counter, |_| counter.advance(90));
+        assert_eq!(result, Ok(()));
+        let result = scheduler.dispatch(&counter, |_| counter.advance(90));
+        assert_eq!(result, Err(Overrun::Tick));
+        assert_eq!(scheduler.tick_stats().overruns, 2);
+        assert_eq!(scheduler.tick_stats().max, 450);
+    }
+}
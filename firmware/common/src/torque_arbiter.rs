@@ -0,0 +1,198 @@
+/// Limits on the final torque
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Limiter {
+    /// A monitor reaction that limits torque
+    Monitor,
+    /// Derating for the temperatures of the motor and the inverter
+    Thermal,
+    /// Power the battery delivers
+    Battery,
+    /// Top speed in the direction of the gear
+    Speed,
+}
+
+/// Sources of torque requests, in increasing priority
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub enum TorqueSource {
+    /// Pedal map and regen
+    Driver,
+    /// Zero torque for a monitor reaction
+    Monitor,
+}
+
+/// Final torque and how it came about
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Arbitration {
+    pub torque: i16,
+    /// Request the torque comes from, `None` without any request
+    pub source: Option<TorqueSource>,
+    /// Limit that cut the request, `None` while the request is passed unchanged
+    pub limiter: Option<Limiter>,
+}
+
+/// Combines torque requests and limits into the final torque request.
+///
+/// The request with the highest priority wins, between requests of the same priority the
+/// first one. The winning request is clamped to the tightest limits, limits only pull torque
+/// towards zero: a minimum above zero or a maximum below zero is taken as zero. The limiter
+/// that cut the request is reported, between equally tight limits the first one.
+///
+/// # Example
+/// ```
+/// use common::torque_arbiter::{Limiter, TorqueArbiter, TorqueSource};
+/// let mut arbiter = TorqueArbiter::new();
+/// arbiter.request(TorqueSource::Driver, 1500);
+/// arbiter.limit(Limiter::Speed, i16::MIN, 2000);
+/// arbiter.limit(Limiter::Battery, i16::MIN, 1200);
+///
+/// let arbitration = arbiter.arbitrate();
+/// assert_eq!(arbitration.torque, 1200);
+/// assert_eq!(arbitration.limiter, Some(Limiter::Battery));
+///
+/// arbiter.request(TorqueSource::Monitor, 0);
+/// assert_eq!(arbiter.arbitrate().torque, 0);
+/// assert_eq!(arbiter.arbitrate().source, Some(TorqueSource::Monitor));
+/// ```
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct TorqueArbiter {
+    request: Option<(TorqueSource, i16)>,
+    min: Option<(Limiter, i16)>,
+    max: Option<(Limiter, i16)>,
+}
+
+impl TorqueArbiter {
+    pub const fn new() -> Self {
+        Self {
+            request: None,
+            min: None,
+            max: None,
+        }
+    }
+
+    /// Request `torque` from `source`
+    pub fn request(&mut self, source: TorqueSource, torque: i16) {
+        if self.request.is_none_or(|(current, _)| source > current) {
+            self.request = Some((source, torque));
+        }
+    }
+
+    /// Limit torque to `min..=max`
+    pub fn limit(&mut self, limiter: Limiter, min: i16, max: i16) {
+        let (min, max) = (min.min(0), max.max(0));
+
+        if self.min.is_none_or(|(_, current)| min > current) {
+            self.min = Some((limiter, min));
+        }
+        if self.max.is_none_or(|(_, current)| max < current) {
+            self.max = Some((limiter, max));
+        }
+    }
+
+    /// The final torque, zero without any request
+    pub fn arbitrate(&self) -> Arbitration {
+        let Some((source, torque)) = self.request else {
+            return Arbitration {
+                torque: 0,
+                source: None,
+                limiter: None,
+            };
+        };
+
+        let (torque, limiter) = match (self.min, self.max) {
+            (_, Some((limiter, max))) if torque > max => (max, Some(limiter)),
+            (Some((limiter, min)), _) if torque < min => (min, Some(limiter)),
+            _ => (torque, None),
+        };
+
+        Arbitration {
+            torque,
+            source: Some(source),
+            limiter,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_request() {
+        let mut arbiter = TorqueArbiter::new();
+        arbiter.limit(Limiter::Speed, -100, 100);
+
+        assert_eq!(
+            arbiter.arbitrate(),
+            Arbitration {
+                torque: 0,
+                source: None,
+                limiter: None
+            }
+        );
+    }
+
+    #[test]
+    fn priority() {
+        let mut arbiter = TorqueArbiter::new();
+        arbiter.request(TorqueSource::Driver, 500);
+        assert_eq!(arbiter.arbitrate().torque, 500);
+
+        // The first of equal priority, the monitor over the driver in either order
+        arbiter.request(TorqueSource::Driver, 800);
+        assert_eq!(arbiter.arbitrate().torque, 500);
+        arbiter.request(TorqueSource::Monitor, 0);
+        arbiter.request(TorqueSource::Driver, 800);
+        assert_eq!(
+            arbiter.arbitrate(),
+            Arbitration {
+                torque: 0,
+                source: Some(TorqueSource::Monitor),
+                limiter: None
+            }
+        );
+    }
+
+    #[test]
+    fn limits() {
+        let mut arbiter = TorqueArbiter::new();
+        arbiter.request(TorqueSource::Driver, 1000);
+        arbiter.limit(Limiter::Speed, -2000, 2000);
+        assert_eq!(arbiter.arbitrate().limiter, None);
+
+        // The tightest limit wins and is reported, the first of equal ones
+        arbiter.limit(Limiter::Thermal, -600, 600);
+        arbiter.limit(Limiter::Battery, i16::MIN, 800);
+        arbiter.limit(Limiter::Monitor, -600, 600);
+        assert_eq!(
+            arbiter.arbitrate(),
+            Arbitration {
+                torque: 600,
+                source: Some(TorqueSource::Driver),
+                limiter: Some(Limiter::Thermal)
+            }
+        );
+
+        // Regen against the minimum
+        let mut arbiter = TorqueArbiter::new();
+        arbiter.request(TorqueSource::Driver, -700);
+        arbiter.limit(Limiter::Battery, -300, i16::MAX);
+        arbiter.limit(Limiter::Thermal, -600, 600);
+        assert_eq!(arbiter.arbitrate().torque, -300);
+        assert_eq!(arbiter.arbitrate().limiter, Some(Limiter::Battery));
+    }
+
+    #[test]
+    fn towards_zero() {
+        // Limits never ask for torque
+        let mut arbiter = TorqueArbiter::new();
+        arbiter.request(TorqueSource::Driver, 0);
+        arbiter.limit(Limiter::Speed, 200, 400);
+        arbiter.limit(Limiter::Battery, -400, -200);
+        assert_eq!(arbiter.arbitrate().torque, 0);
+        assert_eq!(arbiter.arbitrate().limiter, None);
+
+        arbiter.request(TorqueSource::Monitor, 300);
+        assert_eq!(arbiter.arbitrate().torque, 0);
+        assert_eq!(arbiter.arbitrate().limiter, Some(Limiter::Battery));
+    }
+}
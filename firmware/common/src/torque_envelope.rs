@@ -0,0 +1,176 @@
+use crate::curve::{lerp, segment};
+
+#[derive(Debug, PartialEq)]
+pub enum TorqueEnvelopeError {
+    AboveEnvelope,
+}
+
+/// Calibrated envelope of the maximum allowed drive torque as a function of
+/// throttle position (`0..=u16::MAX`) and absolute motor speed in rpm. The
+/// table is bilinearly interpolated between breakpoints and clamped outside
+/// of them. Torque is in the same unit as `EvCanFrame::TorqueRequest`.
+///
+/// Only positive (drive) torque is limited, negative (regenerative) torque is
+/// always inside the envelope.
+///
+/// # Example
+/// ```
+/// use common::torque_envelope::{TorqueEnvelope, TorqueEnvelopeError};
+/// let envelope = TorqueEnvelope::new(
+///     [0, u16::MAX],
+///     [0, 10000],
+///     [
+///         [100, 100],
+///         [1000, 500],
+///     ],
+///     50,
+/// );
+///
+/// // 50% throttle at standstill allows 550 + 50 in margin
+/// assert_eq!(envelope.max_torque(32768, 0), 550);
+/// assert_eq!(envelope.check(32768, 0, 600), Ok(()));
+/// assert_eq!(envelope.check(32768, 0, 601), Err(TorqueEnvelopeError::AboveEnvelope));
+/// ```
+pub struct TorqueEnvelope<const P: usize, const R: usize> {
+    position: [u16; P],
+    rpm: [u16; R],
+    max_torque: [[i16; R]; P],
+    margin: i16,
+}
+
+/// Torque envelope of the car, the monitor checks the torque request of main against it. The
+/// pedal map of main stays below it, see the tests of `pedal_map`.
+pub const TORQUE_ENVELOPE: TorqueEnvelope<4, 4> = TorqueEnvelope::new(
+    [0, 6554, 32768, 65535],
+    [0, 3000, 6000, 10000],
+    [
+        [100, 100, 50, 0],
+        [400, 350, 250, 150],
+        [1200, 1000, 700, 450],
+        [2000, 1800, 1200, 800],
+    ],
+    100,
+);
+
+impl<const P: usize, const R: usize> TorqueEnvelope<P, R> {
+    /// Create a new envelope.
+    ///
+    /// `position: [u16; P]` throttle position breakpoints, strictly increasing
+    ///
+    /// `rpm: [u16; R]` absolute motor speed breakpoints, strictly increasing
+    ///
+    /// `max_torque: [[i16; R]; P]` maximum torque for each position (rows) and rpm (columns)
+    ///
+    /// `margin: i16` extra torque allowed above the table before a request is rejected
+    pub const fn new(
+        position: [u16; P],
+        rpm: [u16; R],
+        max_torque: [[i16; R]; P],
+        margin: i16,
+    ) -> Self {
+        Self {
+            position,
+            rpm,
+            max_torque,
+            margin,
+        }
+    }
+
+    /// Get the interpolated maximum torque for a throttle position and motor speed,
+    /// not including the margin.
+    pub fn max_torque(&self, position: u16, rpm: i16) -> i16 {
+        if P == 0 || R == 0 {
+            return 0;
+        }
+
+        let (p, p_num, p_den) = segment(&self.position, position);
+        let (r, r_num, r_den) = segment(&self.rpm, rpm.unsigned_abs());
+
+        let p_next = (p + 1).min(P - 1);
+        let r_next = (r + 1).min(R - 1);
+
+        let low = lerp(
+            self.max_torque[p][r] as i32,
+            self.max_torque[p][r_next] as i32,
+            r_num,
+            r_den,
+        );
+        let high = lerp(
+            self.max_torque[p_next][r] as i32,
+            self.max_torque[p_next][r_next] as i32,
+            r_num,
+            r_den,
+        );
+
+        lerp(low, high, p_num, p_den) as i16
+    }
+
+    /// Check that a requested torque is within the envelope for the given throttle
+    /// position and motor speed.
+    pub fn check(&self, position: u16, rpm: i16, torque: i16) -> Result<(), TorqueEnvelopeError> {
+        let limit = self.max_torque(position, rpm).saturating_add(self.margin);
+
+        if torque > limit {
+            Err(TorqueEnvelopeError::AboveEnvelope)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn envelope() -> TorqueEnvelope<3, 3> {
+        TorqueEnvelope::new(
+            [0, 32768, 65535],
+            [0, 5000, 10000],
+            [
+                [50, 50, 0],
+                [600, 500, 300],
+                [1200, 1000, 600],
+            ],
+            20,
+        )
+    }
+
+    #[test]
+    fn breakpoints() {
+        let envelope = envelope();
+
+        assert_eq!(envelope.max_torque(0, 0), 50);
+        assert_eq!(envelope.max_torque(32768, 5000), 500);
+        assert_eq!(envelope.max_torque(65535, 10000), 600);
+    }
+
+    #[test]
+    fn interpolation() {
+        let envelope = envelope();
+
+        assert_eq!(envelope.max_torque(16384, 0), 325);
+        assert_eq!(envelope.max_torque(65535, 2500), 1100);
+        assert_eq!(envelope.max_torque(49152, 7500), 600);
+    }
+
+    #[test]
+    fn clamping() {
+        let envelope = envelope();
+
+        assert_eq!(envelope.max_torque(65535, 12000), 600);
+        assert_eq!(envelope.max_torque(32768, -5000), 500);
+        assert_eq!(envelope.max_torque(32768, i16::MIN), 300);
+    }
+
+    #[test]
+    fn check() {
+        let envelope = envelope();
+
+        assert_eq!(envelope.check(0, 0, 0), Ok(()));
+        assert_eq!(envelope.check(0, 0, 70), Ok(()));
+        assert_eq!(envelope.check(0, 0, 71), Err(TorqueEnvelopeError::AboveEnvelope));
+        assert_eq!(envelope.check(0, 0, -1000), Ok(()));
+        assert_eq!(envelope.check(65535, 0, 1220), Ok(()));
+        assert_eq!(envelope.check(65535, 10000, 1220), Err(TorqueEnvelopeError::AboveEnvelope));
+    }
+}
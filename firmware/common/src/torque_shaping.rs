@@ -0,0 +1,383 @@
+/// Torque shaping calibration, torque is in the units of `EvCanFrame::TorqueRequest` and rates
+/// are per cycle of the caller
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct ShapingConfig {
+    /// Largest increase of the torque magnitude per cycle
+    pub rise_rate: i16,
+    /// Largest decrease of the torque magnitude per cycle
+    pub fall_rate: i16,
+    /// Torque around zero that is crossed slowly to take up the backlash of the driveline
+    pub backlash_torque: i16,
+    /// Rate within `backlash_torque` of zero
+    pub backlash_rate: i16,
+    /// Low-pass filter of the motor speed, share of the difference taken every cycle in per
+    /// mille
+    pub speed_filter: u16,
+    /// Damping torque for 1000 rpm of motor speed above its filtered value
+    pub damping_gain: i16,
+    /// Largest damping torque in either direction
+    pub damping_limit: i16,
+}
+
+/// Calibration of the car. Tuned against the two-mass driveline in the tests, not yet on the
+/// car. Torque falls from full to the backlash band within the 5 `TorqueRequest` frames the
+/// monitor tolerates outside of its torque envelope after the pedal is released.
+pub const SHAPING: ShapingConfig = ShapingConfig {
+    // 1 s from zero to full torque
+    rise_rate: 20,
+    // 50 ms from full torque to the backlash band
+    fall_rate: 400,
+    backlash_torque: 50,
+    // 250 ms through the backlash from one side to the other
+    backlash_rate: 4,
+    speed_filter: 30,
+    damping_gain: 2000,
+    damping_limit: 200,
+};
+
+/// Shapes the torque request against driveline shunt and jerk.
+///
+/// The request is rate limited, with separate rates for increasing and decreasing torque.
+/// Around zero torque, within `backlash_torque`, the rate drops to `backlash_rate` so the
+/// gear backlash is taken up gently on every tip-in and change of direction. A step towards
+/// zero stops at the edge of that band first.
+///
+/// On top of the shaped request an active damping term works against oscillation of the
+/// motor speed. The oscillation is the motor speed above its low-pass filtered value, the
+/// damping term is limited to `damping_limit`. Damping only takes torque away, the result
+/// never exceeds the shaped request or reverses it, which keeps it inside the checks of the
+/// monitor wherever the request is.
+///
+/// # Example
+/// ```
+/// use common::torque_shaping::{ShapingConfig, TorqueShaper};
+/// let mut shaper = TorqueShaper::new(ShapingConfig {
+///     rise_rate: 100,
+///     fall_rate: 200,
+///     backlash_torque: 20,
+///     backlash_rate: 10,
+///     speed_filter: 100,
+///     damping_gain: 0,
+///     damping_limit: 0,
+/// });
+///
+/// assert_eq!(shaper.tick(500, 0), 10);
+/// assert_eq!(shaper.tick(500, 0), 20);
+/// assert_eq!(shaper.tick(500, 0), 120);
+/// assert_eq!(shaper.tick(0, 0), 20);
+/// ```
+pub struct TorqueShaper {
+    config: ShapingConfig,
+    /// Shaped request without damping
+    torque: i32,
+    /// Filtered motor speed in millirpm
+    filtered_speed: i64,
+}
+
+impl TorqueShaper {
+    pub fn new(config: ShapingConfig) -> Self {
+        Self {
+            config,
+            torque: 0,
+            filtered_speed: 0,
+        }
+    }
+
+    /// Drop to zero torque without shaping and restart the speed filter at `rpm`, for when no
+    /// torque is requested
+    pub fn reset(&mut self, rpm: i16) {
+        self.torque = 0;
+        self.filtered_speed = rpm as i64 * 1000;
+    }
+
+    /// Shape a torque request, called once every cycle with the motor speed reported by the
+    /// inverter. Returns the torque to request.
+    pub fn tick(&mut self, request: i16, rpm: i16) -> i16 {
+        self.torque = self.slew(request as i32);
+
+        let speed = rpm as i64 * 1000;
+        self.filtered_speed +=
+            (speed - self.filtered_speed) * self.config.speed_filter as i64 / 1000;
+
+        let oscillation = speed - self.filtered_speed;
+        let limit = self.config.damping_limit.max(0) as i64;
+        let damping =
+            (-oscillation * self.config.damping_gain as i64 / 1_000_000).clamp(-limit, limit);
+
+        // Damping only takes torque away, it neither exceeds the request nor reverses it
+        let torque = self.torque as i64;
+        (torque + damping).clamp(torque.min(0), torque.max(0)) as i16
+    }
+
+    fn slew(&self, target: i32) -> i32 {
+        let config = &self.config;
+        let torque = self.torque;
+        let band = config.backlash_torque.max(0) as i32;
+
+        let away =
+            target.abs() > torque.abs() && (torque == 0 || target.signum() == torque.signum());
+        let rate = if away {
+            config.rise_rate
+        } else {
+            config.fall_rate
+        }
+        .max(0) as i32;
+        let next = torque + (target - torque).clamp(-rate, rate);
+        let entering = next.abs() < band || next.signum() == -torque.signum();
+
+        if torque.abs() < band || (torque.abs() == band && entering) {
+            // Within the band, or at its edge and heading into it
+            let rate = config.backlash_rate.max(0) as i32;
+            torque + (target - torque).clamp(-rate, rate)
+        } else if entering {
+            // Stop at the edge of the band before crossing it
+            band * torque.signum()
+        } else {
+            next
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: ShapingConfig = ShapingConfig {
+        rise_rate: 40,
+        fall_rate: 80,
+        backlash_torque: 30,
+        backlash_rate: 5,
+        speed_filter: 100,
+        damping_gain: 0,
+        damping_limit: 0,
+    };
+
+    #[test]
+    fn rates() {
+        let mut shaper = TorqueShaper::new(CONFIG);
+
+        // Backlash band first, then the rise rate
+        let outputs: [i16; 9] = core::array::from_fn(|_| shaper.tick(200, 0));
+        assert_eq!(outputs, [5, 10, 15, 20, 25, 30, 70, 110, 150]);
+        assert_eq!(shaper.tick(200, 0), 190);
+        assert_eq!(shaper.tick(200, 0), 200);
+        assert_eq!(shaper.tick(200, 0), 200);
+
+        // Falling towards zero stops at the band, then enters it slowly
+        let outputs: [i16; 5] = core::array::from_fn(|_| shaper.tick(0, 0));
+        assert_eq!(outputs, [120, 40, 30, 25, 20]);
+
+        // A smaller request within the band edge
+        let mut shaper = TorqueShaper::new(CONFIG);
+        for _ in 0..20 {
+            shaper.tick(500, 0);
+        }
+        assert_eq!(shaper.tick(100, 0), 420);
+        for _ in 0..10 {
+            shaper.tick(100, 0);
+        }
+        assert_eq!(shaper.tick(100, 0), 100);
+    }
+
+    #[test]
+    fn release() {
+        let mut shaper = TorqueShaper::new(SHAPING);
+        for _ in 0..200 {
+            shaper.tick(2000, 0);
+        }
+
+        let outputs: [i16; 5] = core::array::from_fn(|_| shaper.tick(-300, 0));
+        assert_eq!(outputs, [1600, 1200, 800, 400, 50]);
+    }
+
+    #[test]
+    fn zero_crossing() {
+        let mut shaper = TorqueShaper::new(CONFIG);
+        for _ in 0..20 {
+            shaper.tick(300, 0);
+        }
+
+        let mut previous = shaper.tick(-300, 0);
+        let mut crossing = 0;
+        for _ in 0..50 {
+            let torque = shaper.tick(-300, 0);
+            if torque.abs() < 30 || previous.abs() < 30 {
+                assert!((torque - previous).abs() <= 5, "{previous} {torque}");
+                crossing += 1;
+            }
+            previous = torque;
+        }
+        assert_eq!(previous, -300);
+        // Through the band from +30 to -30 at the backlash rate
+        assert_eq!(crossing, 12);
+    }
+
+    #[test]
+    fn damping() {
+        let mut shaper = TorqueShaper::new(ShapingConfig {
+            rise_rate: 1000,
+            backlash_torque: 0,
+            damping_gain: 100,
+            damping_limit: 50,
+            ..CONFIG
+        });
+
+        // A constant speed is not damped
+        shaper.reset(3000);
+        for _ in 0..10 {
+            assert_eq!(shaper.tick(300, 3000), 300);
+        }
+
+        // A speed rising above the filtered speed is damped and limited
+        assert_eq!(shaper.tick(300, 3200), 282);
+        assert_eq!(shaper.tick(300, 3400), 266);
+        assert_eq!(shaper.tick(300, 4000), 250);
+        // Damping never adds torque
+        assert_eq!(shaper.tick(300, 2000), 300);
+    }
+
+    /// Two-mass driveline seen from the motor: the motor inertia and the inertia of the
+    /// vehicle connected by a torsional stiffness with damping and backlash. One unit of the
+    /// torque request is 0.25 Nm.
+    struct Driveline {
+        motor_speed: f32,
+        vehicle_speed: f32,
+        /// Twist of the driveline in rad
+        twist: f32,
+        /// Largest speed of the motor relative to the vehicle when the backlash closed, in rpm
+        impact: f32,
+    }
+
+    impl Driveline {
+        const MOTOR_INERTIA: f32 = 0.05;
+        /// 1500 kg at 0.3 m wheel radius through an 8:1 reduction
+        const VEHICLE_INERTIA: f32 = 2.1;
+        /// Stiffness and damping for a 4 Hz oscillation with a 5 % damping ratio
+        const STIFFNESS: f32 = 31.0;
+        const DAMPING: f32 = 0.12;
+        /// Half the backlash in rad
+        const BACKLASH: f32 = 0.05;
+        const NM_PER_UNIT: f32 = 0.25;
+        const RPM_PER_RAD_S: f32 = 60.0 / (2.0 * core::f32::consts::PI);
+
+        fn new() -> Self {
+            Self {
+                motor_speed: 0.0,
+                vehicle_speed: 0.0,
+                twist: 0.0,
+                impact: 0.0,
+            }
+        }
+
+        fn shaft_torque(&self) -> f32 {
+            let windup = if self.twist > Self::BACKLASH {
+                self.twist - Self::BACKLASH
+            } else if self.twist < -Self::BACKLASH {
+                self.twist + Self::BACKLASH
+            } else {
+                return 0.0;
+            };
+
+            Self::STIFFNESS * windup + Self::DAMPING * (self.motor_speed - self.vehicle_speed)
+        }
+
+        /// Apply a torque request for one 10 ms cycle, returns the motor speed in rpm
+        fn step(&mut self, torque: i16) -> i16 {
+            const STEPS: usize = 100;
+            let dt = 0.01 / STEPS as f32;
+            let motor_torque = torque as f32 * Self::NM_PER_UNIT;
+
+            for _ in 0..STEPS {
+                let shaft = self.shaft_torque();
+                let free = self.twist.abs() <= Self::BACKLASH;
+                self.motor_speed += (motor_torque - shaft) / Self::MOTOR_INERTIA * dt;
+                self.vehicle_speed += shaft / Self::VEHICLE_INERTIA * dt;
+                self.twist += (self.motor_speed - self.vehicle_speed) * dt;
+                if free && self.twist.abs() > Self::BACKLASH {
+                    self.impact = self.impact.max(self.oscillation().abs());
+                }
+            }
+
+            (self.motor_speed * Self::RPM_PER_RAD_S) as i16
+        }
+
+        /// Speed of the motor relative to the vehicle in rpm
+        fn oscillation(&self) -> f32 {
+            (self.motor_speed - self.vehicle_speed) * Self::RPM_PER_RAD_S
+        }
+    }
+
+    /// Tip-in from zero to `request` and back out to `-request`, returns the RMS of the
+    /// oscillation and the largest impact when the backlash closes, both in rpm
+    fn tip_in(config: Option<ShapingConfig>, request: i16) -> (f32, f32) {
+        const CYCLES: usize = 600;
+        let mut driveline = Driveline::new();
+        let mut shaper = config.map(TorqueShaper::new);
+        let mut rpm = 0;
+        let mut squares = 0.0;
+
+        for cycle in 0..CYCLES {
+            let request = if cycle < CYCLES / 2 {
+                request
+            } else {
+                -request
+            };
+            let torque = match &mut shaper {
+                Some(shaper) => shaper.tick(request, rpm),
+                None => request,
+            };
+
+            rpm = driveline.step(torque);
+            squares += driveline.oscillation().powi(2);
+        }
+
+        ((squares / CYCLES as f32).sqrt(), driveline.impact)
+    }
+
+    #[test]
+    fn two_mass_driveline() {
+        let (raw, raw_impact) = tip_in(None, 400);
+        let undamped = ShapingConfig {
+            damping_gain: 0,
+            ..SHAPING
+        };
+        let (shaped, shaped_impact) = tip_in(Some(undamped), 400);
+        let (damped, damped_impact) = tip_in(Some(SHAPING), 400);
+
+        // Rate limiting and the backlash band soften the impacts and the oscillation, damping
+        // takes out most of what is left
+        assert!(shaped < raw / 2.0, "{raw} {shaped}");
+        assert!(damped < shaped / 2.0, "{shaped} {damped}");
+        assert!(
+            shaped_impact < raw_impact * 0.6,
+            "{raw_impact} {shaped_impact}"
+        );
+        assert!(
+            damped_impact < shaped_impact * 0.6,
+            "{shaped_impact} {damped_impact}"
+        );
+    }
+
+    #[test]
+    fn damping_takes_torque_away() {
+        let mut shaper = TorqueShaper::new(SHAPING);
+        let mut driveline = Driveline::new();
+        let mut rpm = 0;
+
+        for cycle in 0..600 {
+            let request = match cycle {
+                0..200 => 400,
+                200..400 => -400,
+                _ => 0,
+            };
+            let torque = shaper.tick(request, rpm);
+            let shaped = shaper.torque;
+
+            assert!(
+                (shaped.min(0)..=shaped.max(0)).contains(&(torque as i32)),
+                "{cycle}"
+            );
+            rpm = driveline.step(torque);
+        }
+    }
+}
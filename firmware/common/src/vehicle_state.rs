@@ -0,0 +1,702 @@
+use crate::{
+    dtc::Dtc,
+    gear::Gear,
+    monitor_message::{MonitorError, MonitorState, Reaction},
+    precharge::PrechargeError,
+    timeout::Timeout,
+};
+
+/// Operating state of the vehicle
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum VehicleState {
+    /// Ignition off, nothing is powered but the controller itself
+    Off,
+    /// Ignition on, waiting for the monitor, the inverter and a crank request
+    Wake,
+    /// Closing the contactors through the precharge resistor
+    Precharge,
+    /// HV connected without torque
+    Ready,
+    /// Torque is requested from the inverter
+    Drive,
+    /// A fault stopped the vehicle, HV is disconnected until the next key cycle
+    Fault,
+    /// Opening the contactors before powering down
+    Shutdown,
+}
+
+impl VehicleState {
+    /// HV is connected or being connected
+    pub fn hv(&self) -> bool {
+        matches!(
+            self,
+            VehicleState::Precharge | VehicleState::Ready | VehicleState::Drive
+        )
+    }
+
+    /// Torque may be requested from the inverter
+    pub fn torque(&self) -> bool {
+        *self == VehicleState::Drive
+    }
+
+    /// The controller keeps its own supply on through POWER_KEEPALIVE
+    pub fn keepalive(&self) -> bool {
+        *self != VehicleState::Off
+    }
+}
+
+/// Inverter as seen from its frames on EV CAN
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum InverterState {
+    /// No recent `InverterStatus` frame
+    Silent,
+    Ok,
+    /// The inverter reports a non-zero error code
+    Error(u8),
+}
+
+/// Progress of the precharge sequence while in `VehicleState::Precharge`
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum PrechargeStatus {
+    Pending,
+    Complete,
+    Failed(PrechargeError),
+}
+
+/// Everything the state machine is driven by, sampled once every cycle
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct VehicleInputs {
+    pub ignition: bool,
+    /// The key is held in the start position
+    pub crank: bool,
+    pub gear: Gear,
+    pub inverter: InverterState,
+    /// Latest state from the monitor, `None` while the serial link is down
+    pub monitor: Option<MonitorState>,
+    pub precharge: PrechargeStatus,
+    /// An active DTC, it keeps HV from being connected
+    pub dtc: Option<Dtc>,
+}
+
+/// Why the state machine changed state
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum TransitionReason {
+    IgnitionOn,
+    IgnitionOff,
+    Crank,
+    PrechargeComplete,
+    PrechargeFailed(PrechargeError),
+    /// Crank with an active DTC
+    Dtc(Dtc),
+    /// Drive or reverse selected
+    GearEngaged(Gear),
+    /// Park or neutral selected
+    GearReleased,
+    /// The monitor requires at least zero torque
+    MonitorFault(MonitorError),
+    /// The monitor is not operational while HV is connected
+    MonitorLost,
+    InverterError(u8),
+    /// No inverter status while HV is connected
+    InverterLost,
+    ShutdownComplete,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Transition {
+    pub from: VehicleState,
+    pub to: VehicleState,
+    pub reason: TransitionReason,
+}
+
+/// Vehicle lifecycle from key on to power down:
+///
+/// - `Off` → `Wake` when the ignition is turned on.
+/// - `Wake` → `Precharge` on crank in park or neutral, once the monitor is operational and the
+///   inverter reports without errors. Turning the ignition off goes straight back to `Off`.
+///   Crank with an active DTC is a fault instead.
+/// - `Precharge` → `Ready` when the precharge sequence is complete.
+/// - `Ready` ⇄ `Drive` when drive or reverse is engaged or released.
+/// - Any state from `Wake` to `Drive` → `Fault` when the monitor requires zero torque or
+///   more, the inverter reports an error or the contactors fail. While HV is connected, losing the
+///   monitor or the inverter is a fault as well. Faults are checked before anything else.
+/// - `Precharge`, `Ready`, `Drive` and `Fault` → `Shutdown` when the ignition is turned off,
+///   `Shutdown` → `Off` once the contactors have had time to open.
+///
+/// # Example
+///
+/// ```
+/// use common::{
+///     gear::Gear,
+///     monitor_message::MonitorState,
+///     vehicle_state::{
+///         InverterState, PrechargeStatus, TransitionReason, VehicleInputs, VehicleState,
+///         VehicleStateMachine,
+///     },
+/// };
+///
+/// let mut vehicle = VehicleStateMachine::new(50);
+/// let mut inputs = VehicleInputs {
+///     ignition: true,
+///     crank: false,
+///     gear: Gear::Park,
+///     inverter: InverterState::Ok,
+///     monitor: Some(MonitorState::Operational),
+///     precharge: PrechargeStatus::Pending,
+///     dtc: None,
+/// };
+///
+/// vehicle.tick(&inputs);
+/// assert_eq!(vehicle.state(), VehicleState::Wake);
+///
+/// inputs.crank = true;
+/// let transition = vehicle.tick(&inputs).unwrap();
+/// assert_eq!(transition.to, VehicleState::Precharge);
+/// assert_eq!(transition.reason, TransitionReason::Crank);
+/// ```
+pub struct VehicleStateMachine {
+    state: VehicleState,
+    shutdown: Timeout,
+    last_transition: Option<Transition>,
+}
+
+impl VehicleStateMachine {
+    /// `shutdown_time` is the number of cycles spent in `Shutdown`
+    pub fn new(shutdown_time: usize) -> Self {
+        Self {
+            state: VehicleState::Off,
+            shutdown: Timeout::new(shutdown_time),
+            last_transition: None,
+        }
+    }
+
+    pub fn state(&self) -> VehicleState {
+        self.state
+    }
+
+    /// The latest transition, kept for diagnostics
+    pub fn last_transition(&self) -> Option<Transition> {
+        self.last_transition
+    }
+
+    /// Advance the state machine, called once every cycle. Returns the transition taken, if
+    /// any, at most one transition is taken per cycle.
+    pub fn tick(&mut self, inputs: &VehicleInputs) -> Option<Transition> {
+        let (to, reason) = self.next(inputs)?;
+
+        let transition = Transition {
+            from: self.state,
+            to,
+            reason,
+        };
+        if to == VehicleState::Shutdown {
+            self.shutdown.reset();
+        }
+        self.state = to;
+        self.last_transition = Some(transition);
+
+        Some(transition)
+    }
+
+    fn next(&mut self, inputs: &VehicleInputs) -> Option<(VehicleState, TransitionReason)> {
+        match self.state {
+            VehicleState::Off => inputs
+                .ignition
+                .then_some((VehicleState::Wake, TransitionReason::IgnitionOn)),
+            VehicleState::Fault => (!inputs.ignition)
+                .then_some((VehicleState::Shutdown, TransitionReason::IgnitionOff)),
+            VehicleState::Shutdown => self
+                .shutdown
+                .tick()
+                .is_err()
+                .then_some((VehicleState::Off, TransitionReason::ShutdownComplete)),
+            VehicleState::Wake
+            | VehicleState::Precharge
+            | VehicleState::Ready
+            | VehicleState::Drive => {
+                if let Some(reason) = self.fault(inputs) {
+                    return Some((VehicleState::Fault, reason));
+                }
+
+                if !inputs.ignition {
+                    let to = match self.state {
+                        VehicleState::Wake => VehicleState::Off,
+                        _ => VehicleState::Shutdown,
+                    };
+                    return Some((to, TransitionReason::IgnitionOff));
+                }
+
+                self.operate(inputs)
+            }
+        }
+    }
+
+    /// Fault in the states from `Wake` to `Drive`
+    fn fault(&self, inputs: &VehicleInputs) -> Option<TransitionReason> {
+        match inputs.monitor {
+            Some(MonitorState::Fault(error, reaction)) if reaction >= Reaction::ZeroTorque => {
+                return Some(TransitionReason::MonitorFault(error));
+            }
+            Some(MonitorState::Operational | MonitorState::Fault(..)) => {}
+            Some(MonitorState::Uncalibrated) | None if self.state.hv() => {
+                return Some(TransitionReason::MonitorLost);
+            }
+            Some(MonitorState::Uncalibrated) | None => {}
+        }
+
+        match inputs.inverter {
+            InverterState::Error(code) => return Some(TransitionReason::InverterError(code)),
+            InverterState::Silent if self.state.hv() => {
+                return Some(TransitionReason::InverterLost)
+            }
+            InverterState::Silent | InverterState::Ok => {}
+        }
+
+        match (self.state, inputs.precharge, inputs.dtc) {
+            (state, PrechargeStatus::Failed(error), _) if state.hv() => {
+                Some(TransitionReason::PrechargeFailed(error))
+            }
+            (VehicleState::Wake, _, Some(dtc)) if inputs.crank => Some(TransitionReason::Dtc(dtc)),
+            _ => None,
+        }
+    }
+
+    /// Normal operation in the states from `Wake` to `Drive` with the ignition on
+    fn operate(&self, inputs: &VehicleInputs) -> Option<(VehicleState, TransitionReason)> {
+        let engaged = inputs.gear.direction() != 0;
+
+        match self.state {
+            VehicleState::Wake => {
+                let monitor = matches!(
+                    inputs.monitor,
+                    Some(MonitorState::Operational | MonitorState::Fault(..))
+                );
+                let start =
+                    inputs.crank && !engaged && monitor && inputs.inverter == InverterState::Ok;
+
+                start.then_some((VehicleState::Precharge, TransitionReason::Crank))
+            }
+            VehicleState::Precharge => (inputs.precharge == PrechargeStatus::Complete)
+                .then_some((VehicleState::Ready, TransitionReason::PrechargeComplete)),
+            VehicleState::Ready => engaged.then_some((
+                VehicleState::Drive,
+                TransitionReason::GearEngaged(inputs.gear),
+            )),
+            VehicleState::Drive => {
+                (!engaged).then_some((VehicleState::Ready, TransitionReason::GearReleased))
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STATES: [VehicleState; 7] = [
+        VehicleState::Off,
+        VehicleState::Wake,
+        VehicleState::Precharge,
+        VehicleState::Ready,
+        VehicleState::Drive,
+        VehicleState::Fault,
+        VehicleState::Shutdown,
+    ];
+
+    const SHUTDOWN_TIME: usize = 3;
+
+    fn inputs() -> VehicleInputs {
+        VehicleInputs {
+            ignition: true,
+            crank: false,
+            gear: Gear::Park,
+            inverter: InverterState::Ok,
+            monitor: Some(MonitorState::Operational),
+            precharge: PrechargeStatus::Pending,
+            dtc: None,
+        }
+    }
+
+    fn machine(state: VehicleState) -> VehicleStateMachine {
+        let mut machine = VehicleStateMachine::new(SHUTDOWN_TIME);
+        machine.state = state;
+        machine
+    }
+
+    /// Tick and return the new state and the reason of the transition
+    fn step(
+        machine: &mut VehicleStateMachine,
+        inputs: &VehicleInputs,
+    ) -> (VehicleState, Option<TransitionReason>) {
+        let reason = machine.tick(inputs).map(|transition| transition.reason);
+        (machine.state(), reason)
+    }
+
+    /// Every combination of inputs with a few representative values for each of them
+    fn all_inputs() -> impl Iterator<Item = VehicleInputs> {
+        let monitors = [
+            None,
+            Some(MonitorState::Uncalibrated),
+            Some(MonitorState::Operational),
+            Some(MonitorState::Fault(
+                MonitorError::EvCanBusOffError,
+                Reaction::Warn,
+            )),
+            Some(MonitorState::Fault(
+                MonitorError::InverterTemperatureError,
+                Reaction::TorqueLimited,
+            )),
+            Some(MonitorState::Fault(
+                MonitorError::DirectionError,
+                Reaction::ZeroTorque,
+            )),
+            Some(MonitorState::Fault(
+                MonitorError::PingError,
+                Reaction::DisconnectHv,
+            )),
+        ];
+        let inverters = [
+            InverterState::Silent,
+            InverterState::Ok,
+            InverterState::Error(3),
+        ];
+        let gears = [Gear::Park, Gear::Reverse, Gear::Neutral, Gear::Drive];
+        let precharges = [
+            PrechargeStatus::Pending,
+            PrechargeStatus::Complete,
+            PrechargeStatus::Failed(PrechargeError::TooSlow(0)),
+        ];
+
+        [false, true].into_iter().flat_map(move |ignition| {
+            [false, true].into_iter().flat_map(move |crank| {
+                gears.into_iter().flat_map(move |gear| {
+                    inverters.into_iter().flat_map(move |inverter| {
+                        monitors.into_iter().flat_map(move |monitor| {
+                            precharges.into_iter().flat_map(move |precharge| {
+                                [None, Some(Dtc::ContactorsWelded)]
+                                    .into_iter()
+                                    .map(move |dtc| VehicleInputs {
+                                        ignition,
+                                        crank,
+                                        gear,
+                                        inverter,
+                                        monitor,
+                                        precharge,
+                                        dtc,
+                                    })
+                            })
+                        })
+                    })
+                })
+            })
+        })
+    }
+
+    fn allowed(from: VehicleState, to: VehicleState) -> bool {
+        use VehicleState::*;
+
+        matches!(
+            (from, to),
+            (Off, Wake)
+                | (Wake, Off | Precharge | Fault)
+                | (Precharge, Ready | Fault | Shutdown)
+                | (Ready, Drive | Fault | Shutdown)
+                | (Drive, Ready | Fault | Shutdown)
+                | (Fault, Shutdown)
+                | (Shutdown, Off)
+        )
+    }
+
+    fn monitor_stop(inputs: &VehicleInputs) -> bool {
+        matches!(
+            inputs.monitor,
+            Some(MonitorState::Fault(_, reaction)) if reaction >= Reaction::ZeroTorque
+        )
+    }
+
+    #[test]
+    fn exhaustive() {
+        for from in STATES {
+            for inputs in all_inputs() {
+                let mut machine = machine(from);
+                let transition = machine.tick(&inputs);
+                let to = machine.state();
+
+                match transition {
+                    Some(transition) => {
+                        assert_eq!((transition.from, transition.to), (from, to));
+                        assert!(allowed(from, to), "{from:?} -> {to:?} with {inputs:?}");
+                        assert_eq!(machine.last_transition(), Some(transition));
+                    }
+                    None => {
+                        assert_eq!(to, from);
+                        assert_eq!(machine.last_transition(), None);
+                    }
+                }
+
+                // Faults stop the vehicle from wake to drive, whatever else happens
+                let stop =
+                    monitor_stop(&inputs) || matches!(inputs.inverter, InverterState::Error(_));
+                if stop && (from == VehicleState::Wake || from.hv()) {
+                    assert_eq!(to, VehicleState::Fault, "{from:?} with {inputs:?}");
+                }
+
+                // HV is only kept with a working monitor and inverter
+                if to.hv() {
+                    assert!(inputs.ignition);
+                    assert_eq!(inputs.inverter, InverterState::Ok);
+                    assert!(matches!(
+                        inputs.monitor,
+                        Some(MonitorState::Operational | MonitorState::Fault(..))
+                    ));
+                    assert!(!monitor_stop(&inputs));
+                }
+                if to.torque() {
+                    assert_ne!(inputs.gear.direction(), 0);
+                }
+                if to.hv() && !from.hv() {
+                    assert!(inputs.crank);
+                    assert_eq!(inputs.gear.direction(), 0);
+                    assert_eq!(inputs.dtc, None);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn drive_cycle() {
+        let mut machine = VehicleStateMachine::new(SHUTDOWN_TIME);
+        let mut inputs = inputs();
+
+        inputs.ignition = false;
+        assert_eq!(step(&mut machine, &inputs), (VehicleState::Off, None));
+        inputs.ignition = true;
+        assert_eq!(
+            step(&mut machine, &inputs),
+            (VehicleState::Wake, Some(TransitionReason::IgnitionOn))
+        );
+        inputs.crank = true;
+        assert_eq!(
+            step(&mut machine, &inputs),
+            (VehicleState::Precharge, Some(TransitionReason::Crank))
+        );
+        inputs.crank = false;
+        assert_eq!(step(&mut machine, &inputs), (VehicleState::Precharge, None));
+        inputs.precharge = PrechargeStatus::Complete;
+        assert_eq!(
+            step(&mut machine, &inputs),
+            (
+                VehicleState::Ready,
+                Some(TransitionReason::PrechargeComplete)
+            )
+        );
+        inputs.gear = Gear::Drive;
+        assert_eq!(
+            step(&mut machine, &inputs),
+            (
+                VehicleState::Drive,
+                Some(TransitionReason::GearEngaged(Gear::Drive))
+            )
+        );
+        // Changing direction stays in drive
+        inputs.gear = Gear::Reverse;
+        assert_eq!(step(&mut machine, &inputs), (VehicleState::Drive, None));
+        inputs.gear = Gear::Park;
+        assert_eq!(
+            step(&mut machine, &inputs),
+            (VehicleState::Ready, Some(TransitionReason::GearReleased))
+        );
+        inputs.ignition = false;
+        assert_eq!(
+            step(&mut machine, &inputs),
+            (VehicleState::Shutdown, Some(TransitionReason::IgnitionOff))
+        );
+    }
+
+    #[test]
+    fn start_conditions() {
+        let mut crank = inputs();
+        crank.crank = true;
+
+        // Monitor not calibrated yet, inverter not reporting, a gear engaged
+        let mut inputs = crank;
+        inputs.monitor = Some(MonitorState::Uncalibrated);
+        assert_eq!(step(&mut machine(VehicleState::Wake), &inputs).1, None);
+        let mut inputs = crank;
+        inputs.monitor = None;
+        assert_eq!(step(&mut machine(VehicleState::Wake), &inputs).1, None);
+        let mut inputs = crank;
+        inputs.inverter = InverterState::Silent;
+        assert_eq!(step(&mut machine(VehicleState::Wake), &inputs).1, None);
+        let mut inputs = crank;
+        inputs.gear = Gear::Drive;
+        assert_eq!(step(&mut machine(VehicleState::Wake), &inputs).1, None);
+
+        // A DTC is only a fault once the driver tries to start
+        let mut inputs = crank;
+        inputs.crank = false;
+        inputs.dtc = Some(Dtc::ContactorStuckOpen);
+        assert_eq!(step(&mut machine(VehicleState::Wake), &inputs).1, None);
+
+        // A monitor fault that still allows torque doesn't prevent a start
+        let mut inputs = crank;
+        inputs.monitor = Some(MonitorState::Fault(
+            MonitorError::MotorTemperatureError,
+            Reaction::TorqueLimited,
+        ));
+        inputs.gear = Gear::Neutral;
+        assert_eq!(
+            step(&mut machine(VehicleState::Wake), &inputs),
+            (VehicleState::Precharge, Some(TransitionReason::Crank))
+        );
+
+        // Turning the ignition off before cranking powers down directly
+        let mut inputs = crank;
+        inputs.ignition = false;
+        assert_eq!(
+            step(&mut machine(VehicleState::Wake), &inputs),
+            (VehicleState::Off, Some(TransitionReason::IgnitionOff))
+        );
+    }
+
+    #[test]
+    fn fault_reasons() {
+        let cases = [
+            (
+                VehicleState::Drive,
+                VehicleInputs {
+                    monitor: Some(MonitorState::Fault(
+                        MonitorError::OverspeedError,
+                        Reaction::ZeroTorque,
+                    )),
+                    ..inputs()
+                },
+                TransitionReason::MonitorFault(MonitorError::OverspeedError),
+            ),
+            (
+                VehicleState::Ready,
+                VehicleInputs {
+                    monitor: None,
+                    ..inputs()
+                },
+                TransitionReason::MonitorLost,
+            ),
+            (
+                VehicleState::Drive,
+                VehicleInputs {
+                    monitor: Some(MonitorState::Uncalibrated),
+                    ..inputs()
+                },
+                TransitionReason::MonitorLost,
+            ),
+            (
+                VehicleState::Wake,
+                VehicleInputs {
+                    inverter: InverterState::Error(7),
+                    ..inputs()
+                },
+                TransitionReason::InverterError(7),
+            ),
+            (
+                VehicleState::Precharge,
+                VehicleInputs {
+                    inverter: InverterState::Silent,
+                    ..inputs()
+                },
+                TransitionReason::InverterLost,
+            ),
+            (
+                VehicleState::Precharge,
+                VehicleInputs {
+                    precharge: PrechargeStatus::Failed(PrechargeError::TooFast(2)),
+                    ..inputs()
+                },
+                TransitionReason::PrechargeFailed(PrechargeError::TooFast(2)),
+            ),
+            (
+                VehicleState::Drive,
+                VehicleInputs {
+                    precharge: PrechargeStatus::Failed(PrechargeError::Dropout(0)),
+                    ..inputs()
+                },
+                TransitionReason::PrechargeFailed(PrechargeError::Dropout(0)),
+            ),
+            (
+                VehicleState::Wake,
+                VehicleInputs {
+                    crank: true,
+                    dtc: Some(Dtc::LowerContactorWelded),
+                    ..inputs()
+                },
+                TransitionReason::Dtc(Dtc::LowerContactorWelded),
+            ),
+            // The fault is reported even if the ignition is turned off at the same time
+            (
+                VehicleState::Drive,
+                VehicleInputs {
+                    ignition: false,
+                    inverter: InverterState::Error(1),
+                    ..inputs()
+                },
+                TransitionReason::InverterError(1),
+            ),
+        ];
+
+        for (from, inputs, reason) in cases {
+            assert_eq!(
+                step(&mut machine(from), &inputs),
+                (VehicleState::Fault, Some(reason)),
+                "{from:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn fault_until_key_cycle() {
+        let mut machine = machine(VehicleState::Fault);
+        let mut inputs = inputs();
+        inputs.crank = true;
+
+        // Everything is fine again, the fault stays
+        for _ in 0..10 {
+            assert_eq!(step(&mut machine, &inputs), (VehicleState::Fault, None));
+        }
+
+        inputs.ignition = false;
+        assert_eq!(
+            step(&mut machine, &inputs),
+            (VehicleState::Shutdown, Some(TransitionReason::IgnitionOff))
+        );
+    }
+
+    #[test]
+    fn shutdown_time() {
+        let mut machine = machine(VehicleState::Ready);
+        let mut inputs = inputs();
+
+        inputs.ignition = false;
+        machine.tick(&inputs);
+        // The ignition coming back on doesn't cut the shutdown short
+        inputs.ignition = true;
+        for _ in 0..SHUTDOWN_TIME {
+            assert_eq!(step(&mut machine, &inputs), (VehicleState::Shutdown, None));
+        }
+        assert_eq!(
+            step(&mut machine, &inputs),
+            (VehicleState::Off, Some(TransitionReason::ShutdownComplete))
+        );
+        assert_eq!(
+            step(&mut machine, &inputs),
+            (VehicleState::Wake, Some(TransitionReason::IgnitionOn))
+        );
+        assert_eq!(
+            machine.last_transition(),
+            Some(Transition {
+                from: VehicleState::Off,
+                to: VehicleState::Wake,
+                reason: TransitionReason::IgnitionOn,
+            })
+        );
+    }
+}
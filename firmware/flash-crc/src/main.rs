@@ -0,0 +1,220 @@
+//! Patches the CRC of the application image into the `.flash_crc` record of a linked ELF
+//! file. The record is placed by the linker script and holds the flash address range of
+//! the image followed by the CRC.
+//!
+//! ```bash
+//! cargo run -- ../monitor-app/target/thumbv6m-none-eabi/release/monitor-app
+//! ```
+
+use std::{env, fs, process::ExitCode};
+
+use common::crc32::{calc_crc32, generate_lookup};
+
+const CRC32_LOOKUP: [u32; 256] = generate_lookup(0x04c1_1db7);
+
+const PT_LOAD: u32 = 1;
+/// Value of erased flash, used for gaps between the loaded segments
+const ERASED: u8 = 0xff;
+
+#[derive(Debug, PartialEq)]
+enum PatchError {
+    NotElf32,
+    Truncated,
+    NoRecord,
+    BadRecord,
+}
+
+fn main() -> ExitCode {
+    let Some(path) = env::args().nth(1) else {
+        eprintln!("usage: flash-crc <elf>");
+        return ExitCode::FAILURE;
+    };
+
+    let mut elf = match fs::read(&path) {
+        Ok(elf) => elf,
+        Err(err) => {
+            eprintln!("{path}: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match patch(&mut elf).map(|crc| (crc, fs::write(&path, &elf))) {
+        Ok((crc, Ok(()))) => {
+            println!("{path}: flash CRC {crc:#010x}");
+            ExitCode::SUCCESS
+        }
+        Ok((_, Err(err))) => {
+            eprintln!("{path}: {err}");
+            ExitCode::FAILURE
+        }
+        Err(err) => {
+            eprintln!("{path}: {err:?}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Calculate the CRC of the image described by the `.flash_crc` record and write it into
+/// the record
+fn patch(elf: &mut [u8]) -> Result<u32, PatchError> {
+    if elf.get(..5) != Some(b"\x7fELF\x01") {
+        return Err(PatchError::NotElf32);
+    }
+
+    let record = section_offset(elf, ".flash_crc")?.ok_or(PatchError::NoRecord)?;
+    let start = read_u32(elf, record)?;
+    let end = read_u32(elf, record + 4)?;
+    if end < start {
+        return Err(PatchError::BadRecord);
+    }
+
+    let image = load_image(elf, start, end)?;
+    let crc = calc_crc32(&image, &CRC32_LOOKUP);
+
+    elf[record + 8..record + 12].copy_from_slice(&crc.to_le_bytes());
+
+    Ok(crc)
+}
+
+/// Assemble the contents of flash between `start` and `end` from the loadable segments,
+/// using the physical (load) addresses.
+fn load_image(elf: &[u8], start: u32, end: u32) -> Result<Vec<u8>, PatchError> {
+    let mut image = vec![ERASED; (end - start) as usize];
+
+    let phoff = read_u32(elf, 0x1c)? as usize;
+    let phentsize = read_u16(elf, 0x2a)? as usize;
+    let phnum = read_u16(elf, 0x2c)? as usize;
+
+    for i in 0..phnum {
+        let header = phoff + i * phentsize;
+        if read_u32(elf, header)? != PT_LOAD {
+            continue;
+        }
+
+        let offset = read_u32(elf, header + 4)? as usize;
+        let paddr = read_u32(elf, header + 12)?;
+        let filesz = read_u32(elf, header + 16)?;
+
+        let from = paddr.max(start);
+        let to = paddr.saturating_add(filesz).min(end);
+        if from >= to {
+            continue;
+        }
+
+        let data = elf
+            .get(offset + (from - paddr) as usize..offset + (to - paddr) as usize)
+            .ok_or(PatchError::Truncated)?;
+        image[(from - start) as usize..(to - start) as usize].copy_from_slice(data);
+    }
+
+    Ok(image)
+}
+
+/// File offset of the section with the given name
+fn section_offset(elf: &[u8], name: &str) -> Result<Option<usize>, PatchError> {
+    let shoff = read_u32(elf, 0x20)? as usize;
+    let shentsize = read_u16(elf, 0x2e)? as usize;
+    let shnum = read_u16(elf, 0x30)? as usize;
+    let shstrndx = read_u16(elf, 0x32)? as usize;
+
+    let names = read_u32(elf, shoff + shstrndx * shentsize + 16)? as usize;
+
+    for i in 0..shnum {
+        let header = shoff + i * shentsize;
+        let start = names + read_u32(elf, header)? as usize;
+        let section_name = elf
+            .get(start..)
+            .and_then(|bytes| bytes.split(|&b| b == 0).next())
+            .ok_or(PatchError::Truncated)?;
+
+        if section_name == name.as_bytes() {
+            return Ok(Some(read_u32(elf, header + 16)? as usize));
+        }
+    }
+
+    Ok(None)
+}
+
+fn read_u32(elf: &[u8], offset: usize) -> Result<u32, PatchError> {
+    let bytes = elf.get(offset..offset + 4).ok_or(PatchError::Truncated)?;
+    Ok(u32::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+fn read_u16(elf: &[u8], offset: usize) -> Result<u16, PatchError> {
+    let bytes = elf.get(offset..offset + 2).ok_or(PatchError::Truncated)?;
+    Ok(u16::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Minimal ELF with two loadable segments, a gap between them and a `.flash_crc`
+    /// section describing the range `0x0800_0000..0x0800_000c`
+    fn elf() -> Vec<u8> {
+        let mut elf = vec![0u8; 0x200];
+        elf[..5].copy_from_slice(b"\x7fELF\x01");
+
+        // Program headers at 0x40
+        elf[0x1c..0x20].copy_from_slice(&0x40u32.to_le_bytes());
+        elf[0x2a..0x2c].copy_from_slice(&32u16.to_le_bytes());
+        elf[0x2c..0x2e].copy_from_slice(&2u16.to_le_bytes());
+        for (i, (offset, paddr, filesz)) in
+            [(0x100u32, 0x0800_0000u32, 4u32), (0x104, 0x0800_0008, 8)]
+                .into_iter()
+                .enumerate()
+        {
+            let header = 0x40 + i * 32;
+            elf[header..header + 4].copy_from_slice(&PT_LOAD.to_le_bytes());
+            elf[header + 4..header + 8].copy_from_slice(&offset.to_le_bytes());
+            elf[header + 12..header + 16].copy_from_slice(&paddr.to_le_bytes());
+            elf[header + 16..header + 20].copy_from_slice(&filesz.to_le_bytes());
+        }
+        elf[0x100..0x10c].copy_from_slice(b"1234\x35\x36\x37\x38\x39\x00\x00\x00");
+
+        // Section headers at 0x80, the null section, the name table and `.flash_crc`
+        elf[0x20..0x24].copy_from_slice(&0x80u32.to_le_bytes());
+        elf[0x2e..0x30].copy_from_slice(&40u16.to_le_bytes());
+        elf[0x30..0x32].copy_from_slice(&3u16.to_le_bytes());
+        elf[0x32..0x34].copy_from_slice(&1u16.to_le_bytes());
+        elf[0x80 + 40 + 16..0x80 + 40 + 20].copy_from_slice(&0x180u32.to_le_bytes());
+        elf[0x180..0x18c].copy_from_slice(b"\0.flash_crc\0");
+        elf[0x80 + 80..0x80 + 84].copy_from_slice(&1u32.to_le_bytes());
+        elf[0x80 + 80 + 16..0x80 + 80 + 20].copy_from_slice(&0x1c0u32.to_le_bytes());
+
+        elf[0x1c0..0x1c4].copy_from_slice(&0x0800_0000u32.to_le_bytes());
+        elf[0x1c4..0x1c8].copy_from_slice(&0x0800_000cu32.to_le_bytes());
+        elf[0x1c8..0x1cc].copy_from_slice(&0xffff_ffffu32.to_le_bytes());
+
+        elf
+    }
+
+    #[test]
+    fn image() {
+        let elf = elf();
+
+        assert_eq!(
+            load_image(&elf, 0x0800_0000, 0x0800_000c),
+            Ok(b"1234\xff\xff\xff\xff5678".to_vec())
+        );
+    }
+
+    #[test]
+    fn patch_record() {
+        let mut elf = elf();
+
+        let crc = patch(&mut elf).unwrap();
+
+        assert_eq!(crc, calc_crc32(b"1234\xff\xff\xff\xff5678", &CRC32_LOOKUP));
+        assert_eq!(elf[0x1c8..0x1cc], crc.to_le_bytes());
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(patch(&mut [0u8; 16]), Err(PatchError::NotElf32));
+
+        let mut elf = elf();
+        elf[0x181] = b'x';
+        assert_eq!(patch(&mut elf), Err(PatchError::NoRecord));
+    }
+}
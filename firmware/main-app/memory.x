@@ -1,5 +1,9 @@
 MEMORY
 {
-  FLASH : ORIGIN = 0x08000000, LENGTH = 1024K
-  RAM : ORIGIN = 0x20000000, LENGTH = 192K
+  FLASH : ORIGIN = 0x08000000, LENGTH = 896K
+  /* Last sector for the active DTCs */
+  DTC : ORIGIN = 0x080E0000, LENGTH = 128K
+  RAM : ORIGIN = 0x20000000, LENGTH = 128K
 }
+
+__dtc = ORIGIN(DTC);
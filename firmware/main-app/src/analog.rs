@@ -0,0 +1,92 @@
+//! Analog inputs on ADC1. The throttle sensors are read through a voltage divider and checked
+//! against the internal reference like on the monitor.
+
+use common::{
+    adc::{AdcChannelCheck, AdcError, VrefCalibration},
+    throttle::Throttle,
+};
+use embassy_stm32::{
+    adc::{Adc, AdcChannel, SampleTime},
+    peripherals::{ADC1, PC0, PC1},
+    Peri,
+};
+use embassy_time::{Duration, Ticker};
+
+use crate::{calibration::CALIBRATION, shared::ACCELERATOR};
+
+const PERIOD: Duration = Duration::from_millis(5);
+
+/// Number of conversions that are summed for every read
+const OVERSAMPLING: usize = 8;
+
+/// Factory calibration of VREFINT, measured at 3.3 V
+const VREFINT_CAL: *const u16 = 0x1fff_7a2a as *const u16;
+const VREFINT_CAL_MILLIVOLT: u16 = 3300;
+
+/// Margin to the converter rails in raw counts
+const RAIL_MARGIN: u16 = 16;
+/// Number of reads with an unchanged sum before the converter is considered stuck
+const STUCK_LIMIT: usize = 100;
+
+/// Ratio of the voltage divider between the throttle sensors and the ADC pins as
+/// `(numerator, denominator)`, the same as on the monitor
+const ACC_DIVIDER: (u32, u32) = (2, 1);
+
+#[embassy_executor::task]
+pub async fn analog_task(
+    mut adc: Adc<'static, ADC1>,
+    mut acc1: Peri<'static, PC0>,
+    mut acc2: Peri<'static, PC1>,
+) {
+    // The filtered inputs have a high source impedance and VREFINT needs 10 us
+    adc.set_sample_time(SampleTime::CYCLES480);
+    let mut vrefint = adc.enable_vrefint();
+
+    // Safety: Factory calibration value in system memory
+    let vrefint_cal = unsafe { VREFINT_CAL.read_volatile() };
+    let vref = VrefCalibration::new(vrefint_cal, VREFINT_CAL_MILLIVOLT, (3000, 3600));
+    let mut acc1_check = AdcChannelCheck::new(RAIL_MARGIN, STUCK_LIMIT);
+    let mut acc2_check = AdcChannelCheck::new(RAIL_MARGIN, STUCK_LIMIT);
+
+    let throttle = Throttle::new(
+        CALIBRATION.throttle_sensor1,
+        CALIBRATION.throttle_sensor2,
+        CALIBRATION.throttle_tolerance,
+    );
+
+    let mut ticker = Ticker::every(PERIOD);
+    loop {
+        let vrefint_sum: u32 = convert(&mut adc, &mut vrefint)
+            .iter()
+            .map(|&s| s as u32)
+            .sum();
+        let acc1_samples = convert(&mut adc, &mut acc1);
+        let acc2_samples = convert(&mut adc, &mut acc2);
+
+        let sensors = vref.vdda(vrefint_sum, OVERSAMPLING as u32).and_then(|_| {
+            let acc1 = vref.millivolt(acc1_check.check(&acc1_samples)?, vrefint_sum)?;
+            let acc2 = vref.millivolt(acc2_check.check(&acc2_samples)?, vrefint_sum)?;
+            Ok::<_, AdcError>((sensor_millivolt(acc1), sensor_millivolt(acc2)))
+        });
+
+        let position = sensors
+            .ok()
+            .and_then(|(acc1, acc2)| throttle.position(acc1, acc2).ok());
+        ACCELERATOR.set(position);
+
+        ticker.next().await;
+    }
+}
+
+fn convert(
+    adc: &mut Adc<'static, ADC1>,
+    channel: &mut impl AdcChannel<ADC1>,
+) -> [u16; OVERSAMPLING] {
+    core::array::from_fn(|_| adc.blocking_read(channel))
+}
+
+/// Scale a pin voltage to the sensor voltage in front of the divider
+fn sensor_millivolt(pin_millivolt: u16) -> u16 {
+    let millivolt = pin_millivolt as u32 * ACC_DIVIDER.0 / ACC_DIVIDER.1;
+    millivolt.min(u16::MAX as u32) as u16
+}
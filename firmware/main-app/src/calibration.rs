@@ -0,0 +1,64 @@
+use common::{
+    curve::Curve,
+    derating::{DeratingConfig, DeratingTable},
+    regen::{Regen, RegenConfig},
+};
+
+/// Calibration of the monitor, kept in `common` so the monitor tests check it against its
+/// plausibility bounds
+pub use common::calibration::CALIBRATION;
+
+/// Driver torque request, kept in `common` so its tests check it against the torque envelope
+/// of the monitor
+pub use common::pedal_map::PEDAL_MAP;
+
+/// Lift-off regen in percent of the calibrated torque
+pub const REGEN_LEVEL_PERCENT: u8 = 100;
+
+/// Torque at 1000 rpm for 1 kW, 9.55 Nm at 0.25 Nm per unit of the torque request. The unit
+/// has not been checked on the inverter yet.
+pub const POWER_TORQUE: u32 = 38;
+
+/// Regen tapers to zero at 300 rpm, above the speed the direction check of the monitor allows
+/// torque against the gear and the rpm its unintended acceleration check treats as standstill.
+pub const REGEN: Regen<2> = Regen::new(RegenConfig {
+    // 5 % pedal
+    liftoff_position: 3277,
+    liftoff_torque: 300,
+    brake_torque: 800,
+    taper: Curve::new([300, 1500], [0, 1000]),
+    power_torque: POWER_TORQUE,
+    // 96 cells at 4.11 V to 4.2 V
+    millivolt: (395_000, 403_000),
+    soc_percent: (90, 98),
+});
+
+/// Torque shaping, kept in `common` with the driveline model it is tuned against
+pub use common::torque_shaping::SHAPING;
+
+/// Torque allowed while a monitor reaction limits torque, a quarter of the maximum
+pub const MONITOR_LIMITED_TORQUE: i16 = 500;
+
+/// Drive torque over the motor speed in the direction of the gear, fading out below the 10500
+/// rpm at which the monitor reports overspeed
+pub const SPEED_LIMIT: Curve<2> = Curve::new([9500, 10000], [i16::MAX, 0]);
+/// Like `SPEED_LIMIT` in reverse
+pub const REVERSE_SPEED_LIMIT: Curve<2> = Curve::new([2500, 3000], [i16::MAX, 0]);
+
+/// Torque the thermal derating takes its share of, the maximum of the pedal map
+pub const MAX_TORQUE: i16 = 2000;
+
+/// Derating reaches a quarter of the maximum torque short of the temperatures at which the
+/// monitor limits torque, 150 °C for the motor and 85 °C for the inverter. The driver is warned
+/// 10 °C before derating starts.
+pub const DERATING: DeratingConfig<2> = DeratingConfig {
+    motor: DeratingTable {
+        share: Curve::new([120, 145], [1000, 250]),
+        warning: 110,
+    },
+    inverter: DeratingTable {
+        share: Curve::new([70, 82], [1000, 250]),
+        warning: 60,
+    },
+    hysteresis: 5,
+};
@@ -0,0 +1,148 @@
+//! Both CAN buses at 500 kbit/s. CAN1 is EV CAN with the inverter, CAN2 is the vehicle CAN.
+
+use common::ev_can::{EvCanFrame, INVERTER_STATUS_ID, INVERTER_TEMPERATURE_ID};
+use embassy_futures::join::join;
+use embassy_stm32::can::{
+    enums::BusError,
+    filter::{BankConfig, Mask32},
+    Can, CanRx, CanTx, Fifo, Frame, Id, StandardId,
+};
+use embassy_time::Instant;
+
+use crate::{
+    debug::debug,
+    shared::{
+        InverterStatus, InverterTemperature, EV_CAN_TX, INVERTER_STATUS, INVERTER_TEMPERATURE,
+    },
+};
+
+pub const BITRATE: u32 = 500_000;
+
+/// First filter bank of CAN2, the banks are shared with CAN1
+const CAN2_FIRST_BANK: u8 = 14;
+
+/// Set up the filters of both controllers, CAN1 only receives the inverter frames
+pub fn filters(can1: &mut Can<'_>) {
+    let mut filters = can1.modify_filters();
+    filters
+        .set_split(CAN2_FIRST_BANK)
+        .enable_bank(0, Fifo::Fifo0, only(INVERTER_STATUS_ID))
+        .enable_bank(1, Fifo::Fifo0, only(INVERTER_TEMPERATURE_ID));
+    filters
+        .slave_filters()
+        .enable_bank(CAN2_FIRST_BANK, Fifo::Fifo0, Mask32::accept_all());
+}
+
+fn only(id: u16) -> BankConfig {
+    let id = StandardId::new(id).unwrap();
+    Mask32::frames_with_std_id(id, StandardId::MAX).into()
+}
+
+#[embassy_executor::task]
+pub async fn can1_task(mut can: Can<'static>) {
+    can.enable().await;
+    let (mut tx, mut rx) = can.split();
+
+    join(ev_can_receive(&mut rx), ev_can_transmit(&mut tx)).await;
+}
+
+/// Publish the latest inverter frames
+async fn ev_can_receive(rx: &mut CanRx<'_>) {
+    let mut errors = Errors::new("CAN1");
+
+    loop {
+        let frame = match rx.read().await {
+            Ok(envelope) => {
+                errors.ok();
+                envelope.frame
+            }
+            Err(err) => {
+                errors.error(err);
+                continue;
+            }
+        };
+        let Id::Standard(id) = frame.id() else {
+            continue;
+        };
+
+        let received = Instant::now();
+        match EvCanFrame::from_raw(id.as_raw(), frame.data()) {
+            Ok(EvCanFrame::InverterStatus {
+                millivolt,
+                rpm,
+                current,
+                error,
+            }) => INVERTER_STATUS.set(Some(InverterStatus {
+                millivolt,
+                rpm,
+                current,
+                error,
+                received,
+            })),
+            Ok(EvCanFrame::InverterTemperature {
+                motor_temperature,
+                inverter_temperature,
+            }) => INVERTER_TEMPERATURE.set(Some(InverterTemperature {
+                motor: motor_temperature,
+                inverter: inverter_temperature,
+                received,
+            })),
+            _ => {}
+        }
+    }
+}
+
+/// Send the frames queued by the other tasks
+async fn ev_can_transmit(tx: &mut CanTx<'_>) {
+    loop {
+        let frame = EV_CAN_TX.receive().await;
+        let Ok((id, data)) = frame.to_raw() else {
+            continue;
+        };
+        if let Ok(frame) = Frame::new_standard(id, &data) {
+            tx.write(&frame).await;
+        }
+    }
+}
+
+/// Vehicle CAN, nothing is decoded yet. The controller is kept receiving so that bus errors
+/// show up on the debug UART.
+#[embassy_executor::task]
+pub async fn can2_task(mut can: Can<'static>) {
+    can.enable().await;
+    let mut errors = Errors::new("CAN2");
+
+    loop {
+        match can.read().await {
+            Ok(_) => errors.ok(),
+            Err(err) => errors.error(err),
+        }
+    }
+}
+
+/// Reports the first bus error after a received frame, a disconnected bus would otherwise
+/// flood the debug UART
+struct Errors {
+    bus: &'static str,
+    reported: bool,
+}
+
+impl Errors {
+    fn new(bus: &'static str) -> Self {
+        Self {
+            bus,
+            reported: false,
+        }
+    }
+
+    fn ok(&mut self) {
+        self.reported = false;
+    }
+
+    fn error(&mut self, err: BusError) {
+        if !self.reported {
+            debug!("{} error {:?}", self.bus, err);
+            self.reported = true;
+        }
+    }
+}
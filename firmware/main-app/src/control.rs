@@ -0,0 +1,329 @@
+//! Vehicle control. Runs every 10 ms, drives the vehicle state machine and the contactors and
+//! sends the inverter its keepalives and torque requests. Torque is requested from the pedal
+//! map and regen in drive only. The torque arbiter limits it for monitor reactions, the motor
+//! and inverter temperatures, the battery and the top speed and logs the limiter whenever it
+//! changes, then it is shaped against driveline shunt. The driver is warned over EV CAN before
+//! the temperatures derate torque. Contactor faults found by the precharge controller raise
+//! DTCs, which keep the vehicle from getting ready again. Active DTCs are stored in flash and
+//! survive power cycles until they are cleared by holding the brake and the pedal beyond 90 %
+//! for 5 s with the ignition on and HV disconnected, which also clears the fault log of the
+//! monitor.
+
+use common::{
+    derating::ThermalDerating,
+    dtc::ClearRequest,
+    dtc_store::DtcStore,
+    ev_can::EvCanFrame,
+    gear::Gear,
+    monitor_message::{MonitorState, Reaction},
+    precharge::{PrechargeConfig, PrechargeController},
+    regen::RegenInputs,
+    torque_arbiter::{Limiter, TorqueArbiter, TorqueSource},
+    torque_shaping::TorqueShaper,
+    vehicle_state::{
+        InverterState, PrechargeStatus, VehicleInputs, VehicleState, VehicleStateMachine,
+    },
+};
+use embassy_stm32::gpio::{Input, Output};
+use embassy_time::{Duration, Ticker};
+
+use crate::{
+    calibration::{
+        DERATING, MAX_TORQUE, MONITOR_LIMITED_TORQUE, PEDAL_MAP, POWER_TORQUE, REGEN,
+        REGEN_LEVEL_PERCENT, REVERSE_SPEED_LIMIT, SHAPING, SPEED_LIMIT,
+    },
+    debug::debug,
+    dtc_flash::DtcFlash,
+    shared::{
+        ACCELERATOR, BATTERY_LIMITS, CLEAR_REQUESTS, EV_CAN_TX, GEAR, HV_CONNECTED,
+        INVERTER_STATUS, INVERTER_TEMPERATURE, MONITOR_STATUS, PACK_MILLIVOLT, REACTION,
+    },
+};
+
+const PERIOD: Duration = Duration::from_millis(10);
+/// `VcmKeepalive2` and `DriverWarning` are sent every 100 ms
+const KEEPALIVE2_CYCLES: u32 = 10;
+/// A status line goes to the debug UART every second
+const STATUS_CYCLES: u32 = 100;
+/// Cycles spent in shutdown for the contactors to open and the DC link to be checked, 6 s
+const SHUTDOWN_TIME: usize = 600;
+
+const PRECHARGE: PrechargeConfig = PrechargeConfig {
+    target_percent: 95,
+    // 100 ms to 3 s
+    min_time: 10,
+    max_time: 300,
+    overlap: 10,
+    open_percent: 90,
+    // 5 s for the inverter to discharge the DC link
+    open_time: 500,
+    // 200 ms
+    weld_check_time: 20,
+    weld_millivolt: 20_000,
+    // 500 ms
+    verify_time: 50,
+};
+
+/// Clearing the DTCs takes the pedal pressed beyond 90 %
+const CLEAR_POSITION: u16 = u16::MAX / 10 * 9;
+/// Time the brake and the pedal are held to clear the DTCs, 5 s
+const CLEAR_TIME: usize = 500;
+
+/// The inverter status is sent every 10 ms
+const INVERTER_TIMEOUT: Duration = Duration::from_millis(100);
+/// The inverter temperature is sent every 100 ms
+const TEMPERATURE_TIMEOUT: Duration = Duration::from_millis(500);
+/// The monitor sends a message every 10 ms
+const MONITOR_TIMEOUT: Duration = Duration::from_millis(50);
+
+/// Inputs and outputs of the vehicle control task
+pub struct ControlPins {
+    pub ignition: Input<'static>,
+    pub crank: Input<'static>,
+    pub brake: Input<'static>,
+    pub power_keepalive: Output<'static>,
+    pub precharge_enable: Output<'static>,
+    pub upper_contactor_enable: Output<'static>,
+    pub lower_contactor_enable: Output<'static>,
+}
+
+#[embassy_executor::task]
+pub async fn vehicle_control_task(mut pins: ControlPins, mut dtc_store: DtcStore<DtcFlash>) {
+    let mut vehicle = VehicleStateMachine::new(SHUTDOWN_TIME);
+    let mut precharge = PrechargeController::new(PRECHARGE);
+    let mut dtcs = dtc_store.dtcs();
+    for dtc in dtcs.iter() {
+        debug!("DTC {:?} stored", dtc);
+    }
+    let mut clear_request = ClearRequest::new(CLEAR_TIME);
+    let mut shaper = TorqueShaper::new(SHAPING);
+    let mut limiter = None;
+    let mut derating = ThermalDerating::new(DERATING);
+    let mut warnings = (false, false);
+    let mut ticker = Ticker::every(PERIOD);
+    let mut cycle: u32 = 0;
+
+    loop {
+        let monitor = MONITOR_STATUS
+            .get()
+            .filter(|status| status.received.elapsed() < MONITOR_TIMEOUT)
+            .map(|status| status.state);
+
+        // The contactors follow the state of the previous cycle
+        let dc_millivolt = INVERTER_STATUS
+            .get()
+            .filter(|status| status.received.elapsed() < INVERTER_TIMEOUT)
+            .map(|status| status.millivolt);
+        let active = dtcs;
+        let clear = vehicle.state() == VehicleState::Wake
+            && pins.brake.is_high()
+            && ACCELERATOR
+                .get()
+                .is_some_and(|position| position >= CLEAR_POSITION);
+        if clear_request.tick(clear) {
+            CLEAR_REQUESTS.set(CLEAR_REQUESTS.get().wrapping_add(1));
+            if !dtcs.is_empty() {
+                dtcs.clear();
+                debug!("DTCs cleared");
+            }
+        }
+        let contactors = precharge.tick(
+            vehicle.state().hv(),
+            PACK_MILLIVOLT.get(),
+            dc_millivolt,
+            &mut dtcs,
+        );
+        for dtc in dtcs.iter().filter(|&dtc| !active.contains(dtc)) {
+            debug!("DTC {:?}", dtc);
+        }
+        if dtcs != active {
+            if let Err(error) = dtc_store.store(dtcs) {
+                debug!("DTC store {:?}", error);
+            }
+        }
+        HV_CONNECTED.set(precharge.status() == PrechargeStatus::Complete);
+        pins.precharge_enable.set_level(contactors.precharge.into());
+        pins.upper_contactor_enable
+            .set_level(contactors.upper.into());
+        pins.lower_contactor_enable
+            .set_level(contactors.lower.into());
+
+        let inputs = VehicleInputs {
+            ignition: pins.ignition.is_high(),
+            crank: pins.crank.is_high(),
+            gear: GEAR.get(),
+            inverter: inverter_state(),
+            monitor,
+            precharge: precharge.status(),
+            dtc: dtcs.first(),
+        };
+        if let Some(transition) = vehicle.tick(&inputs) {
+            debug!(
+                "Vehicle {:?} -> {:?}: {:?}",
+                transition.from, transition.to, transition.reason
+            );
+            // Erasing the sector stalls the CPU for up to 4 s, which starves the monitor link
+            // beyond the 100 ms ping timeout of the monitor. Here HV is disconnected and the
+            // keepalive is still held. Unless the ignition is turned on again during the erase
+            // the controller powers down and the ping error the monitor latches is gone after
+            // the next power up.
+            if transition.to == VehicleState::Off {
+                if let Err(error) = dtc_store.compact() {
+                    debug!("DTC store {:?}", error);
+                }
+            }
+        }
+        pins.power_keepalive
+            .set_level(vehicle.state().keepalive().into());
+
+        // The more severe reactions fault the vehicle and disconnect HV
+        let reaction = match monitor {
+            Some(MonitorState::Fault(_, reaction)) => Some(reaction),
+            _ => None,
+        };
+        REACTION.set(reaction);
+
+        let rpm = INVERTER_STATUS
+            .get()
+            .filter(|status| status.received.elapsed() < INVERTER_TIMEOUT)
+            .map_or(0, |status| status.rpm);
+        let gear = GEAR.get();
+        let mut arbiter = TorqueArbiter::new();
+        match ACCELERATOR.get() {
+            Some(position) if vehicle.state().torque() => {
+                let regen = REGEN.torque(&RegenInputs {
+                    position,
+                    brake: pins.brake.is_high(),
+                    rpm,
+                    gear,
+                    battery: BATTERY_LIMITS.get(),
+                    level_percent: REGEN_LEVEL_PERCENT,
+                });
+                let request = PEDAL_MAP.torque(position, rpm, gear) + regen;
+                arbiter.request(TorqueSource::Driver, request);
+            }
+            _ => {}
+        }
+        match reaction {
+            Some(Reaction::Warn) | None => {}
+            Some(Reaction::TorqueLimited) => arbiter.limit(
+                Limiter::Monitor,
+                -MONITOR_LIMITED_TORQUE,
+                MONITOR_LIMITED_TORQUE,
+            ),
+            Some(_) => arbiter.request(TorqueSource::Monitor, 0),
+        }
+        let temperatures = INVERTER_TEMPERATURE
+            .get()
+            .filter(|temperature| temperature.received.elapsed() < TEMPERATURE_TIMEOUT)
+            .map(|temperature| (temperature.motor, temperature.inverter));
+        let thermal = derating.tick(temperatures);
+        let max_torque = thermal.max_torque(MAX_TORQUE);
+        arbiter.limit(Limiter::Thermal, -max_torque, max_torque);
+        if (thermal.motor_warning, thermal.inverter_warning) != warnings {
+            warnings = (thermal.motor_warning, thermal.inverter_warning);
+            debug!(
+                "Temperature warning motor {} inverter {}",
+                warnings.0, warnings.1
+            );
+        }
+        if let Some(battery) = BATTERY_LIMITS.get() {
+            // Only driving the motor draws from the battery
+            let drive = battery.drive_torque(POWER_TORQUE, rpm.unsigned_abs());
+            match rpm {
+                0.. => arbiter.limit(Limiter::Battery, i16::MIN, drive),
+                _ => arbiter.limit(Limiter::Battery, -drive, i16::MAX),
+            }
+        }
+        let (min, max) = speed_limit(rpm, gear);
+        arbiter.limit(Limiter::Speed, min, max);
+
+        let arbitration = arbiter.arbitrate();
+        if arbitration.limiter != limiter {
+            debug!("Torque limited by {:?}", arbitration.limiter);
+            limiter = arbitration.limiter;
+        }
+        // Limits are shaped like the pedal, so torque ramps back up once a limit is released.
+        // Zero torque for the monitor takes effect at once.
+        let torque = match arbitration.source {
+            Some(TorqueSource::Driver) => shaper.tick(arbitration.torque, rpm),
+            _ => {
+                shaper.reset(rpm);
+                arbitration.torque
+            }
+        };
+
+        // Frames are dropped while the bus is down
+        let counter = (cycle % 4) as u8;
+        EV_CAN_TX
+            .try_send(EvCanFrame::VcmKeepalive1 { counter })
+            .ok();
+        EV_CAN_TX
+            .try_send(EvCanFrame::TorqueRequest { torque, counter })
+            .ok();
+        if cycle.is_multiple_of(KEEPALIVE2_CYCLES) {
+            EV_CAN_TX.try_send(EvCanFrame::VcmKeepalive2).ok();
+            EV_CAN_TX
+                .try_send(EvCanFrame::DriverWarning {
+                    motor_temperature: thermal.motor_warning,
+                    inverter_temperature: thermal.inverter_warning,
+                    derating: thermal.source.is_some(),
+                })
+                .ok();
+        }
+
+        if cycle.is_multiple_of(STATUS_CYCLES) {
+            debug!("Vehicle {:?}", vehicle.state());
+            status();
+        }
+
+        cycle = cycle.wrapping_add(1);
+        ticker.next().await;
+    }
+}
+
+/// Torque range the top speed in the direction of the gear allows
+fn speed_limit(rpm: i16, gear: Gear) -> (i16, i16) {
+    match gear {
+        Gear::Drive => (i16::MIN, SPEED_LIMIT.at(rpm.max(0) as u16)),
+        Gear::Reverse => (
+            -REVERSE_SPEED_LIMIT.at(rpm.saturating_neg().max(0) as u16),
+            i16::MAX,
+        ),
+        Gear::Park | Gear::Neutral => (i16::MIN, i16::MAX),
+    }
+}
+
+fn inverter_state() -> InverterState {
+    match INVERTER_STATUS
+        .get()
+        .filter(|status| status.received.elapsed() < INVERTER_TIMEOUT)
+    {
+        None => InverterState::Silent,
+        Some(status) if status.error != 0 => InverterState::Error(status.error),
+        Some(_) => InverterState::Ok,
+    }
+}
+
+fn status() {
+    debug!("Accelerator {:?}", ACCELERATOR.get());
+    match INVERTER_STATUS
+        .get()
+        .filter(|status| status.received.elapsed() < INVERTER_TIMEOUT)
+    {
+        Some(status) => debug!(
+            "Inverter {} mV {} rpm current {} error {}",
+            status.millivolt, status.rpm, status.current, status.error
+        ),
+        None => debug!("Inverter status missing"),
+    }
+    if let Some(temperature) = INVERTER_TEMPERATURE
+        .get()
+        .filter(|temperature| temperature.received.elapsed() < TEMPERATURE_TIMEOUT)
+    {
+        debug!(
+            "Motor {} C inverter {} C",
+            temperature.motor, temperature.inverter
+        );
+    }
+}
@@ -0,0 +1,44 @@
+//! Text output on the debug UART. Lines are queued by any task and written by the debug task,
+//! a line that doesn't fit in the queue is dropped.
+
+use core::fmt::{self, Write};
+
+use embassy_stm32::{mode::Async, usart::UartTx};
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, channel::Channel};
+use heapless::String;
+
+pub const DEBUG_BAUDRATE: u32 = 115_200;
+
+const LINE_LENGTH: usize = 96;
+
+type Line = String<LINE_LENGTH>;
+
+static LINES: Channel<CriticalSectionRawMutex, Line, 16> = Channel::new();
+
+/// Queue a line, use the `debug!` macro
+pub fn log(args: fmt::Arguments) {
+    let mut line = Line::new();
+    if write!(line, "{args}\r\n").is_err() {
+        // Cut off a line that is too long, the output is ASCII
+        line.truncate(LINE_LENGTH - 2);
+        line.push_str("\r\n").ok();
+    }
+
+    LINES.try_send(line).ok();
+}
+
+/// Queue a line for the debug UART, with the same arguments as `format!`
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        $crate::debug::log(format_args!($($arg)*))
+    };
+}
+pub(crate) use debug;
+
+#[embassy_executor::task]
+pub async fn debug_task(mut tx: UartTx<'static, Async>) {
+    loop {
+        let line = LINES.receive().await;
+        tx.write(line.as_bytes()).await.ok();
+    }
+}
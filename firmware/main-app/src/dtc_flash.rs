@@ -0,0 +1,50 @@
+use core::ptr::addr_of;
+
+use common::dtc_store::{FlashError, SectorFlash};
+use embassy_stm32::flash::{Blocking, Flash, FLASH_BASE};
+
+extern "C" {
+    /// Start of the sector reserved for the active DTCs, see `memory.x`
+    static __dtc: u8;
+}
+
+/// The flash sector of the active DTCs. Code keeps running from the same flash bank, so the
+/// CPU stalls while the sector is erased or programmed.
+pub struct DtcFlash {
+    flash: Flash<'static, Blocking>,
+}
+
+impl DtcFlash {
+    pub fn new(flash: Flash<'static, Blocking>) -> Self {
+        Self { flash }
+    }
+
+    /// Offset of the sector from the start of flash
+    fn base(&self) -> u32 {
+        (addr_of!(__dtc) as usize - FLASH_BASE) as u32
+    }
+}
+
+impl SectorFlash for DtcFlash {
+    const SIZE: usize = 128 * 1024;
+
+    fn read(&mut self, offset: usize, bytes: &mut [u8]) {
+        // Reads only fail outside of flash
+        self.flash
+            .blocking_read(self.base() + offset as u32, bytes)
+            .unwrap();
+    }
+
+    fn erase(&mut self) -> Result<(), FlashError> {
+        let base = self.base();
+        self.flash
+            .blocking_erase(base, base + Self::SIZE as u32)
+            .map_err(|_| FlashError::Erase)
+    }
+
+    fn program(&mut self, offset: usize, bytes: &[u8]) -> Result<(), FlashError> {
+        self.flash
+            .blocking_write(self.base() + offset as u32, bytes)
+            .map_err(|_| FlashError::Program)
+    }
+}
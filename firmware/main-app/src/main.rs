@@ -1,27 +1,127 @@
 #![no_std]
 #![no_main]
 
-use cortex_m_rt::entry;
+mod analog;
+mod calibration;
+mod can;
+mod control;
+mod debug;
+mod dtc_flash;
+mod monitor_link;
+mod shared;
+
+use common::dtc_store::DtcStore;
+use embassy_executor::Spawner;
+use embassy_stm32::{
+    adc::Adc,
+    bind_interrupts,
+    can::{Can, Rx0InterruptHandler, Rx1InterruptHandler, SceInterruptHandler, TxInterruptHandler},
+    flash::Flash,
+    gpio::{Input, Level, Output, Pull, Speed},
+    peripherals::{CAN1, CAN2, USART2},
+    time::Hertz,
+    usart::{self, BufferedUart, UartTx},
+    Config,
+};
+use embassy_time::Timer;
 use panic_halt as _; // you can put a breakpoint on `rust_begin_unwind` to catch panics
-use stm32f4xx_hal::{pac, prelude::*};
+use static_cell::StaticCell;
+
+use crate::debug::debug;
+
+bind_interrupts!(struct Irqs {
+    CAN1_TX => TxInterruptHandler<CAN1>;
+    CAN1_RX0 => Rx0InterruptHandler<CAN1>;
+    CAN1_RX1 => Rx1InterruptHandler<CAN1>;
+    CAN1_SCE => SceInterruptHandler<CAN1>;
+    CAN2_TX => TxInterruptHandler<CAN2>;
+    CAN2_RX0 => Rx0InterruptHandler<CAN2>;
+    CAN2_RX1 => Rx1InterruptHandler<CAN2>;
+    CAN2_SCE => SceInterruptHandler<CAN2>;
+    USART2 => usart::BufferedInterruptHandler<USART2>;
+});
+
+static MONITOR_TX_BUFFER: StaticCell<[u8; 128]> = StaticCell::new();
+static MONITOR_RX_BUFFER: StaticCell<[u8; 128]> = StaticCell::new();
 
-#[entry]
-fn main() -> ! {
-    let dp = pac::Peripherals::take().unwrap();
+#[embassy_executor::main]
+async fn main(spawner: Spawner) {
+    let mut config = Config::default();
+    {
+        use embassy_stm32::rcc::*;
+        config.rcc.hse = Some(Hse {
+            freq: Hertz(8_000_000),
+            mode: HseMode::Oscillator,
+        });
+        config.rcc.pll_src = PllSource::HSE;
+        config.rcc.pll = Some(Pll {
+            prediv: PllPreDiv::DIV4,
+            mul: PllMul::MUL168,
+            divp: Some(PllPDiv::DIV2), // Main system clock at 168 MHz
+            divq: Some(PllQDiv::DIV7), // 48 MHz for USB
+            divr: None,
+        });
+        config.rcc.sys = Sysclk::PLL1_P;
+        config.rcc.ahb_pre = AHBPrescaler::DIV1;
+        config.rcc.apb1_pre = APBPrescaler::DIV4; // 42 MHz for bxCAN and USART2
+        config.rcc.apb2_pre = APBPrescaler::DIV2; // 84 MHz for ADC1 and USART1
+    }
+    let p = embassy_stm32::init(config);
+
+    let mut debug_config = usart::Config::default();
+    debug_config.baudrate = debug::DEBUG_BAUDRATE;
+    let debug_tx = UartTx::new(p.USART1, p.PA9, p.DMA2_CH7, debug_config).unwrap();
 
-    let rcc = dp.RCC.constrain();
+    let mut monitor_config = usart::Config::default();
+    monitor_config.baudrate = monitor_link::MONITOR_BAUDRATE;
+    let monitor_uart = BufferedUart::new(
+        p.USART2,
+        p.PA3,
+        p.PA2,
+        MONITOR_TX_BUFFER.init([0; 128]),
+        MONITOR_RX_BUFFER.init([0; 128]),
+        Irqs,
+        monitor_config,
+    )
+    .unwrap();
 
-    rcc.cfgr.use_hse(8.MHz()).freeze();
+    let mut can1 = Can::new(p.CAN1, p.PB8, p.PB9, Irqs);
+    let mut can2 = Can::new(p.CAN2, p.PB12, p.PB13, Irqs);
+    can::filters(&mut can1);
+    can1.set_bitrate(can::BITRATE);
+    can2.set_bitrate(can::BITRATE);
 
+    let adc = Adc::new(p.ADC1);
 
-    let gpioa = dp.GPIOA.split();
-    let mut led = gpioa.pa0.into_push_pull_output();
+    spawner.spawn(debug::debug_task(debug_tx)).unwrap();
+    debug!("Main MCU started");
 
+    spawner.spawn(can::can1_task(can1)).unwrap();
+    spawner.spawn(can::can2_task(can2)).unwrap();
+    spawner
+        .spawn(monitor_link::monitor_link_task(monitor_uart))
+        .unwrap();
+    spawner
+        .spawn(analog::analog_task(adc, p.PC0, p.PC1))
+        .unwrap();
+    // Only reads, the sector is erased at shutdown
+    let dtc_store = DtcStore::new(dtc_flash::DtcFlash::new(Flash::new_blocking(p.FLASH)));
+    let control_pins = control::ControlPins {
+        ignition: Input::new(p.PC4, Pull::Down),
+        crank: Input::new(p.PC5, Pull::Down),
+        brake: Input::new(p.PC10, Pull::Down),
+        power_keepalive: Output::new(p.PC6, Level::Low, Speed::Low),
+        precharge_enable: Output::new(p.PC7, Level::Low, Speed::Low),
+        upper_contactor_enable: Output::new(p.PC8, Level::Low, Speed::Low),
+        lower_contactor_enable: Output::new(p.PC9, Level::Low, Speed::Low),
+    };
+    spawner
+        .spawn(control::vehicle_control_task(control_pins, dtc_store))
+        .unwrap();
+
+    let mut led = Output::new(p.PA0, Level::Low, Speed::Low);
     loop {
         led.toggle();
-
-        for _ in 0..10000 {
-            cortex_m::asm::nop();
-        }
+        Timer::after_millis(500).await;
     }
 }
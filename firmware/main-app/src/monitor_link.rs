@@ -0,0 +1,204 @@
+//! Serial link to the monitor MCU. The monitor sends a message every 10 ms and main answers
+//! every one of them, see `common::monitor_serial` for the framing. The diagnostics of the
+//! monitor are kept in `shared` and logged whenever they change. The fault log of the monitor
+//! is logged at startup and cleared together with the DTCs.
+
+use common::{
+    calibration::CalibrationBlock,
+    fault_log_reader::{FaultLogReader, FaultLogReaderState},
+    monitor_message::{
+        Diagnostics, MainState, MainToMonitor, MonitorToMain, MONITOR_MESSAGE_BUFFER_SIZE,
+    },
+    monitor_serial::{decode_frame, encode_frame},
+};
+use embassy_stm32::usart::BufferedUart;
+use embassy_time::Instant;
+use embedded_io_async::{Read, Write};
+use heapless::Vec;
+
+use crate::{
+    calibration::CALIBRATION,
+    debug::debug,
+    shared::{
+        MonitorStatus, ACCELERATOR, CLEAR_REQUESTS, GEAR, HV_CONNECTED, MONITOR_DIAGNOSTICS,
+        MONITOR_STATUS, REACTION,
+    },
+};
+
+pub const MONITOR_BAUDRATE: u32 = 115_200;
+
+#[embassy_executor::task]
+pub async fn monitor_link_task(mut uart: BufferedUart<'static>) {
+    let calibration = CalibrationBlock::new(CALIBRATION);
+    let mut link = Link::new();
+
+    let mut frame: Vec<u8, MONITOR_MESSAGE_BUFFER_SIZE> = Vec::new();
+    // Set when a frame did not fit in the buffer, the rest of it is dropped
+    let mut discard = false;
+    let mut buffer = [0u8; 32];
+
+    loop {
+        let len = match uart.read(&mut buffer).await {
+            Ok(len) => len,
+            // The receiver lost data, the frame in progress is incomplete
+            Err(_) => {
+                frame.clear();
+                discard = true;
+                continue;
+            }
+        };
+
+        for &byte in &buffer[..len] {
+            if byte != 0 {
+                if !discard && frame.push(byte).is_err() {
+                    frame.clear();
+                    discard = true;
+                }
+                continue;
+            }
+
+            let message = (!discard)
+                .then(|| decode_frame::<MonitorToMain>(&mut frame).ok())
+                .flatten();
+            frame.clear();
+            discard = false;
+
+            if let Some(message) = message {
+                let reply = link.message(&message, &calibration);
+                if let Ok(bytes) = encode_frame::<_, MONITOR_MESSAGE_BUFFER_SIZE>(&reply) {
+                    uart.write_all(&bytes).await.ok();
+                }
+            }
+        }
+    }
+}
+
+/// State of the link across messages
+struct Link {
+    /// The fault log of the monitor is read once at startup
+    fault_log: FaultLogReader,
+    /// `CLEAR_REQUESTS` when the fault log was last cleared
+    clear_requests: u32,
+    first: bool,
+}
+
+impl Link {
+    fn new() -> Self {
+        let mut fault_log = FaultLogReader::new();
+        fault_log.read();
+
+        Self {
+            fault_log,
+            clear_requests: CLEAR_REQUESTS.get(),
+            first: true,
+        }
+    }
+
+    /// Handle a message from the monitor and build the reply
+    fn message(
+        &mut self,
+        message: &MonitorToMain,
+        calibration: &CalibrationBlock,
+    ) -> MainToMonitor {
+        if self.first {
+            debug!("Monitor reset cause {:?}", message.reset_cause);
+            self.first = false;
+        }
+
+        if MONITOR_STATUS.get().map(|status| status.state) != Some(message.state) {
+            debug!("Monitor {:?}", message.state);
+        }
+        MONITOR_STATUS.set(Some(MonitorStatus {
+            state: message.state,
+            received: Instant::now(),
+        }));
+        diagnostics(message.diagnostics);
+
+        let state = self.fault_log.state();
+        self.fault_log.response(message.fault_log.as_ref());
+        match (state, self.fault_log.state()) {
+            (FaultLogReaderState::Reading, FaultLogReaderState::Reading)
+            | (FaultLogReaderState::Clearing, FaultLogReaderState::Clearing) => {}
+            (FaultLogReaderState::Reading, _) => {
+                for entry in self.fault_log.entries() {
+                    debug!("Monitor fault log {:?}", entry);
+                }
+            }
+            (FaultLogReaderState::Clearing, state) => {
+                debug!("Monitor fault log clear {:?}", state)
+            }
+            _ => {}
+        }
+
+        // A clear waits for the read in progress
+        let clear_requests = CLEAR_REQUESTS.get();
+        if clear_requests != self.clear_requests
+            && !matches!(
+                self.fault_log.state(),
+                FaultLogReaderState::Reading | FaultLogReaderState::Clearing
+            )
+        {
+            self.clear_requests = clear_requests;
+            self.fault_log.clear();
+        }
+
+        MainToMonitor {
+            pong: message.ping,
+            state: MainState::Operational,
+            accelerator: ACCELERATOR.get().unwrap_or(0),
+            // Main doesn't drive the high side output yet, the monitor only checks that it is off
+            // while disabled
+            high_side_on: None,
+            hv_connected: HV_CONNECTED.get(),
+            gear: GEAR.get(),
+            reaction_ack: REACTION.get(),
+            fault_log: self.fault_log.request(),
+            calibration: calibration.pending(message.calibration_crc),
+        }
+    }
+}
+
+/// Keep the latest diagnostics of each kind and log them whenever they change
+fn diagnostics(diagnostics: Diagnostics) {
+    let mut latest = MONITOR_DIAGNOSTICS.get();
+    let kind = match diagnostics {
+        Diagnostics::Serial(_) => &mut latest.serial,
+        Diagnostics::Can { .. } => &mut latest.can,
+        Diagnostics::Loop { .. } => &mut latest.loop_timing,
+        Diagnostics::Qualifications { .. } => &mut latest.qualifications,
+    };
+    if *kind == Some(diagnostics) {
+        return;
+    }
+    *kind = Some(diagnostics);
+    MONITOR_DIAGNOSTICS.set(latest);
+
+    match diagnostics {
+        Diagnostics::Serial(stats) => debug!(
+            "Monitor serial CRC errors {} overruns {}",
+            stats.crc_errors, stats.overruns
+        ),
+        Diagnostics::Can { tec, rec, bus_off } => {
+            debug!("Monitor CAN TEC {} REC {} bus off {}", tec, rec, bus_off)
+        }
+        Diagnostics::Loop {
+            max,
+            jitter,
+            overruns,
+        } => debug!(
+            "Monitor loop max {} jitter {} cycles overruns {}",
+            max, jitter, overruns
+        ),
+        Diagnostics::Qualifications {
+            throttle,
+            torque,
+            inverter,
+            high_side,
+            direction,
+            acceleration,
+        } => debug!(
+            "Monitor qualified throttle {} torque {} inverter {} high side {} direction {} UA {}",
+            throttle, torque, inverter, high_side, direction, acceleration
+        ),
+    }
+}
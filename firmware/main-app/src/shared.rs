@@ -0,0 +1,111 @@
+//! State shared between the tasks. Every value is written by the task that owns it and read
+//! by the others whenever they need it, no task waits for another one.
+
+use core::cell::Cell;
+
+use common::{
+    ev_can::EvCanFrame,
+    gear::Gear,
+    monitor_message::{Diagnostics, MonitorState, Reaction},
+    regen::BatteryLimits,
+};
+use embassy_sync::{
+    blocking_mutex::{raw::CriticalSectionRawMutex, Mutex},
+    channel::Channel,
+};
+use embassy_time::Instant;
+
+/// A value that is copied in and out under a critical section
+pub struct Shared<T>(Mutex<CriticalSectionRawMutex, Cell<T>>);
+
+impl<T: Copy> Shared<T> {
+    pub const fn new(value: T) -> Self {
+        Self(Mutex::new(Cell::new(value)))
+    }
+
+    pub fn get(&self) -> T {
+        self.0.lock(Cell::get)
+    }
+
+    pub fn set(&self, value: T) {
+        self.0.lock(|cell| cell.set(value))
+    }
+}
+
+/// Latest `InverterStatus` frame
+#[derive(Clone, Copy)]
+pub struct InverterStatus {
+    pub millivolt: u32,
+    pub rpm: i16,
+    pub current: i16,
+    pub error: u8,
+    pub received: Instant,
+}
+
+/// Latest `InverterTemperature` frame, in °C
+#[derive(Clone, Copy)]
+pub struct InverterTemperature {
+    pub motor: u8,
+    pub inverter: u8,
+    pub received: Instant,
+}
+
+/// Written by the CAN1 task
+pub static INVERTER_STATUS: Shared<Option<InverterStatus>> = Shared::new(None);
+/// Written by the CAN1 task
+pub static INVERTER_TEMPERATURE: Shared<Option<InverterTemperature>> = Shared::new(None);
+
+/// Pack voltage the DC link is precharged to, `None` until the BMS is read from vehicle CAN
+pub static PACK_MILLIVOLT: Shared<Option<u32>> = Shared::new(None);
+
+/// The contactors are closed and the DC link is precharged. Written by the vehicle control
+/// task, reported to the monitor.
+pub static HV_CONNECTED: Shared<bool> = Shared::new(false);
+
+/// Charge and discharge limits of the pack for regen and drive torque, `None` until the BMS is
+/// read from vehicle CAN
+pub static BATTERY_LIMITS: Shared<Option<BatteryLimits>> = Shared::new(None);
+
+/// Throttle position in the full `0..=u16::MAX` range, `None` while the sensors are
+/// implausible. Written by the analog task.
+pub static ACCELERATOR: Shared<Option<u16>> = Shared::new(None);
+
+/// Latest state reported by the monitor
+#[derive(Clone, Copy)]
+pub struct MonitorStatus {
+    pub state: MonitorState,
+    pub received: Instant,
+}
+
+/// `None` until the first message. Written by the monitor link task.
+pub static MONITOR_STATUS: Shared<Option<MonitorStatus>> = Shared::new(None);
+
+/// Latest diagnostics of each kind reported by the monitor, `None` until received
+#[derive(Clone, Copy)]
+pub struct MonitorDiagnostics {
+    pub serial: Option<Diagnostics>,
+    pub can: Option<Diagnostics>,
+    pub loop_timing: Option<Diagnostics>,
+    pub qualifications: Option<Diagnostics>,
+}
+
+/// Written by the monitor link task
+pub static MONITOR_DIAGNOSTICS: Shared<MonitorDiagnostics> = Shared::new(MonitorDiagnostics {
+    serial: None,
+    can: None,
+    loop_timing: None,
+    qualifications: None,
+});
+
+/// Gear selected by the driver, park until a gear selector is read
+pub static GEAR: Shared<Gear> = Shared::new(Gear::Park);
+/// Reaction to a monitor fault that vehicle control has applied, acknowledged to the monitor
+/// by the monitor link task
+pub static REACTION: Shared<Option<Reaction>> = Shared::new(None);
+
+/// Counts the requests of the driver to clear the DTCs, the monitor link task clears the fault
+/// log of the monitor whenever it changes. Written by the vehicle control task.
+pub static CLEAR_REQUESTS: Shared<u32> = Shared::new(0);
+
+/// Frames to send on EV CAN
+pub static EV_CAN_TX: Channel<CriticalSectionRawMutex, EvCanFrame, 8> = Channel::new();
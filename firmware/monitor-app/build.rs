@@ -8,7 +8,8 @@
 //! updating `memory.x` ensures a rebuild of the application with the
 //! new memory settings.
 //!
-//! The build script also sets the linker flags to tell it which link script to use.
+//! The build script also sets the linker flags to tell it which link script to use. They
+//! only apply to the binary, the library is also built and tested on the host.
 
 use std::env;
 use std::fs::File;
@@ -36,8 +37,8 @@ fn main() {
     // `--nmagic` is required if memory section addresses are not aligned to 0x10000,
     // for example the FLASH and RAM sections in your `memory.x`.
     // See https://github.com/rust-embedded/cortex-m-quickstart/pull/95
-    println!("cargo:rustc-link-arg=--nmagic");
+    println!("cargo:rustc-link-arg-bins=--nmagic");
 
     // Set the linker script to the one provided by cortex-m-rt.
-    println!("cargo:rustc-link-arg=-Tlink.x");
+    println!("cargo:rustc-link-arg-bins=-Tlink.x");
 }
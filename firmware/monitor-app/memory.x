@@ -1,5 +1,25 @@
 MEMORY
 {
-  FLASH : ORIGIN = 0x08000000, LENGTH = 256K
+  FLASH : ORIGIN = 0x08000000, LENGTH = 252K
+  /* Two pages for the emulated EEPROM of the fault log */
+  EEPROM : ORIGIN = 0x0803F000, LENGTH = 4K
   RAM : ORIGIN = 0x20000000, LENGTH = 32K
 }
+
+__eeprom = ORIGIN(EEPROM);
+
+/* Record describing the application image for the flash CRC self test, placed after
+   everything else that is loaded into flash. The linker fills in the address range of
+   the image, the CRC itself is patched into the ELF after linking by `flash-crc`.
+   An unpatched image keeps the erased value and fails the self test. */
+SECTIONS
+{
+  .flash_crc : ALIGN(4)
+  {
+    __flash_crc = .;
+    LONG(ORIGIN(FLASH));
+    /* The load image of .data directly follows .rodata */
+    LONG(__erodata + SIZEOF(.data));
+    LONG(0xFFFFFFFF);
+  } > FLASH
+} INSERT AFTER .gnu.sgstubs;
@@ -0,0 +1,181 @@
+use core::{
+    mem::MaybeUninit,
+    ptr::{addr_of, addr_of_mut},
+};
+
+use common::adc::{AdcChannelCheck, AdcError, VrefCalibration};
+use stm32f0xx_hal::{
+    gpio::{
+        gpioa::{PA0, PA1},
+        Analog,
+    },
+    pac,
+};
+
+/// Number of conversion sequences that are summed for every read
+const OVERSAMPLING: usize = 16;
+/// ACC1 (IN0), ACC2 (IN1) and VREFINT (IN17), converted in that order
+const CHANNELS: usize = 3;
+
+/// Factory calibration of VREFINT, measured at 3.3 V
+const VREFINT_CAL: *const u16 = 0x1fff_f7ba as *const u16;
+const VREFINT_CAL_MILLIVOLT: u16 = 3300;
+
+/// DMA1 channel selection register, not part of the `stm32f0x1` PAC
+const DMA1_CSELR: *mut u32 = 0x4002_00a8 as *mut u32;
+/// Request 1 selects the ADC on DMA1 channel 1
+const DMA1_C1S_ADC: u32 = 0b0001;
+
+/// Margin to the converter rails in raw counts
+const RAIL_MARGIN: u16 = 16;
+/// Number of reads with an unchanged sum before the converter is considered stuck
+const STUCK_LIMIT: usize = 500;
+
+/// Written by the DMA at any time, placed outside of the RAM that is tested at runtime
+#[link_section = ".uninit.BUFFER"]
+static mut BUFFER: MaybeUninit<[u16; OVERSAMPLING * CHANNELS]> = MaybeUninit::uninit();
+
+/// Continuous acquisition of the throttle sensors. The ADC converts ACC1, ACC2 and VREFINT
+/// over and over while DMA1 channel 1 writes the results into a circular buffer, holding
+/// `OVERSAMPLING` complete sequences.
+pub struct ThrottleAdc {
+    adc: pac::ADC,
+    dma: pac::DMA1,
+    vref: VrefCalibration,
+    acc1: AdcChannelCheck,
+    acc2: AdcChannelCheck,
+}
+
+impl ThrottleAdc {
+    pub fn new(adc: pac::ADC, dma: pac::DMA1, _acc1: PA0<Analog>, _acc2: PA1<Analog>) -> Self {
+        // Safety: Only the enable bits for ADC and DMA1 are modified
+        let rcc = unsafe { &*pac::RCC::ptr() };
+        rcc.apb2enr.modify(|_, w| w.adcen().enabled());
+        rcc.ahbenr.modify(|_, w| w.dmaen().enabled());
+
+        // ADC clock must be below 14 MHz, PCLK / 4 -> 12 MHz
+        adc.cfgr2.write(|w| w.ckmode().pclk_div4());
+
+        // Calibrate with the ADC disabled
+        if adc.cr.read().aden().is_enabled() {
+            adc.cr.modify(|_, w| w.addis().disable());
+            while adc.cr.read().aden().is_enabled() {}
+        }
+        adc.cr.modify(|_, w| w.adcal().start_calibration());
+        while adc.cr.read().adcal().is_calibrating() {}
+
+        adc.cfgr1.write(|w| {
+            w.res()
+                .twelve_bit()
+                .align()
+                .right()
+                .scandir()
+                .upward()
+                .cont()
+                .continuous()
+                .dmaen()
+                .enabled()
+                .dmacfg()
+                .circular()
+                .ovrmod()
+                .overwritten()
+        });
+        // The filtered inputs have a high source impedance, use the longest sample time
+        adc.smpr.write(|w| w.smp().cycles239_5());
+        adc.chselr.write(|w| {
+            w.chsel0()
+                .selected()
+                .chsel1()
+                .selected()
+                .chsel17()
+                .selected()
+        });
+        adc.ccr.modify(|_, w| w.vrefen().enabled());
+
+        // Safety: The register is reserved for channel selection on the STM32F09x
+        unsafe {
+            let cselr = DMA1_CSELR.read_volatile();
+            DMA1_CSELR.write_volatile((cselr & !0x0f) | DMA1_C1S_ADC);
+        }
+
+        let ch1 = &dma.ch1;
+        // Safety: The buffer is only ever written by the DMA, reads are volatile
+        unsafe {
+            ch1.par.write(|w| w.bits(adc.dr.as_ptr() as u32));
+            ch1.mar.write(|w| w.bits(addr_of_mut!(BUFFER) as u32));
+        }
+        ch1.ndtr
+            .write(|w| w.ndt().bits((OVERSAMPLING * CHANNELS) as u16));
+        ch1.cr.write(|w| {
+            w.dir()
+                .from_peripheral()
+                .circ()
+                .enabled()
+                .minc()
+                .enabled()
+                .psize()
+                .bits16()
+                .msize()
+                .bits16()
+                .pl()
+                .high()
+                .en()
+                .enabled()
+        });
+
+        adc.isr.write(|w| w.adrdy().clear());
+        adc.cr.modify(|_, w| w.aden().enabled());
+        while adc.isr.read().adrdy().is_not_ready() {}
+        adc.cr.modify(|_, w| w.adstart().start_conversion());
+
+        // Safety: Factory calibration value in system memory
+        let vrefint_cal = unsafe { VREFINT_CAL.read_volatile() };
+
+        Self {
+            adc,
+            dma,
+            vref: VrefCalibration::new(vrefint_cal, VREFINT_CAL_MILLIVOLT, (3000, 3600)),
+            acc1: AdcChannelCheck::new(RAIL_MARGIN, STUCK_LIMIT),
+            acc2: AdcChannelCheck::new(RAIL_MARGIN, STUCK_LIMIT),
+        }
+    }
+
+    /// Read both sensor inputs as millivolts at the pins. At least half of the buffer must
+    /// have been refreshed since the previous read, which also makes sure that it has been
+    /// written before the first read.
+    pub fn read(&mut self) -> Result<(u16, u16), AdcError> {
+        let isr = self.dma.isr.read();
+        if !isr.htif1().is_half() && !isr.tcif1().is_complete() {
+            return Err(AdcError::NoConversion);
+        }
+        self.dma.ifcr.write(|w| w.cgif1().clear());
+
+        if self.adc.isr.read().ovr().is_overrun() {
+            self.adc.isr.write(|w| w.ovr().clear());
+        }
+
+        let mut acc1 = [0u16; OVERSAMPLING];
+        let mut acc2 = [0u16; OVERSAMPLING];
+        let mut vrefint_sum = 0u32;
+
+        for i in 0..OVERSAMPLING {
+            // Safety: Volatile read of a buffer that is concurrently written by the DMA
+            let sequence = unsafe {
+                addr_of!(BUFFER)
+                    .cast::<[u16; CHANNELS]>()
+                    .add(i)
+                    .read_volatile()
+            };
+            acc1[i] = sequence[0];
+            acc2[i] = sequence[1];
+            vrefint_sum += sequence[2] as u32;
+        }
+
+        self.vref.vdda(vrefint_sum, OVERSAMPLING as u32)?;
+
+        let acc1 = self.vref.millivolt(self.acc1.check(&acc1)?, vrefint_sum)?;
+        let acc2 = self.vref.millivolt(self.acc2.check(&acc2)?, vrefint_sum)?;
+
+        Ok((acc1, acc2))
+    }
+}
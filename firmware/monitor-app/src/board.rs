@@ -1,36 +1,78 @@
-use core::convert::Infallible;
-
 use common::{
-    ev_can::EvCanFrame,
-    monitor_message::{MonitorToMain, MONITOR_MESSAGE_BUFFER_SIZE},
+    adc::AdcError,
+    ev_can::{
+        EvCanError, EvCanFrame, INVERTER_STATUS_ID, INVERTER_TEMPERATURE_ID, TORQUE_REQUEST_ID,
+        VCM_KEEPALIVE1_ID, VCM_KEEPALIVE2_ID,
+    },
+    monitor_message::{
+        MainToMonitor, MonitorToMain, ResetCause, SelfTest, SerialStats,
+        MONITOR_MESSAGE_BUFFER_SIZE,
+    },
+    monitor_serial::MonitorSerialPort,
+};
+use embedded_io::ErrorKind;
+use heapless::Deque;
+use monitor_app::{
+    checkpoint::Checkpoint,
+    fault_log::FaultLog,
+    monitor_board::{CanStatus, MonitorBoard},
 };
 use stm32f0xx_hal::{
     can::{
-        bxcan::{filter::BankConfig, Can, Data, Frame, Id, StandardId, TransmitStatus},
+        bxcan::{filter::ListEntry16, Can, Id, StandardId},
         CanInstance,
     },
     gpio::{
-        gpioa::{PA2, PA3, PA5},
-        gpiob::{PB8, PB9},
-        gpioc::PC13,
-        Alternate, GpioExt, Input, Output, PullUp, PushPull, AF1, AF4,
+        gpioa::PA5,
+        gpiob::{PB0, PB1, PB2, PB8, PB9},
+        Alternate, GpioExt, Input, Output, PullDown, PushPull, AF4,
     },
     pac::{self, USART2},
     prelude::*,
     rcc::{HSEBypassMode, Rcc},
-    serial::Serial,
+    serial::{Rx, Serial, Tx},
+};
+
+use crate::{
+    adc::ThrottleAdc, cycle_counter::Tim2CycleCounter, eeprom_flash::EepromFlash,
+    self_tests::SelfTests, watchdog::Watchdog,
 };
 
+/// Ratio of the voltage divider between the throttle sensors and the ADC pins as
+/// `(numerator, denominator)`, sensor voltage = pin voltage * numerator / denominator
+const ACC_DIVIDER: (u32, u32) = (2, 1);
+
+/// Room for two messages to main
+const SERIAL_TX_BUFFER: usize = 2 * MONITOR_MESSAGE_BUFFER_SIZE;
+
+pub type EvCan = Can<CanInstance<PB9<Alternate<AF4>>, PB8<Alternate<AF4>>>>;
+
 pub struct Board {
     pub led: PA5<Output<PushPull>>,
-    pub button: PC13<Input<PullUp>>,
-    pub ev_can: Can<CanInstance<PB9<Alternate<AF4>>, PB8<Alternate<AF4>>>>,
+    pub ev_can: EvCan,
     pub rcc: Rcc,
-    pub serial: Serial<USART2, PA2<Alternate<AF1>>, PA3<Alternate<AF1>>>,
+    pub high_side_enable: PB0<Output<PushPull>>,
+    pub high_side_sense: PB1<Input<PullDown>>,
+    pub brake: PB2<Input<PullDown>>,
+    pub serial: MonitorSerialPort<MONITOR_MESSAGE_BUFFER_SIZE, SerialTx, SerialRx>,
+    pub throttle_adc: ThrottleAdc,
+    pub watchdog: Watchdog,
+    pub cycle_counter: Tim2CycleCounter,
+    pub self_tests: SelfTests,
+    pub fault_log: Option<FaultLog<EepromFlash>>,
+    pub reset_cause: ResetCause,
+    pub startup_self_test: Result<(), SelfTest>,
 }
 
 impl Board {
+    /// Send buffered serial data while the transmitter is ready, called between ticks
+    pub fn pump_serial(&mut self) {
+        self.serial.tx().pump();
+    }
+
     pub fn new(mut dp: pac::Peripherals) -> Self {
+        let reset_cause = take_reset_cause(&dp.RCC);
+
         let mut rcc = dp
             .RCC
             .configure()
@@ -41,11 +83,18 @@ impl Board {
 
         let gpioa = dp.GPIOA.split(&mut rcc);
         let gpiob = dp.GPIOB.split(&mut rcc);
-        let gpioc = dp.GPIOC.split(&mut rcc);
 
         cortex_m::interrupt::free(|cs| {
             let led = gpioa.pa5.into_push_pull_output(cs);
-            let button = gpioc.pc13.into_pull_up_input(cs);
+            // Starts low, the monitor enables the high side drive once it is operational
+            let high_side_enable = gpiob.pb0.into_push_pull_output(cs);
+            // Status of the high side output, reads low if the line is open
+            let high_side_sense = gpiob.pb1.into_pull_down_input(cs);
+            // Brake switch, reads released if the line is open
+            let brake = gpiob.pb2.into_pull_down_input(cs);
+
+            let acc1 = gpioa.pa0.into_analog(cs);
+            let acc2 = gpioa.pa1.into_analog(cs);
 
             let can_rx = gpiob.pb8.into_alternate_af4(cs);
             let can_tx = gpiob.pb9.into_alternate_af4(cs);
@@ -53,65 +102,272 @@ impl Board {
             let serial_rx = gpioa.pa3.into_alternate_af1(cs);
             let serial_tx = gpioa.pa2.into_alternate_af1(cs);
 
+            // The monitor only observes the EV CAN bus, silent mode makes sure that it
+            // never transmits anything, not even acknowledge bits.
             let can = CanInstance::new(dp.CAN, can_tx, can_rx, &mut rcc);
-            let ev_can = Can::builder(can)
+            let mut ev_can = Can::builder(can)
                 .set_bit_timing(0x001c0005)
-                .set_loopback(true)
-                .enable();
+                .set_silent(true)
+                .leave_disabled();
+
+            let filter = |id| ListEntry16::data_frames_with_id(StandardId::new(id).unwrap());
+            ev_can
+                .modify_filters()
+                .enable_bank(
+                    0,
+                    [
+                        filter(TORQUE_REQUEST_ID),
+                        filter(VCM_KEEPALIVE1_ID),
+                        filter(VCM_KEEPALIVE2_ID),
+                        filter(INVERTER_STATUS_ID),
+                    ],
+                )
+                .enable_bank(1, [filter(INVERTER_TEMPERATURE_ID); 4]);
+
+            nb::block!(ev_can.enable_non_blocking()).ok();
+
+            let (tx, rx) =
+                Serial::usart2(dp.USART2, (serial_tx, serial_rx), 115_200.bps(), &mut rcc).split();
+            let serial = MonitorSerialPort::new(
+                SerialTx {
+                    tx,
+                    buffer: Deque::new(),
+                },
+                SerialRx { rx, next: None },
+            );
 
-            let serial = Serial::usart2(dp.USART2, (serial_tx, serial_rx), 115_200.bps(), &mut rcc);
+            let throttle_adc = ThrottleAdc::new(dp.ADC, dp.DMA1, acc1, acc2);
+
+            let mut self_tests = SelfTests::new(dp.TIM14);
+            let startup_self_test = self_tests.startup();
+
+            // Opening the log may erase flash, which takes too long once the watchdogs run
+            let fault_log = FaultLog::new(EepromFlash::new(dp.FLASH)).ok();
+
+            // Start the watchdogs last, the main loop has to be running before they elapse
+            let watchdog = Watchdog::new(dp.IWDG, dp.WWDG);
+
+            let cycle_counter = Tim2CycleCounter::new(dp.TIM2);
 
             Self {
                 led,
-                button,
+                high_side_enable,
+                high_side_sense,
+                brake,
                 ev_can,
                 rcc,
                 serial,
+                throttle_adc,
+                watchdog,
+                cycle_counter,
+                self_tests,
+                fault_log,
+                reset_cause,
+                startup_self_test,
             }
         })
     }
+}
 
-    pub fn enable_led(&mut self) {
-        self.led.set_high().ok();
+impl MonitorBoard for Board {
+    type Flash = EepromFlash;
+
+    fn set_led(&mut self, on: bool) {
+        if on {
+            self.led.set_high().ok();
+        } else {
+            self.led.set_low().ok();
+        }
     }
 
-    pub fn disable_led(&mut self) {
-        self.led.set_low().ok();
+    fn set_high_side_enable(&mut self, enable: bool) {
+        if enable {
+            self.high_side_enable.set_high().ok();
+        } else {
+            self.high_side_enable.set_low().ok();
+        }
     }
 
-    pub fn is_button_pressed(&self) -> bool {
-        self.button.is_low().unwrap_or(false)
+    fn read_high_side(&mut self) -> (bool, bool) {
+        // The output driver of PB0 has no input in the HAL, the level on the pin is read from
+        // the input data register
+        let gpiob = unsafe { &*pac::GPIOB::ptr() };
+        let enable = gpiob.idr.read().idr0().bit_is_set();
+
+        (enable, self.high_side_sense.is_high().unwrap_or(false))
     }
 
-    pub fn enable_can_bank(&mut self, index: u8, mask: impl Into<BankConfig>) {
-        let mut filters = self.ev_can.modify_filters();
-        filters.enable_bank(index, mask);
+    fn read_brake(&mut self) -> bool {
+        self.brake.is_high().unwrap_or(false)
     }
 
-    // TODO: This should not be needed once CAN is in silent mode
-    pub fn ev_can_send(&mut self, frame: EvCanFrame) -> nb::Result<TransmitStatus, Infallible> {
-        self.ev_can.transmit(&frame.into())
+    fn read_throttle_sensors(&mut self) -> Result<(u16, u16), AdcError> {
+        let (acc1, acc2) = self.throttle_adc.read()?;
+
+        Ok((sensor_millivolt(acc1), sensor_millivolt(acc2)))
     }
 
-    pub fn ev_can_receive(&mut self) -> Option<EvCanFrame> {
-        if let Ok(rx_frame) = self.ev_can.receive() {
-            Ok(EvCanFrame::try_from(rx_frame).ok()?)
-        } else {
-            None
+    fn ev_can_receive(&mut self) -> Option<Result<EvCanFrame, EvCanError>> {
+        let rx_frame = self.ev_can.receive().ok()?;
+
+        match (rx_frame.id(), rx_frame.data()) {
+            (Id::Standard(id), Some(data)) => Some(EvCanFrame::from_raw(id.as_raw(), data)),
+            (_, None) => Some(Err(EvCanError::NoData)),
+            _ => Some(Err(EvCanError::UnknownFrame)),
+        }
+    }
+
+    fn ev_can_status(&mut self) -> CanStatus {
+        // The HAL has no access to the error status register
+        let can = unsafe { &*pac::CAN::ptr() };
+        let esr = can.esr.read();
+
+        CanStatus {
+            tec: esr.tec().bits(),
+            rec: esr.rec().bits(),
+            bus_off: esr.boff().bit_is_set(),
+        }
+    }
+
+    fn serial_send(&mut self, message: &MonitorToMain) {
+        self.serial.send(message).ok();
+    }
+
+    fn serial_receive(&mut self) -> Option<MainToMonitor> {
+        self.serial.poll()
+    }
+
+    fn serial_stats(&self) -> SerialStats {
+        self.serial.stats()
+    }
+
+    fn self_test(&mut self) -> Result<(), SelfTest> {
+        self.self_tests.cycle()
+    }
+
+    fn checkpoint(&mut self, checkpoint: Checkpoint) {
+        self.watchdog.checkpoint(checkpoint);
+    }
+
+    fn service_watchdog(&mut self) {
+        self.watchdog.service();
+    }
+
+    fn fault_log(&mut self) -> Option<&mut FaultLog<EepromFlash>> {
+        self.fault_log.as_mut()
+    }
+}
+
+/// `embedded-io` adapter for the transmit half of the serial port. Writes only fill a buffer,
+/// `pump()` hands the bytes to the peripheral without blocking.
+pub struct SerialTx {
+    tx: Tx<USART2>,
+    buffer: Deque<u8, SERIAL_TX_BUFFER>,
+}
+
+impl SerialTx {
+    fn pump(&mut self) {
+        while let Some(&byte) = self.buffer.front() {
+            if self.tx.write(byte).is_err() {
+                break;
+            }
+            self.buffer.pop_front();
         }
     }
+}
+
+impl embedded_io::ErrorType for SerialTx {
+    type Error = ErrorKind;
+}
 
-    pub fn serial_send(&mut self, message: &MonitorToMain) {
-        let bytes: heapless::Vec<u8, MONITOR_MESSAGE_BUFFER_SIZE> =
-            postcard::to_vec(message).unwrap();
+impl embedded_io::Write for SerialTx {
+    /// A message that doesn't fit the buffer is dropped as a whole
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        if SERIAL_TX_BUFFER - self.buffer.len() < buf.len() {
+            return Err(ErrorKind::OutOfMemory);
+        }
 
-        for b in bytes {
-            nb::block!(self.serial.write(b)).unwrap();
+        for &byte in buf {
+            self.buffer.push_back(byte).ok();
         }
+        Ok(buf.len())
     }
 
-    /// Read the throttle sensor ADC inputs in millivolts
-    pub fn read_throttle_sensors(&self) -> (u16, u16) {
-        (1500, 3500)
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        while !self.buffer.is_empty() {
+            self.pump();
+        }
+        nb::block!(self.tx.flush()).map_err(|_| ErrorKind::Other)
     }
 }
+
+/// `embedded-io` adapter for the receive half of the serial port. The peripheral can't be
+/// checked for received data without reading it, so `read_ready()` keeps the byte it read.
+pub struct SerialRx {
+    rx: Rx<USART2>,
+    next: Option<u8>,
+}
+
+impl embedded_io::ErrorType for SerialRx {
+    type Error = ErrorKind;
+}
+
+impl embedded_io::Read for SerialRx {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        buf[0] = match self.next.take() {
+            Some(byte) => byte,
+            None => nb::block!(self.rx.read()).map_err(|_| ErrorKind::Other)?,
+        };
+        Ok(1)
+    }
+}
+
+impl embedded_io::ReadReady for SerialRx {
+    fn read_ready(&mut self) -> Result<bool, Self::Error> {
+        if self.next.is_none() {
+            match self.rx.read() {
+                Ok(byte) => self.next = Some(byte),
+                Err(nb::Error::WouldBlock) => {}
+                Err(nb::Error::Other(_)) => return Err(ErrorKind::Other),
+            }
+        }
+
+        Ok(self.next.is_some())
+    }
+}
+
+/// Read the cause of the latest reset and clear the reset flags. A watchdog reset also
+/// pulls NRST, so the watchdog flags have to be checked before the pin flag.
+fn take_reset_cause(rcc: &pac::RCC) -> ResetCause {
+    let csr = rcc.csr.read();
+
+    let reset_cause = if csr.iwdgrstf().bit_is_set() {
+        ResetCause::IndependentWatchdog
+    } else if csr.wwdgrstf().bit_is_set() {
+        ResetCause::WindowWatchdog
+    } else if csr.porrstf().bit_is_set() {
+        ResetCause::PowerOn
+    } else if csr.sftrstf().bit_is_set() {
+        ResetCause::Software
+    } else if csr.lpwrrstf().bit_is_set() {
+        ResetCause::LowPower
+    } else if csr.pinrstf().bit_is_set() {
+        ResetCause::Pin
+    } else {
+        ResetCause::Unknown
+    };
+
+    rcc.csr.modify(|_, w| w.rmvf().clear());
+
+    reset_cause
+}
+
+/// Scale a pin voltage to the sensor voltage in front of the divider
+fn sensor_millivolt(pin_millivolt: u16) -> u16 {
+    let millivolt = pin_millivolt as u32 * ACC_DIVIDER.0 / ACC_DIVIDER.1;
+    millivolt.min(u16::MAX as u32) as u16
+}
@@ -0,0 +1,169 @@
+//! Plausibility bounds for the calibration pushed by main. The bounds are part of the monitor
+//! image, a calibration outside of them is rejected even if its CRC is intact.
+
+use common::calibration::{Calibration, CalibrationBlock};
+
+/// Range the throttle sensors can be calibrated to, outside of it a sensor is shorted or open
+const SENSOR_MILLIVOLT: (u16, u16) = (250, 4750);
+/// Smallest difference between released and pressed
+const MIN_SENSOR_SPAN: u16 = 500;
+/// 10 % of the throttle range
+const MAX_THROTTLE_TOLERANCE: u16 = 6554;
+/// Longest qualification times and timeouts in cycles
+const MAX_THROTTLE_TIMEOUT: u16 = 50;
+const MAX_TORQUE_FRAME_TIMEOUT: u16 = 50;
+/// In `TorqueRequest` frames, 50 ms
+const MAX_TORQUE_ERROR_TIMEOUT: u16 = 5;
+/// 20 % of the throttle range, the pedal is not released beyond it
+const MAX_UA_PEDAL_RELEASED: u16 = 13107;
+/// The unintended acceleration thresholds have to catch a runaway within a car length
+const MAX_UA_RPM_RISE: u16 = 1000;
+const MAX_UA_DRIVE_TORQUE: u16 = 500;
+const MAX_UA_TIME: u16 = 500;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum CalibrationError {
+    /// Bad version or CRC
+    Corrupt,
+    /// A value is outside of the plausibility bounds
+    Implausible,
+}
+
+/// The calibration of a block that can be used by the monitor
+pub fn check(block: &CalibrationBlock) -> Result<Calibration, CalibrationError> {
+    if !block.valid() {
+        return Err(CalibrationError::Corrupt);
+    }
+
+    let calibration = block.calibration;
+    let (sensor1, sensor2) = (calibration.throttle_sensor1, calibration.throttle_sensor2);
+
+    let plausible = sensor_plausible(sensor1)
+        && sensor_plausible(sensor2)
+        && short_detected(sensor1, sensor2, calibration.throttle_tolerance)
+        && calibration.throttle_tolerance <= MAX_THROTTLE_TOLERANCE
+        && (1..=MAX_THROTTLE_TIMEOUT).contains(&calibration.throttle_timeout)
+        && (1..=MAX_TORQUE_FRAME_TIMEOUT).contains(&calibration.torque_frame_timeout)
+        && (1..=MAX_TORQUE_ERROR_TIMEOUT).contains(&calibration.torque_error_timeout)
+        && calibration.ua_pedal_released <= MAX_UA_PEDAL_RELEASED
+        && (1..=MAX_UA_RPM_RISE).contains(&calibration.ua_rpm_rise)
+        && (1..=MAX_UA_DRIVE_TORQUE).contains(&calibration.ua_drive_torque)
+        && (1..=MAX_UA_TIME).contains(&calibration.ua_time);
+
+    if !plausible {
+        return Err(CalibrationError::Implausible);
+    }
+
+    Ok(calibration)
+}
+
+fn sensor_plausible((released, pressed): (u16, u16)) -> bool {
+    let (min, max) = SENSOR_MILLIVOLT;
+
+    released >= min && pressed <= max && pressed >= released.saturating_add(MIN_SENSOR_SPAN)
+}
+
+/// A short between the sensors puts the same voltage on both. It is detected if the voltage
+/// is outside of one of the ranges, or if the positions of the sensors differ by more than the
+/// tolerance at that voltage. Ranges that don't overlap always detect it. Overlapping ones, like
+/// those of a ratiometric dual track pedal, only if the difference keeps its sign and stays
+/// above the tolerance across the overlap.
+fn short_detected(sensor1: (u16, u16), sensor2: (u16, u16), tolerance: u16) -> bool {
+    let low = sensor1.0.max(sensor2.0);
+    let high = sensor1.1.min(sensor2.1);
+    if low > high {
+        return true;
+    }
+
+    // The difference is linear in the voltage, so it is smallest at one end of the overlap
+    let difference = |millivolt| position(sensor1, millivolt) - position(sensor2, millivolt);
+    let (low, high) = (difference(low), difference(high));
+
+    low.signum() == high.signum() && low.abs().min(high.abs()) > tolerance as i32
+}
+
+/// Position of a sensor in the full `0..=u16::MAX` range, for a voltage inside its range
+fn position((released, pressed): (u16, u16), millivolt: u16) -> i32 {
+    (millivolt - released) as i32 * u16::MAX as i32 / (pressed - released) as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CALIBRATION: Calibration = Calibration {
+        throttle_sensor1: (1000, 2000),
+        throttle_sensor2: (3000, 4000),
+        throttle_tolerance: 1500,
+        throttle_timeout: 10,
+        torque_frame_timeout: 10,
+        torque_error_timeout: 3,
+        ua_pedal_released: 3277,
+        ua_rpm_rise: 200,
+        ua_drive_torque: 50,
+        ua_time: 20,
+    };
+
+    fn check_changed(
+        change: impl FnOnce(&mut Calibration),
+    ) -> Result<Calibration, CalibrationError> {
+        let mut calibration = CALIBRATION;
+        change(&mut calibration);
+        check(&CalibrationBlock::new(calibration))
+    }
+
+    #[test]
+    fn plausible() {
+        assert_eq!(check(&CalibrationBlock::new(CALIBRATION)), Ok(CALIBRATION));
+        assert!(check_changed(|c| c.throttle_sensor1 = (3000, 4000)).is_err());
+        assert!(check_changed(|c| {
+            c.throttle_sensor1 = (3000, 4000);
+            c.throttle_sensor2 = (1000, 2000);
+        })
+        .is_ok());
+
+        // Overlapping ranges of a ratiometric dual track pedal
+        assert!(check_changed(|c| c.throttle_sensor2 = (500, 1000)).is_ok());
+        assert!(check_changed(|c| c.throttle_sensor2 = (900, 1900)).is_ok());
+    }
+
+    #[test]
+    fn car() {
+        let block = CalibrationBlock::new(common::calibration::CALIBRATION);
+        assert_eq!(check(&block), Ok(block.calibration));
+    }
+
+    #[test]
+    fn corrupt() {
+        let mut block = CalibrationBlock::new(CALIBRATION);
+        block.calibration.throttle_timeout = 11;
+
+        assert_eq!(check(&block), Err(CalibrationError::Corrupt));
+    }
+
+    #[test]
+    fn implausible() {
+        let changes: [fn(&mut Calibration); 11] = [
+            // Reversed or too narrow sensor ranges
+            |c| c.throttle_sensor1 = (2000, 1000),
+            |c| c.throttle_sensor2 = (3000, 3400),
+            // Sensor ranges that reach the rails
+            |c| c.throttle_sensor1 = (100, 2000),
+            // A short between the sensors that stays within the tolerance
+            |c| c.throttle_sensor2 = (1010, 2010),
+            // Sensors that cross each other
+            |c| c.throttle_sensor2 = (1200, 1800),
+            |c| c.throttle_tolerance = 10000,
+            |c| c.throttle_timeout = 0,
+            |c| c.torque_error_timeout = 6,
+            // A pedal that is released at half travel
+            |c| c.ua_pedal_released = 32768,
+            |c| c.ua_drive_torque = 0,
+            |c| c.ua_time = 5000,
+        ];
+
+        for change in changes {
+            assert_eq!(check_changed(change), Err(CalibrationError::Implausible));
+        }
+    }
+}
@@ -0,0 +1,82 @@
+/// Monitors and self tests that have to complete their cycle before the watchdogs are
+/// refreshed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Checkpoint {
+    Throttle = 0b0001,
+    Torque = 0b0010,
+    MainApp = 0b0100,
+    SelfTest = 0b1000,
+    Inverter = 0b1_0000,
+    HighSide = 0b10_0000,
+    Direction = 0b100_0000,
+    UnintendedAcceleration = 0b1000_0000,
+}
+
+const ALL_CHECKPOINTS: u8 = Checkpoint::Throttle as u8
+    | Checkpoint::Torque as u8
+    | Checkpoint::MainApp as u8
+    | Checkpoint::SelfTest as u8
+    | Checkpoint::Inverter as u8
+    | Checkpoint::HighSide as u8
+    | Checkpoint::Direction as u8
+    | Checkpoint::UnintendedAcceleration as u8;
+
+/// Keeps track of which monitors that have completed during the current cycle
+pub struct Checkpoints(u8);
+
+impl Checkpoints {
+    pub fn new() -> Self {
+        Self(0)
+    }
+
+    pub fn complete(&mut self, checkpoint: Checkpoint) {
+        self.0 |= checkpoint as u8;
+    }
+
+    /// Returns `true` if all monitors have completed and starts a new cycle
+    pub fn end_cycle(&mut self) -> bool {
+        let completed = self.0 == ALL_CHECKPOINTS;
+        self.0 = 0;
+        completed
+    }
+}
+
+impl Default for Checkpoints {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checkpoints() {
+        let mut checkpoints = Checkpoints::new();
+
+        assert!(!checkpoints.end_cycle());
+
+        checkpoints.complete(Checkpoint::Throttle);
+        checkpoints.complete(Checkpoint::Torque);
+        checkpoints.complete(Checkpoint::MainApp);
+        checkpoints.complete(Checkpoint::SelfTest);
+        checkpoints.complete(Checkpoint::Inverter);
+        checkpoints.complete(Checkpoint::HighSide);
+        checkpoints.complete(Checkpoint::Direction);
+        checkpoints.complete(Checkpoint::UnintendedAcceleration);
+        assert!(checkpoints.end_cycle());
+
+        // Every cycle starts from scratch
+        checkpoints.complete(Checkpoint::Throttle);
+        checkpoints.complete(Checkpoint::MainApp);
+        checkpoints.complete(Checkpoint::SelfTest);
+        checkpoints.complete(Checkpoint::Inverter);
+        checkpoints.complete(Checkpoint::HighSide);
+        checkpoints.complete(Checkpoint::Direction);
+        checkpoints.complete(Checkpoint::UnintendedAcceleration);
+        assert!(!checkpoints.end_cycle());
+        checkpoints.complete(Checkpoint::Torque);
+        assert!(!checkpoints.end_cycle());
+    }
+}
@@ -0,0 +1,31 @@
+use common::scheduler::CycleCounter;
+use stm32f0xx_hal::pac;
+
+/// The Cortex-M0 has no DWT cycle counter, the 32 bit TIM2 counts the core clock instead.
+/// The timer is only read once it is running, so the counter can be copied freely.
+#[derive(Clone, Copy)]
+pub struct Tim2CycleCounter;
+
+impl Tim2CycleCounter {
+    pub fn new(tim2: pac::TIM2) -> Self {
+        // Safety: Only the enable bit for TIM2 is modified
+        let rcc = unsafe { &*pac::RCC::ptr() };
+        rcc.apb1enr.modify(|_, w| w.tim2en().enabled());
+
+        // TIM2 runs on PCLK, which is the core clock
+        tim2.psc.write(|w| w.psc().bits(0));
+        tim2.arr.write(|w| w.arr().bits(u32::MAX));
+        tim2.egr.write(|w| w.ug().update());
+        tim2.cr1.modify(|_, w| w.cen().enabled());
+
+        Self
+    }
+}
+
+impl CycleCounter for Tim2CycleCounter {
+    fn cycles(&self) -> u32 {
+        // Safety: Read only access to the counter
+        let tim2 = unsafe { &*pac::TIM2::ptr() };
+        tim2.cnt.read().bits()
+    }
+}
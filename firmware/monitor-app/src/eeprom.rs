@@ -0,0 +1,345 @@
+//! EEPROM emulated in two pages of flash.
+//!
+//! Every write appends a record with a key, the data and a CRC to the active page, the latest
+//! valid record of a key holds its value. When the active page is full `compact()` copies the
+//! latest records to the other page and erases the active one, so writes and erases are spread
+//! over both pages. A record or compaction that is interrupted by a reset is detected by its
+//! CRC or missing page marker and the previous value is kept.
+
+use common::crc32::{calc_crc32, generate_lookup};
+
+const CRC32_LOOKUP: [u32; 256] = generate_lookup(0x04c1_1db7);
+
+pub const PAGE_SIZE: usize = 2048;
+/// Data bytes stored with every key
+pub const DATA_SIZE: usize = 14;
+
+/// Sequence number and marker of a page, followed by the records
+const HEADER_SIZE: usize = 8;
+/// Key, data and CRC32
+const RECORD_SIZE: usize = 2 + DATA_SIZE + 4;
+const RECORDS: usize = (PAGE_SIZE - HEADER_SIZE) / RECORD_SIZE;
+/// Written when a page has received all records and is the active page
+const ACTIVE: u16 = 0x5aa5;
+
+pub type Data = [u8; DATA_SIZE];
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum FlashError {
+    Program,
+    Erase,
+}
+
+/// Two pages of flash, offsets are relative to the start of the first page
+pub trait Flash {
+    fn read(&self, offset: usize, bytes: &mut [u8]);
+
+    fn erase_page(&mut self, page: usize) -> Result<(), FlashError>;
+
+    /// Program half words at an even offset, the flash has to be erased
+    fn program(&mut self, offset: usize, half_words: &[u16]) -> Result<(), FlashError>;
+}
+
+impl<F: Flash> Flash for &mut F {
+    fn read(&self, offset: usize, bytes: &mut [u8]) {
+        (**self).read(offset, bytes)
+    }
+
+    fn erase_page(&mut self, page: usize) -> Result<(), FlashError> {
+        (**self).erase_page(page)
+    }
+
+    fn program(&mut self, offset: usize, half_words: &[u16]) -> Result<(), FlashError> {
+        (**self).program(offset, half_words)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum EepromError {
+    Flash(FlashError),
+    /// The active page has no room left, see `compact()`
+    Full,
+}
+
+impl From<FlashError> for EepromError {
+    fn from(value: FlashError) -> Self {
+        Self::Flash(value)
+    }
+}
+
+pub struct Eeprom<F: Flash> {
+    flash: F,
+    page: usize,
+    sequence: u32,
+    /// Index of the next free record in the active page
+    next: usize,
+}
+
+impl<F: Flash> Eeprom<F> {
+    /// Find the active page and finish or roll back an interrupted compaction. Pages without
+    /// a valid active page are formatted. Erases flash, so this can take a while.
+    pub fn new(mut flash: F) -> Result<Self, EepromError> {
+        let (page, sequence) = match (header(&flash, 0), header(&flash, 1)) {
+            (Some(first), Some(second)) if first > second => (0, first),
+            (_, Some(second)) => (1, second),
+            (Some(first), None) => (0, first),
+            (None, None) => {
+                flash.erase_page(0)?;
+                flash.program(0, &[0, 0, ACTIVE])?;
+                (0, 0)
+            }
+        };
+
+        if !blank(&flash, 1 - page) {
+            flash.erase_page(1 - page)?;
+        }
+
+        let next = (0..RECORDS)
+            .rev()
+            .find(|&index| !blank_record(&flash, page, index))
+            .map_or(0, |index| index + 1);
+
+        Ok(Self {
+            flash,
+            page,
+            sequence,
+            next,
+        })
+    }
+
+    /// Latest value of a key
+    pub fn read(&self, key: u16) -> Option<Data> {
+        // The CRC is only checked for records with the right key, reading has to fit a tick
+        (0..self.next)
+            .rev()
+            .filter(|&index| {
+                let mut bytes = [0u8; 2];
+                self.flash.read(record_offset(self.page, index), &mut bytes);
+                u16::from_le_bytes(bytes) == key
+            })
+            .find_map(|index| record(&self.flash, self.page, index))
+            .map(|(_, data)| data)
+    }
+
+    pub fn write(&mut self, key: u16, data: &Data) -> Result<(), EepromError> {
+        if self.next >= RECORDS {
+            return Err(EepromError::Full);
+        }
+
+        // The record is used even if programming fails, it can't be programmed again
+        let offset = record_offset(self.page, self.next);
+        self.next += 1;
+        self.flash.program(offset, &encode(key, data))?;
+
+        Ok(())
+    }
+
+    /// Number of records that can be written before the page is full
+    pub fn free(&self) -> usize {
+        RECORDS - self.next
+    }
+
+    /// Move the latest value of every key for which `keep` returns `true` to the other page and
+    /// erase the active page. Erases flash, so this can take a while.
+    pub fn compact(&mut self, mut keep: impl FnMut(u16, &Data) -> bool) -> Result<(), EepromError> {
+        let target = 1 - self.page;
+        if !blank(&self.flash, target) {
+            self.flash.erase_page(target)?;
+        }
+
+        let sequence = self.sequence.wrapping_add(1);
+        let base = target * PAGE_SIZE;
+        self.flash
+            .program(base, &[sequence as u16, (sequence >> 16) as u16])?;
+
+        let mut copied = 0;
+        for index in (0..self.next).rev() {
+            let Some((key, data)) = record(&self.flash, self.page, index) else {
+                continue;
+            };
+
+            let newer = (0..copied).any(|copy| {
+                record(&self.flash, target, copy).is_some_and(|(copy_key, _)| copy_key == key)
+            });
+            if newer || !keep(key, &data) {
+                continue;
+            }
+
+            self.flash
+                .program(record_offset(target, copied), &encode(key, &data))?;
+            copied += 1;
+        }
+
+        self.flash.program(base + 4, &[ACTIVE])?;
+        self.flash.erase_page(self.page)?;
+
+        self.page = target;
+        self.sequence = sequence;
+        self.next = copied;
+
+        Ok(())
+    }
+}
+
+/// Sequence number of a page if it is the active page
+fn header<F: Flash>(flash: &F, page: usize) -> Option<u32> {
+    let mut bytes = [0u8; HEADER_SIZE];
+    flash.read(page * PAGE_SIZE, &mut bytes);
+
+    let marker = u16::from_le_bytes([bytes[4], bytes[5]]);
+    (marker == ACTIVE).then(|| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn blank<F: Flash>(flash: &F, page: usize) -> bool {
+    let mut bytes = [0u8; 32];
+    (0..PAGE_SIZE).step_by(bytes.len()).all(|offset| {
+        flash.read(page * PAGE_SIZE + offset, &mut bytes);
+        bytes.iter().all(|byte| *byte == 0xff)
+    })
+}
+
+fn blank_record<F: Flash>(flash: &F, page: usize, index: usize) -> bool {
+    let mut bytes = [0u8; RECORD_SIZE];
+    flash.read(record_offset(page, index), &mut bytes);
+    bytes.iter().all(|byte| *byte == 0xff)
+}
+
+fn record_offset(page: usize, index: usize) -> usize {
+    page * PAGE_SIZE + HEADER_SIZE + index * RECORD_SIZE
+}
+
+/// Key and data of a record with a valid CRC
+fn record<F: Flash>(flash: &F, page: usize, index: usize) -> Option<(u16, Data)> {
+    let mut bytes = [0u8; RECORD_SIZE];
+    flash.read(record_offset(page, index), &mut bytes);
+
+    let (content, crc) = bytes.split_at(RECORD_SIZE - 4);
+    if calc_crc32(content, &CRC32_LOOKUP) != u32::from_le_bytes(crc.try_into().unwrap()) {
+        return None;
+    }
+
+    let key = u16::from_le_bytes([content[0], content[1]]);
+    Some((key, content[2..].try_into().unwrap()))
+}
+
+fn encode(key: u16, data: &Data) -> [u16; RECORD_SIZE / 2] {
+    let mut bytes = [0u8; RECORD_SIZE];
+    bytes[..2].copy_from_slice(&key.to_le_bytes());
+    bytes[2..2 + DATA_SIZE].copy_from_slice(data);
+    let crc = calc_crc32(&bytes[..2 + DATA_SIZE], &CRC32_LOOKUP);
+    bytes[2 + DATA_SIZE..].copy_from_slice(&crc.to_le_bytes());
+
+    let mut half_words = [0u16; RECORD_SIZE / 2];
+    for (half_word, bytes) in half_words.iter_mut().zip(bytes.chunks_exact(2)) {
+        *half_word = u16::from_le_bytes([bytes[0], bytes[1]]);
+    }
+    half_words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_board::MockFlash;
+
+    fn data(value: u8) -> Data {
+        [value; DATA_SIZE]
+    }
+
+    #[test]
+    fn format() {
+        let mut flash = MockFlash::new();
+        flash.bytes.fill(0x17);
+
+        let eeprom = Eeprom::new(&mut flash).unwrap();
+        assert_eq!(eeprom.read(1), None);
+        assert_eq!(eeprom.free(), RECORDS);
+        assert_eq!(flash.erases, [1, 1]);
+    }
+
+    #[test]
+    fn read_write() {
+        let mut flash = MockFlash::new();
+
+        let mut eeprom = Eeprom::new(&mut flash).unwrap();
+        eeprom.write(1, &data(1)).unwrap();
+        eeprom.write(2, &data(2)).unwrap();
+        eeprom.write(1, &data(3)).unwrap();
+        assert_eq!(eeprom.read(1), Some(data(3)));
+        assert_eq!(eeprom.read(2), Some(data(2)));
+        assert_eq!(eeprom.read(3), None);
+
+        // The values are kept over a reset
+        let eeprom = Eeprom::new(&mut flash).unwrap();
+        assert_eq!(eeprom.read(1), Some(data(3)));
+        assert_eq!(eeprom.free(), RECORDS - 3);
+    }
+
+    #[test]
+    fn compact() {
+        let mut flash = MockFlash::new();
+        let mut eeprom = Eeprom::new(&mut flash).unwrap();
+
+        for value in 0..RECORDS {
+            eeprom
+                .write((value % 4) as u16, &data(value as u8))
+                .unwrap();
+        }
+        assert_eq!(eeprom.write(1, &data(0)), Err(EepromError::Full));
+
+        // Key 3 is dropped
+        eeprom.compact(|key, _| key != 3).unwrap();
+        assert_eq!(eeprom.free(), RECORDS - 3);
+        for key in 0..3 {
+            let last = (RECORDS - 1 - (RECORDS - 1 - key) % 4) as u8;
+            assert_eq!(eeprom.read(key as u16), Some(data(last)));
+        }
+        assert_eq!(eeprom.read(3), None);
+
+        // Writes and erases alternate between the pages
+        eeprom.compact(|_, _| true).unwrap();
+        eeprom.write(3, &data(3)).unwrap();
+        let eeprom = Eeprom::new(&mut flash).unwrap();
+        assert_eq!(eeprom.read(3), Some(data(3)));
+        assert_eq!(eeprom.free(), RECORDS - 4);
+        assert_eq!(flash.erases, [2, 1]);
+    }
+
+    #[test]
+    fn interrupted_write() {
+        let mut flash = MockFlash::new();
+        let mut eeprom = Eeprom::new(&mut flash).unwrap();
+        eeprom.write(1, &data(1)).unwrap();
+
+        // Reset halfway through a record
+        flash.power_loss_after(4);
+        let mut eeprom = Eeprom::new(&mut flash).unwrap();
+        assert!(eeprom.write(1, &data(2)).is_err());
+
+        flash.power_loss_after(usize::MAX);
+        let mut eeprom = Eeprom::new(&mut flash).unwrap();
+        assert_eq!(eeprom.read(1), Some(data(1)));
+        eeprom.write(1, &data(3)).unwrap();
+        assert_eq!(eeprom.read(1), Some(data(3)));
+    }
+
+    #[test]
+    fn interrupted_compact() {
+        for budget in 0..40 {
+            let mut flash = MockFlash::new();
+            let mut eeprom = Eeprom::new(&mut flash).unwrap();
+            eeprom.write(1, &data(1)).unwrap();
+            eeprom.write(2, &data(2)).unwrap();
+            eeprom.write(1, &data(3)).unwrap();
+
+            flash.power_loss_after(budget);
+            let mut eeprom = Eeprom::new(&mut flash).unwrap();
+            let _ = eeprom.compact(|_, _| true);
+
+            // Whatever step the compaction got to, the latest values are found
+            flash.power_loss_after(usize::MAX);
+            let eeprom = Eeprom::new(&mut flash).unwrap();
+            assert_eq!(eeprom.read(1), Some(data(3)), "budget {budget}");
+            assert_eq!(eeprom.read(2), Some(data(2)), "budget {budget}");
+        }
+    }
+}
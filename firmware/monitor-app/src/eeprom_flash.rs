@@ -0,0 +1,92 @@
+use core::ptr::addr_of;
+
+use monitor_app::eeprom::{Flash, FlashError, PAGE_SIZE};
+use stm32f0xx_hal::pac;
+
+const KEY1: u32 = 0x4567_0123;
+const KEY2: u32 = 0xcdef_89ab;
+
+extern "C" {
+    /// Start of the two pages reserved for the emulated EEPROM, see `memory.x`
+    static __eeprom: u8;
+}
+
+/// The flash pages of the emulated EEPROM. The flash is unlocked for every operation and
+/// locked again afterwards. Code keeps running from flash, so the CPU stalls while a page is
+/// erased.
+pub struct EepromFlash {
+    flash: pac::FLASH,
+}
+
+impl EepromFlash {
+    pub fn new(flash: pac::FLASH) -> Self {
+        Self { flash }
+    }
+
+    fn base(&self) -> usize {
+        addr_of!(__eeprom) as usize
+    }
+
+    fn unlock(&mut self) {
+        if self.flash.cr.read().lock().is_locked() {
+            self.flash.keyr.write(|w| w.fkeyr().bits(KEY1));
+            self.flash.keyr.write(|w| w.fkeyr().bits(KEY2));
+        }
+    }
+
+    fn lock(&mut self) {
+        self.flash
+            .cr
+            .modify(|_, w| w.pg().clear_bit().per().clear_bit().lock().set_bit());
+    }
+
+    /// Wait for the current operation and clear its flags
+    fn wait(&mut self) -> bool {
+        while self.flash.sr.read().bsy().is_active() {}
+
+        let sr = self.flash.sr.read();
+        let ok = sr.pgerr().is_no_error() && sr.wrprt().is_no_error();
+        self.flash
+            .sr
+            .write(|w| w.eop().set_bit().pgerr().set_bit().wrprt().set_bit());
+        ok
+    }
+}
+
+impl Flash for EepromFlash {
+    fn read(&self, offset: usize, bytes: &mut [u8]) {
+        let start = (self.base() + offset) as *const u8;
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            // Safety: The EEPROM pages are reserved for this in `memory.x`
+            *byte = unsafe { start.add(i).read_volatile() };
+        }
+    }
+
+    fn erase_page(&mut self, page: usize) -> Result<(), FlashError> {
+        let address = self.base() + page * PAGE_SIZE;
+
+        self.unlock();
+        self.flash.cr.modify(|_, w| w.per().set_bit());
+        self.flash.ar.write(|w| w.far().bits(address as u32));
+        self.flash.cr.modify(|_, w| w.strt().set_bit());
+        let ok = self.wait();
+        self.lock();
+
+        ok.then_some(()).ok_or(FlashError::Erase)
+    }
+
+    fn program(&mut self, offset: usize, half_words: &[u16]) -> Result<(), FlashError> {
+        let start = (self.base() + offset) as *mut u16;
+
+        self.unlock();
+        self.flash.cr.modify(|_, w| w.pg().set_bit());
+        let ok = half_words.iter().enumerate().all(|(i, half_word)| {
+            // Safety: The EEPROM pages are reserved for this in `memory.x`, offset is even
+            unsafe { start.add(i).write_volatile(*half_word) };
+            self.wait()
+        });
+        self.lock();
+
+        ok.then_some(()).ok_or(FlashError::Program)
+    }
+}
@@ -0,0 +1,364 @@
+use common::monitor_message::{FaultEvent, FaultLogEntry, FAULT_LOG_ENTRIES};
+use heapless::Deque;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::eeprom::{Data, Eeprom, EepromError, Flash, DATA_SIZE};
+
+/// Epoch, boot counter and the slot of the next entry
+const KEY_STATE: u16 = 0x0001;
+/// Entries are kept in a ring of slots
+const KEY_ENTRY: u16 = 0x0100;
+/// Occurrence counter of every event, see `counter_key()`
+const KEY_COUNTER: u16 = 0x1000;
+
+/// Free records needed for the writes of one boot, the log is compacted at startup when there
+/// are fewer. Compaction erases flash and is too slow to run once the watchdogs are started.
+const RESERVE: usize = 16;
+/// Entries waiting to be written, a reset cause and a few faults
+const QUEUE: usize = 4;
+
+type State = (u16, u16, u8);
+type StoredEntry = (u16, u16, u32, FaultEvent);
+type Counter = (u16, u16);
+
+/// Record of the queued entry that is programmed next
+#[derive(Clone, Copy)]
+enum Step {
+    Entry,
+    Counter,
+    State,
+}
+
+/// Log of the latest monitor errors and reset causes, kept in emulated EEPROM. Entries are
+/// stamped with the boot counter and the time since startup.
+///
+/// Every value is stored with the epoch of the log. Clearing the log only starts a new epoch,
+/// values of older epochs are ignored and dropped when the EEPROM is compacted.
+///
+/// An entry takes three records. Programming the 10 half words of a record stalls the CPU for
+/// up to 0.7 ms, so entries are queued by `log()` and written one record per call of
+/// `write_record()`.
+pub struct FaultLog<F: Flash> {
+    eeprom: Eeprom<F>,
+    epoch: u16,
+    boot: u16,
+    /// Slot of the next entry
+    head: u8,
+    queue: Deque<(FaultEvent, u32), QUEUE>,
+    step: Step,
+}
+
+impl<F: Flash> FaultLog<F> {
+    /// Open the log and count the boot. Has to be called before the watchdogs are started as
+    /// it may erase flash.
+    pub fn new(flash: F) -> Result<Self, EepromError> {
+        let eeprom = Eeprom::new(flash)?;
+        let (epoch, boot, head) = eeprom
+            .read(KEY_STATE)
+            .and_then(|data| decode::<State>(&data))
+            .unwrap_or((0, 0, 0));
+
+        let mut log = Self {
+            eeprom,
+            epoch,
+            boot: boot.wrapping_add(1),
+            head: head % FAULT_LOG_ENTRIES,
+            queue: Deque::new(),
+            step: Step::Entry,
+        };
+
+        if log.eeprom.free() < RESERVE {
+            let epoch = log.epoch;
+            log.eeprom
+                .compact(|key, data| key == KEY_STATE || decode::<u16>(data) == Some(epoch))?;
+        }
+        log.write_state()?;
+
+        Ok(log)
+    }
+
+    pub fn boot(&self) -> u16 {
+        self.boot
+    }
+
+    /// Queue an entry, the oldest entry is overwritten when the log is full. The entry is
+    /// dropped when the queue is full.
+    pub fn log(&mut self, event: FaultEvent, millis: u32) {
+        let _ = self.queue.push_back((event, millis));
+    }
+
+    /// Entries are waiting to be written
+    pub fn pending(&self) -> bool {
+        !self.queue.is_empty()
+    }
+
+    /// Program the next record of the queued entries. An entry is only visible once all of its
+    /// records are written, a record that fails is skipped.
+    pub fn write_record(&mut self) -> Result<(), EepromError> {
+        let Some(&(event, millis)) = self.queue.front() else {
+            return Ok(());
+        };
+
+        let (result, step) = match self.step {
+            Step::Entry => {
+                let entry: StoredEntry = (self.epoch, self.boot, millis, event);
+                let key = KEY_ENTRY + self.head as u16;
+                (self.eeprom.write(key, &encode(&entry)), Step::Counter)
+            }
+            Step::Counter => {
+                let count = self.count(&event).saturating_add(1);
+                let counter: Counter = (self.epoch, count);
+                (
+                    self.eeprom.write(counter_key(&event), &encode(&counter)),
+                    Step::State,
+                )
+            }
+            Step::State => {
+                self.head = (self.head + 1) % FAULT_LOG_ENTRIES;
+                self.queue.pop_front();
+                (self.write_state(), Step::Entry)
+            }
+        };
+        self.step = step;
+
+        result
+    }
+
+    /// Entry with the given age, 0 is the latest
+    pub fn entry(&self, index: u8) -> Option<FaultLogEntry> {
+        if index >= FAULT_LOG_ENTRIES {
+            return None;
+        }
+
+        let slot = (self.head + FAULT_LOG_ENTRIES - 1 - index) % FAULT_LOG_ENTRIES;
+        let (epoch, boot, millis, event) = self
+            .eeprom
+            .read(KEY_ENTRY + slot as u16)
+            .and_then(|data| decode::<StoredEntry>(&data))?;
+        if epoch != self.epoch {
+            return None;
+        }
+
+        Some(FaultLogEntry {
+            boot,
+            millis,
+            event,
+            count: self.count(&event),
+        })
+    }
+
+    /// Remove all entries, including the queued ones, and reset the counters. The boot counter
+    /// is kept.
+    pub fn clear(&mut self) -> Result<(), EepromError> {
+        self.epoch = self.epoch.wrapping_add(1);
+        self.head = 0;
+        self.queue.clear();
+        self.step = Step::Entry;
+        self.write_state()
+    }
+
+    fn count(&self, event: &FaultEvent) -> u16 {
+        match self
+            .eeprom
+            .read(counter_key(event))
+            .and_then(|data| decode::<Counter>(&data))
+        {
+            Some((epoch, count)) if epoch == self.epoch => count,
+            _ => 0,
+        }
+    }
+
+    fn write_state(&mut self) -> Result<(), EepromError> {
+        let state: State = (self.epoch, self.boot, self.head);
+        self.eeprom.write(KEY_STATE, &encode(&state))
+    }
+}
+
+/// Counters are keyed by the serialized event, the event and error variants are packed into
+/// the lower 12 bits
+fn counter_key(event: &FaultEvent) -> u16 {
+    let mut bytes = [0u8; 3];
+    let _ = postcard::to_slice(event, &mut bytes);
+
+    KEY_COUNTER
+        | (bytes[0] as u16 & 0x01) << 11
+        | (bytes[1] as u16 & 0x3f) << 5
+        | (bytes[2] as u16 & 0x1f)
+}
+
+fn encode<T: Serialize>(value: &T) -> Data {
+    let mut data = [0u8; DATA_SIZE];
+    // All stored values fit the data of a record
+    let _ = postcard::to_slice(value, &mut data);
+    data
+}
+
+fn decode<T: DeserializeOwned>(data: &Data) -> Option<T> {
+    postcard::from_bytes(data).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_board::MockFlash;
+    use common::monitor_message::{MonitorError, ResetCause, SelfTest};
+
+    const PING: FaultEvent = FaultEvent::Error(MonitorError::PingError);
+    const WATCHDOG: FaultEvent = FaultEvent::Reset(ResetCause::WindowWatchdog);
+
+    /// Log an entry and write all of its records
+    fn write(log: &mut FaultLog<&mut MockFlash>, event: FaultEvent, millis: u32) {
+        log.log(event, millis);
+        for _ in 0..3 {
+            log.write_record().unwrap();
+        }
+        assert!(!log.pending());
+    }
+
+    #[test]
+    fn log() {
+        let mut flash = MockFlash::new();
+
+        let mut log = FaultLog::new(&mut flash).unwrap();
+        assert_eq!(log.boot(), 1);
+        assert_eq!(log.entry(0), None);
+        write(&mut log, PING, 1234);
+
+        let mut log = FaultLog::new(&mut flash).unwrap();
+        assert_eq!(log.boot(), 2);
+        write(&mut log, WATCHDOG, 0);
+        write(&mut log, PING, 50);
+
+        assert_eq!(
+            log.entry(0),
+            Some(FaultLogEntry {
+                boot: 2,
+                millis: 50,
+                event: PING,
+                count: 2,
+            })
+        );
+        assert_eq!(
+            log.entry(1),
+            Some(FaultLogEntry {
+                boot: 2,
+                millis: 0,
+                event: WATCHDOG,
+                count: 1,
+            })
+        );
+        assert_eq!(log.entry(2).unwrap().millis, 1234);
+        assert_eq!(log.entry(3), None);
+        assert_eq!(log.entry(FAULT_LOG_ENTRIES), None);
+    }
+
+    #[test]
+    fn ring() {
+        let mut flash = MockFlash::new();
+        let mut log = FaultLog::new(&mut flash).unwrap();
+
+        for millis in 0..FAULT_LOG_ENTRIES as u32 + 3 {
+            write(&mut log, PING, millis);
+        }
+
+        assert_eq!(log.entry(0).unwrap().millis, FAULT_LOG_ENTRIES as u32 + 2);
+        assert_eq!(log.entry(FAULT_LOG_ENTRIES - 1).unwrap().millis, 3);
+        // The counter keeps counting when entries are overwritten
+        assert_eq!(log.entry(0).unwrap().count, FAULT_LOG_ENTRIES as u16 + 3);
+    }
+
+    #[test]
+    fn counters() {
+        let events = [
+            PING,
+            WATCHDOG,
+            FaultEvent::Reset(ResetCause::IndependentWatchdog),
+            FaultEvent::Error(MonitorError::SelfTestError(SelfTest::Ram)),
+            FaultEvent::Error(MonitorError::SelfTestError(SelfTest::Clock)),
+        ];
+        let mut flash = MockFlash::new();
+        let mut log = FaultLog::new(&mut flash).unwrap();
+
+        for (i, event) in events.iter().enumerate() {
+            for _ in 0..=i {
+                write(&mut log, *event, 0);
+            }
+        }
+
+        for (i, event) in events.iter().enumerate() {
+            assert_eq!(log.count(event), i as u16 + 1);
+        }
+    }
+
+    #[test]
+    fn queue() {
+        let mut flash = MockFlash::new();
+        let mut log = FaultLog::new(&mut flash).unwrap();
+
+        for millis in 0..QUEUE as u32 + 1 {
+            log.log(PING, millis);
+        }
+        // Nothing to write while the entry is incomplete
+        log.write_record().unwrap();
+        log.write_record().unwrap();
+        assert_eq!(log.entry(0), None);
+        log.write_record().unwrap();
+        assert_eq!(log.entry(0).unwrap().millis, 0);
+
+        // The entry that didn't fit in the queue is dropped
+        while log.pending() {
+            log.write_record().unwrap();
+        }
+        assert_eq!(log.entry(0).unwrap().millis, QUEUE as u32 - 1);
+        assert_eq!(log.entry(0).unwrap().count, QUEUE as u16);
+
+        // Clearing drops the queue
+        log.log(WATCHDOG, 10);
+        log.write_record().unwrap();
+        log.clear().unwrap();
+        assert!(!log.pending());
+        assert_eq!(log.entry(0), None);
+    }
+
+    #[test]
+    fn clear() {
+        let mut flash = MockFlash::new();
+        let mut log = FaultLog::new(&mut flash).unwrap();
+        write(&mut log, PING, 10);
+        log.clear().unwrap();
+        assert_eq!(log.entry(0), None);
+
+        let mut log = FaultLog::new(&mut flash).unwrap();
+        assert_eq!(log.entry(0), None);
+        write(&mut log, PING, 20);
+        assert_eq!(log.entry(0).unwrap().count, 1);
+        assert_eq!(log.entry(1), None);
+        assert_eq!(log.boot(), 2);
+    }
+
+    #[test]
+    fn many_boots() {
+        let mut flash = MockFlash::new();
+
+        // The log is compacted at startup and never runs out of room
+        for boot in 1..=500u16 {
+            let mut log = FaultLog::new(&mut flash).unwrap();
+            assert_eq!(log.boot(), boot);
+            write(&mut log, WATCHDOG, 0);
+            write(&mut log, PING, boot as u32);
+            if boot == 300 {
+                log.clear().unwrap();
+            }
+        }
+
+        let log = FaultLog::new(&mut flash).unwrap();
+        assert_eq!(log.entry(0).unwrap().millis, 500);
+        assert_eq!(log.entry(0).unwrap().count, 200);
+        assert_eq!(log.entry(FAULT_LOG_ENTRIES - 1).unwrap().boot, 493);
+
+        // Both pages wear evenly, the first one is also erased when formatting
+        let [first, second] = flash.erases;
+        assert!(first.abs_diff(second) <= 2);
+        assert!(first > 10);
+    }
+}
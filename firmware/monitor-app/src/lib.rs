@@ -0,0 +1,17 @@
+//! Hardware independent parts of the monitor MCU application. Everything in here builds
+//! for the host as well, the binary adds the board support for the STM32F091.
+#![no_std]
+
+pub mod calibration;
+pub mod checkpoint;
+pub mod eeprom;
+pub mod fault_log;
+pub mod monitor;
+pub mod monitor_board;
+pub mod monitors;
+pub mod reaction;
+pub mod schedule;
+pub mod self_test;
+
+#[cfg(test)]
+mod mock_board;
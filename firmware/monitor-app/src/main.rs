@@ -1,14 +1,25 @@
 #![no_std]
 #![no_main]
 
+mod adc;
 mod board;
-mod monitors;
+mod cycle_counter;
+mod eeprom_flash;
+mod self_tests;
+mod watchdog;
 
-use common::{monitor_message::*, throttle::Throttle};
+use common::torque_envelope::TORQUE_ENVELOPE;
 use cortex_m_rt::entry;
-use monitors::{MainAppMonitor, ThrottleMonitor, TorqueMonitor};
+use monitor_app::{
+    monitor::Monitor,
+    monitors::{
+        DirectionLimits, DirectionMonitor, HighSideMonitor, InverterLimits, InverterMonitor,
+        MainAppMonitor,
+    },
+    schedule,
+};
 use panic_halt as _; // you can put a breakpoint on `rust_begin_unwind` to catch panics
-use stm32f0xx_hal::{can::bxcan::filter::Mask32, pac, prelude::*, timers::Timer};
+use stm32f0xx_hal::{pac, prelude::*, timers::Timer};
 
 #[entry]
 fn main() -> ! {
@@ -17,54 +28,48 @@ fn main() -> ! {
 
     let mut board = board::Board::new(dp);
 
-    board.enable_can_bank(0, Mask32::accept_all());
-
-    let mut led_enabled = false;
-
     let mut timer = Timer::syst(cp.SYST, 1000.hz(), &board.rcc);
 
-    let msg = MonitorToMain {
-        ping: 12345,
-        state: MonitorState::Operational,
+    let inverter_limits = InverterLimits {
+        max_rpm: 10500,
+        overspeed_time: 20,
+        dc_millivolt: (200_000, 410_000),
+        dc_voltage_time: 50,
+        max_motor_temperature: 150,
+        max_inverter_temperature: 85,
+        temperature_time: 1000,
+        status_timeout: 50,
+        temperature_timeout: 500,
     };
 
-    let throttle = Throttle::new((1000, 2000), (3000, 4000), 1500);
-    let mut throttle_monitor = ThrottleMonitor::new(&throttle, 10);
-    let mut torque_monitor = TorqueMonitor::new(10, 10);
-    let mut main_app_monitor = MainAppMonitor::new(10);
+    let mut monitor = Monitor::new(
+        // The throttle and torque monitors are calibrated by main
+        &TORQUE_ENVELOPE,
+        InverterMonitor::new(&inverter_limits),
+        // Main's claim is up to one message old and the output takes a few ms to switch
+        HighSideMonitor::new(50),
+        DirectionMonitor::new(DirectionLimits {
+            torque_deadband: 50,
+            regen_rpm: 200,
+            // The gear is up to one message old
+            debounce: 50,
+        }),
+        // Counts messages to main, every 10 ms
+        MainAppMonitor::new(10),
+        board.reset_cause,
+        board.startup_self_test,
+    );
+
+    let mut scheduler = schedule::scheduler();
+    let cycle_counter = board.cycle_counter;
 
     loop {
-        if board.is_button_pressed() {
-            board.ev_can_send(EvCanCommand::SetLed(led_enabled)).ok();
-            led_enabled = !led_enabled;
+        // Overruns are counted by the scheduler
+        schedule::tick(&mut scheduler, &cycle_counter, &mut monitor, &mut board).ok();
 
-            // wait until the button is released
-            while board.is_button_pressed() {
-                cortex_m::asm::nop();
-            }
+        // Serial data is sent in the time left until the next tick
+        while timer.wait().is_err() {
+            board.pump_serial();
         }
-
-        board.serial_send(&msg);
-
-        // Monitor main application
-        main_app_monitor.tick().ok(); // TODO: Manage errors.
-
-        // Monitor acceleration pedal
-        let (acc_sensor1, acc_sensor2) = board.read_throttle_sensors();
-        let throttle_position = match throttle_monitor.check(acc_sensor1, acc_sensor2) {
-            Err(_err) => {
-                // TODO: goto safe state
-                0
-            }
-            Ok(pos) => pos,
-        };
-
-        // Monitor torque request
-        torque_monitor.tick().ok(); // TODO: manage error
-        if let Some(frame) = board.ev_can_receive() {
-            torque_monitor.frame(throttle_position, &frame).ok(); // TODO: Manage error
-        };
-
-        nb::block!(timer.wait()).unwrap();
     }
 }
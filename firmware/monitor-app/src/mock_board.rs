@@ -0,0 +1,184 @@
+extern crate std;
+
+use std::{collections::VecDeque, vec, vec::Vec};
+
+use common::{
+    adc::AdcError,
+    ev_can::{EvCanError, EvCanFrame},
+    monitor_message::{MainToMonitor, MonitorToMain, SelfTest, SerialStats},
+};
+
+use crate::{
+    checkpoint::{Checkpoint, Checkpoints},
+    eeprom::{Flash, FlashError, PAGE_SIZE},
+    fault_log::FaultLog,
+    monitor_board::{CanStatus, MonitorBoard},
+};
+
+/// Board for tests on the host. Inputs are set by the test, outputs are recorded.
+pub struct MockBoard {
+    pub led: bool,
+    pub high_side_enable: bool,
+    pub high_side_sense: bool,
+    pub brake: bool,
+    pub throttle_sensors: Result<(u16, u16), AdcError>,
+    pub ev_can: VecDeque<Result<EvCanFrame, EvCanError>>,
+    pub ev_can_status: CanStatus,
+    pub received: Option<MainToMonitor>,
+    pub sent: Vec<MonitorToMain>,
+    pub serial_stats: SerialStats,
+    pub self_test: Result<(), SelfTest>,
+    pub fault_log: Option<FaultLog<MockFlash>>,
+    /// Number of times the watchdog was serviced without all checkpoints being completed
+    pub missed_checkpoints: usize,
+    checkpoints: Checkpoints,
+}
+
+impl MockBoard {
+    pub fn new() -> Self {
+        Self {
+            led: false,
+            high_side_enable: false,
+            high_side_sense: false,
+            brake: false,
+            throttle_sensors: Err(AdcError::NoConversion),
+            ev_can: VecDeque::new(),
+            ev_can_status: CanStatus::default(),
+            received: None,
+            sent: Vec::new(),
+            serial_stats: SerialStats::default(),
+            self_test: Ok(()),
+            fault_log: FaultLog::new(MockFlash::new()).ok(),
+            missed_checkpoints: 0,
+            checkpoints: Checkpoints::new(),
+        }
+    }
+}
+
+impl MonitorBoard for MockBoard {
+    type Flash = MockFlash;
+
+    fn set_led(&mut self, on: bool) {
+        self.led = on;
+    }
+
+    fn set_high_side_enable(&mut self, enable: bool) {
+        self.high_side_enable = enable;
+    }
+
+    fn read_high_side(&mut self) -> (bool, bool) {
+        (self.high_side_enable, self.high_side_sense)
+    }
+
+    fn read_brake(&mut self) -> bool {
+        self.brake
+    }
+
+    fn read_throttle_sensors(&mut self) -> Result<(u16, u16), AdcError> {
+        self.throttle_sensors
+    }
+
+    fn ev_can_receive(&mut self) -> Option<Result<EvCanFrame, EvCanError>> {
+        self.ev_can.pop_front()
+    }
+
+    fn ev_can_status(&mut self) -> CanStatus {
+        self.ev_can_status
+    }
+
+    fn serial_send(&mut self, message: &MonitorToMain) {
+        self.sent.push(message.clone());
+    }
+
+    fn serial_receive(&mut self) -> Option<MainToMonitor> {
+        self.received.take()
+    }
+
+    fn serial_stats(&self) -> SerialStats {
+        self.serial_stats
+    }
+
+    fn self_test(&mut self) -> Result<(), SelfTest> {
+        self.self_test
+    }
+
+    fn checkpoint(&mut self, checkpoint: Checkpoint) {
+        self.checkpoints.complete(checkpoint);
+    }
+
+    fn service_watchdog(&mut self) {
+        if !self.checkpoints.end_cycle() {
+            self.missed_checkpoints += 1;
+        }
+    }
+
+    fn fault_log(&mut self) -> Option<&mut FaultLog<MockFlash>> {
+        self.fault_log.as_mut()
+    }
+}
+
+/// Two pages of flash that behave like the F091 flash, programming a half word that isn't
+/// erased fails. A reset during programming or erasing is simulated by making every operation
+/// after a number of half words fail.
+pub struct MockFlash {
+    pub bytes: Vec<u8>,
+    /// Number of times each page has been erased
+    pub erases: [usize; 2],
+    budget: usize,
+}
+
+impl MockFlash {
+    pub fn new() -> Self {
+        Self {
+            bytes: vec![0xff; 2 * PAGE_SIZE],
+            erases: [0, 0],
+            budget: usize::MAX,
+        }
+    }
+
+    /// Let the given number of half words be programmed or pages erased before the power is
+    /// lost, `usize::MAX` restores the power
+    pub fn power_loss_after(&mut self, operations: usize) {
+        self.budget = operations;
+    }
+
+    fn spend(&mut self) -> bool {
+        match self.budget {
+            0 => false,
+            usize::MAX => true,
+            _ => {
+                self.budget -= 1;
+                true
+            }
+        }
+    }
+}
+
+impl Flash for MockFlash {
+    fn read(&self, offset: usize, bytes: &mut [u8]) {
+        bytes.copy_from_slice(&self.bytes[offset..offset + bytes.len()]);
+    }
+
+    fn erase_page(&mut self, page: usize) -> Result<(), FlashError> {
+        if !self.spend() {
+            return Err(FlashError::Erase);
+        }
+
+        self.bytes[page * PAGE_SIZE..(page + 1) * PAGE_SIZE].fill(0xff);
+        self.erases[page] += 1;
+        Ok(())
+    }
+
+    fn program(&mut self, offset: usize, half_words: &[u16]) -> Result<(), FlashError> {
+        assert!(offset.is_multiple_of(2));
+
+        for (i, half_word) in half_words.iter().enumerate() {
+            let address = offset + 2 * i;
+            if !self.spend() || self.bytes[address..address + 2] != [0xff, 0xff] {
+                return Err(FlashError::Program);
+            }
+            self.bytes[address..address + 2].copy_from_slice(&half_word.to_le_bytes());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,1326 @@
+use common::{
+    calibration::{Calibration, CalibrationBlock},
+    ev_can::EvCanError,
+    monitor_message::{
+        Diagnostics, FaultEvent, FaultLogCommand, FaultLogReply, FaultLogRequest, FaultLogResponse,
+        MonitorError, MonitorState, MonitorToMain, Reaction, ResetCause, SelfTest,
+    },
+    throttle::Throttle,
+    torque_envelope::TorqueEnvelope,
+};
+
+use crate::{
+    calibration,
+    checkpoint::Checkpoint,
+    monitor_board::{CanStatus, MonitorBoard},
+    monitors::{
+        DirectionMonitor, HighSideMonitor, InverterError, InverterMonitor, MainAppMonitor,
+        MonitorError as CheckError, ThrottleMonitor, TorqueMonitor, UnintendedAccelerationLimits,
+        UnintendedAccelerationMonitor,
+    },
+    reaction::{Escalation, ACK_TIMEOUT},
+};
+
+/// The monitor application. `step()` runs one cycle of all monitors against a board and is
+/// called every millisecond, `link_step()` exchanges messages with main at a lower rate, see
+/// `schedule`. Every fault requires a reaction from main, see `reaction`. The fault with the
+/// most severe reaction is latched until the next reset and reported to main, which has to
+/// acknowledge it. The high side drive is disabled when HV has to be disconnected.
+///
+/// The throttle, torque and unintended acceleration monitors are set up from the calibration
+/// block that main pushes at startup, the monitor stays uncalibrated with the outputs disabled
+/// until it receives a valid block.
+///
+/// Every fault that becomes the reported one and any reset cause other than power on are
+/// queued for the fault log and written by `link_step()`, main can read and clear the log
+/// through the serial link. Every
+/// message to main also carries one kind of diagnostics in turn.
+pub struct Monitor<'a, const P: usize, const R: usize> {
+    torque_envelope: &'a TorqueEnvelope<P, R>,
+    calibrated: Option<Calibrated<'a, P, R>>,
+    inverter_monitor: InverterMonitor<'a>,
+    high_side_monitor: HighSideMonitor,
+    direction_monitor: DirectionMonitor,
+    main_app_monitor: MainAppMonitor,
+    escalation: Escalation,
+    message: MonitorToMain,
+    /// Time since startup
+    millis: u32,
+    /// Latest fault written to the fault log
+    logged_error: Option<MonitorError>,
+    ev_can_status: CanStatus,
+    bus_off_count: u16,
+    loop_timing: Diagnostics,
+    /// Kind of diagnostics in the latest message
+    diagnostics_turn: u8,
+}
+
+impl<'a, const P: usize, const R: usize> Monitor<'a, P, R> {
+    pub fn new(
+        torque_envelope: &'a TorqueEnvelope<P, R>,
+        inverter_monitor: InverterMonitor<'a>,
+        high_side_monitor: HighSideMonitor,
+        direction_monitor: DirectionMonitor,
+        main_app_monitor: MainAppMonitor,
+        reset_cause: ResetCause,
+        startup_self_test: Result<(), SelfTest>,
+    ) -> Self {
+        let mut monitor = Self {
+            torque_envelope,
+            calibrated: None,
+            inverter_monitor,
+            high_side_monitor,
+            direction_monitor,
+            main_app_monitor,
+            escalation: Escalation::new(ACK_TIMEOUT),
+            message: MonitorToMain {
+                ping: 0,
+                state: MonitorState::Uncalibrated,
+                reset_cause,
+                fault_log: None,
+                calibration_crc: None,
+                diagnostics: Diagnostics::Loop {
+                    max: 0,
+                    jitter: 0,
+                    overruns: 0,
+                },
+            },
+            millis: 0,
+            logged_error: None,
+            ev_can_status: CanStatus::default(),
+            bus_off_count: 0,
+            loop_timing: Diagnostics::Loop {
+                max: 0,
+                jitter: 0,
+                overruns: 0,
+            },
+            diagnostics_turn: 0,
+        };
+
+        if let Err(test) = startup_self_test {
+            monitor.error(MonitorError::SelfTestError(test));
+        }
+        monitor
+    }
+
+    pub fn state(&self) -> MonitorState {
+        self.message.state
+    }
+
+    /// Timing of the loop in core cycles for the diagnostics, see `Diagnostics::Loop`
+    pub fn loop_timing(&mut self, max: u32, jitter: u32, overruns: u32) {
+        self.loop_timing = Diagnostics::Loop {
+            max,
+            jitter,
+            overruns,
+        };
+    }
+
+    pub fn step<B: MonitorBoard>(&mut self, board: &mut B) {
+        // Self tests
+        if let Err(test) = board.self_test() {
+            self.error(MonitorError::SelfTestError(test));
+        }
+        board.checkpoint(Checkpoint::SelfTest);
+
+        // Monitor acceleration pedal, once calibrated
+        let throttle_check =
+            self.calibrated
+                .as_mut()
+                .map(|calibrated| match board.read_throttle_sensors() {
+                    Ok((acc_sensor1, acc_sensor2)) => {
+                        calibrated.throttle_monitor.check(acc_sensor1, acc_sensor2)
+                    }
+                    Err(err) => calibrated.throttle_monitor.sensor_fault(err),
+                });
+        let throttle_position = match throttle_check {
+            Some(Err(_)) => {
+                self.error(MonitorError::AcceleratorError);
+                0
+            }
+            Some(Ok(pos)) => pos,
+            None => 0,
+        };
+        board.checkpoint(Checkpoint::Throttle);
+
+        // Monitor torque request, once calibrated. The inverter frames are always used.
+        let mut torque_check = self
+            .calibrated
+            .as_mut()
+            .map_or(Ok(()), |calibrated| calibrated.torque_monitor.tick());
+        while let Some(frame) = board.ev_can_receive() {
+            if let Ok(frame) = &frame {
+                self.inverter_monitor.frame(frame);
+                self.direction_monitor.frame(frame);
+            }
+            let Some(calibrated) = self.calibrated.as_mut() else {
+                continue;
+            };
+
+            if let Ok(frame) = &frame {
+                calibrated.unintended_acceleration_monitor.frame(frame);
+            }
+            let check = match frame {
+                Ok(frame) => calibrated.torque_monitor.frame(throttle_position, &frame),
+                Err(EvCanError::BadCrc) => calibrated.torque_monitor.bad_frame(),
+                Err(_) => Ok(()),
+            };
+            torque_check = torque_check.and(check);
+        }
+        if torque_check.is_err() {
+            self.error(MonitorError::TorqueRequestError);
+        }
+        board.checkpoint(Checkpoint::Torque);
+
+        // Monitor the sign of the torque request against the gear
+        if self.direction_monitor.tick().is_err() {
+            self.error(MonitorError::DirectionError);
+        }
+        board.checkpoint(Checkpoint::Direction);
+
+        // Monitor the motor while braking, once calibrated
+        let brake = board.read_brake();
+        if let Some(calibrated) = self.calibrated.as_mut() {
+            if calibrated
+                .unintended_acceleration_monitor
+                .tick(throttle_position, brake)
+                .is_err()
+            {
+                self.error(MonitorError::UnintendedAccelerationError);
+            }
+        }
+        board.checkpoint(Checkpoint::UnintendedAcceleration);
+
+        // Bus off events are short, the controller recovers by itself
+        let ev_can_status = board.ev_can_status();
+        if ev_can_status.bus_off && !self.ev_can_status.bus_off {
+            self.bus_off_count = self.bus_off_count.saturating_add(1);
+            self.error(MonitorError::EvCanBusOffError);
+        }
+        self.ev_can_status = ev_can_status;
+
+        // Monitor inverter health
+        if let Err(err) = self.inverter_monitor.tick() {
+            self.error(match err {
+                CheckError::Inverter(InverterError::Overspeed) => MonitorError::OverspeedError,
+                CheckError::Inverter(InverterError::DcVoltage) => MonitorError::DcVoltageError,
+                CheckError::Inverter(InverterError::MotorTemperature) => {
+                    MonitorError::MotorTemperatureError
+                }
+                CheckError::Inverter(InverterError::InverterTemperature) => {
+                    MonitorError::InverterTemperatureError
+                }
+                _ => MonitorError::InverterTimeoutError,
+            });
+        }
+        board.checkpoint(Checkpoint::Inverter);
+
+        // Monitor the high side output against main's claim
+        let (enable, sense) = board.read_high_side();
+        if self.high_side_monitor.tick(enable, sense).is_err() {
+            self.error(MonitorError::HighSideError);
+        }
+        board.checkpoint(Checkpoint::HighSide);
+
+        let high_side_enable = match self.message.state {
+            MonitorState::Uncalibrated => false,
+            MonitorState::Operational => true,
+            // HV stays connected while main applies the less severe reactions
+            MonitorState::Fault(_, reaction) => {
+                self.calibrated.is_some() && reaction < Reaction::DisconnectHv
+            }
+        };
+        board.set_high_side_enable(high_side_enable);
+        board.set_led(self.message.state == MonitorState::Operational);
+
+        self.log_faults(board);
+        self.millis = self.millis.wrapping_add(1);
+    }
+
+    /// Send the state to main and monitor its answers
+    pub fn link_step<B: MonitorBoard>(&mut self, board: &mut B) {
+        self.message.ping = self.main_app_monitor.send_ping();
+        self.message.diagnostics = self.next_diagnostics(board);
+        board.serial_send(&self.message);
+
+        if let Some(message) = board.serial_receive() {
+            self.main_app_monitor.pong(message.pong);
+            self.high_side_monitor.claim(message.high_side_on);
+            self.inverter_monitor.hv_connected(message.hv_connected);
+            self.direction_monitor.gear(message.gear);
+            if let Some(block) = message.calibration {
+                self.calibrate(&block);
+            }
+            if let Some(request) = message.fault_log {
+                self.fault_log_request(board, request);
+            }
+            self.escalation.ack(message.reaction_ack);
+        }
+        if self.main_app_monitor.tick().is_err() {
+            self.error(MonitorError::PingError);
+        }
+
+        // Reactions that main doesn't acknowledge become more severe
+        self.escalation.tick();
+        self.update_state();
+
+        // One record at a time, programming stalls the CPU for longer than the monitor task
+        // may take
+        if let Some(log) = board.fault_log() {
+            let _ = log.write_record();
+        }
+        board.checkpoint(Checkpoint::MainApp);
+    }
+
+    fn next_diagnostics<B: MonitorBoard>(&mut self, board: &B) -> Diagnostics {
+        self.diagnostics_turn = (self.diagnostics_turn + 1) % 4;
+
+        match self.diagnostics_turn {
+            0 => Diagnostics::Serial(board.serial_stats()),
+            1 => Diagnostics::Can {
+                tec: self.ev_can_status.tec,
+                rec: self.ev_can_status.rec,
+                bus_off: self.bus_off_count,
+            },
+            2 => self.loop_timing,
+            _ => {
+                let (throttle, torque, acceleration) =
+                    self.calibrated.as_ref().map_or((0, 0, 0), |calibrated| {
+                        (
+                            calibrated.throttle_monitor.qualifications(),
+                            calibrated.torque_monitor.qualifications(),
+                            calibrated.unintended_acceleration_monitor.qualifications(),
+                        )
+                    });
+
+                Diagnostics::Qualifications {
+                    throttle,
+                    torque,
+                    inverter: self.inverter_monitor.qualifications(),
+                    high_side: self.high_side_monitor.qualifications(),
+                    direction: self.direction_monitor.qualifications(),
+                    acceleration,
+                }
+            }
+        }
+    }
+
+    /// Set up the calibrated monitors from the first valid block. Later blocks are ignored,
+    /// main sees the CRC of the block in use.
+    fn calibrate(&mut self, block: &CalibrationBlock) {
+        if self.calibrated.is_some() {
+            return;
+        }
+
+        match calibration::check(block) {
+            Ok(calibration) => {
+                self.calibrated = Some(Calibrated::new(&calibration, self.torque_envelope));
+                self.message.calibration_crc = Some(block.crc);
+                if self.message.state == MonitorState::Uncalibrated {
+                    self.message.state = MonitorState::Operational;
+                }
+            }
+            Err(_) => self.error(MonitorError::CalibrationError),
+        }
+    }
+
+    /// Handle a request from main, the response is repeated until the next request
+    fn fault_log_request<B: MonitorBoard>(&mut self, board: &mut B, request: FaultLogRequest) {
+        if self
+            .message
+            .fault_log
+            .is_some_and(|response| response.id == request.id)
+        {
+            return;
+        }
+
+        let reply = match (board.fault_log(), request.command) {
+            (Some(log), FaultLogCommand::Read(index)) => match log.entry(index) {
+                Some(entry) => FaultLogReply::Entry(entry),
+                None => FaultLogReply::Empty,
+            },
+            (Some(log), FaultLogCommand::Clear) => match log.clear() {
+                Ok(_) => FaultLogReply::Cleared,
+                Err(_) => FaultLogReply::Failed,
+            },
+            (None, _) => FaultLogReply::Failed,
+        };
+
+        self.message.fault_log = Some(FaultLogResponse {
+            id: request.id,
+            reply,
+        });
+    }
+
+    /// Queue the faults for the fault log, they are written by `link_step()`. Logging is best
+    /// effort, the monitor keeps running when the flash fails.
+    fn log_faults<B: MonitorBoard>(&mut self, board: &mut B) {
+        let Some(log) = board.fault_log() else {
+            return;
+        };
+
+        if self.millis == 0 && self.message.reset_cause != ResetCause::PowerOn {
+            log.log(FaultEvent::Reset(self.message.reset_cause), 0);
+        }
+
+        if let MonitorState::Fault(error, _) = self.message.state {
+            if self.logged_error != Some(error) {
+                log.log(FaultEvent::Error(error), self.millis);
+                self.logged_error = Some(error);
+            }
+        }
+    }
+
+    fn error(&mut self, error: MonitorError) {
+        if self.escalation.raise(error) {
+            self.update_state();
+        }
+    }
+
+    fn update_state(&mut self) {
+        if let Some((error, reaction)) = self.escalation.fault() {
+            self.message.state = MonitorState::Fault(error, reaction);
+        }
+    }
+}
+
+/// Monitors that depend on the calibration from main
+struct Calibrated<'a, const P: usize, const R: usize> {
+    throttle_monitor: ThrottleMonitor,
+    torque_monitor: TorqueMonitor<'a, P, R>,
+    unintended_acceleration_monitor: UnintendedAccelerationMonitor,
+}
+
+impl<'a, const P: usize, const R: usize> Calibrated<'a, P, R> {
+    fn new(calibration: &Calibration, torque_envelope: &'a TorqueEnvelope<P, R>) -> Self {
+        let (sensor1, sensor2) = (calibration.throttle_sensor1, calibration.throttle_sensor2);
+
+        Self {
+            throttle_monitor: ThrottleMonitor::new(
+                Throttle::new(sensor1, sensor2, calibration.throttle_tolerance),
+                calibration.throttle_timeout as usize,
+            ),
+            torque_monitor: TorqueMonitor::new(
+                torque_envelope,
+                calibration.torque_frame_timeout as usize,
+                calibration.torque_error_timeout as usize,
+            ),
+            unintended_acceleration_monitor: UnintendedAccelerationMonitor::new(
+                UnintendedAccelerationLimits {
+                    pedal_released: calibration.ua_pedal_released,
+                    rpm_rise: calibration.ua_rpm_rise,
+                    drive_torque: calibration.ua_drive_torque,
+                    time: calibration.ua_time as usize,
+                },
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        mock_board::MockBoard,
+        monitors::{DirectionLimits, InverterLimits},
+        schedule::{self, LINK_PERIOD, TASKS},
+    };
+    use common::{
+        adc::AdcError,
+        ev_can::EvCanFrame,
+        fault_log_reader::{FaultLogReader, FaultLogReaderState},
+        gear::Gear,
+        monitor_message::{FaultLogEntry, MainState, MainToMonitor, SerialStats},
+        scheduler::{CycleCounter, Scheduler},
+    };
+
+    /// Accelerator at half travel
+    const ACC_HALF: (u16, u16) = (1500, 3500);
+    const ACC_RELEASED: (u16, u16) = (1000, 3000);
+
+    const CALIBRATION: Calibration = Calibration {
+        throttle_sensor1: (1000, 2000),
+        throttle_sensor2: (3000, 4000),
+        throttle_tolerance: 1500,
+        throttle_timeout: 10,
+        torque_frame_timeout: 10,
+        torque_error_timeout: 3,
+        ua_pedal_released: 3277,
+        ua_rpm_rise: 200,
+        ua_drive_torque: 50,
+        ua_time: 20,
+    };
+
+    fn envelope() -> TorqueEnvelope<2, 2> {
+        TorqueEnvelope::new([0, 65535], [0, 10000], [[100, 0], [1000, 500]], 50)
+    }
+
+    const INVERTER_LIMITS: InverterLimits = InverterLimits {
+        max_rpm: 10000,
+        overspeed_time: 10,
+        dc_millivolt: (200_000, 400_000),
+        dc_voltage_time: 10,
+        max_motor_temperature: 140,
+        max_inverter_temperature: 80,
+        temperature_time: 100,
+        status_timeout: 10,
+        temperature_timeout: 100,
+    };
+
+    const DIRECTION_LIMITS: DirectionLimits = DirectionLimits {
+        torque_deadband: 20,
+        regen_rpm: 100,
+        debounce: 20,
+    };
+
+    fn monitor(envelope: &TorqueEnvelope<2, 2>) -> Monitor<'_, 2, 2> {
+        Monitor::new(
+            envelope,
+            InverterMonitor::new(&INVERTER_LIMITS),
+            HighSideMonitor::new(20),
+            DirectionMonitor::new(DIRECTION_LIMITS),
+            MainAppMonitor::new(10),
+            ResetCause::PowerOn,
+            Ok(()),
+        )
+    }
+
+    /// Execution times are not measured on the host
+    struct NoCycles;
+
+    impl CycleCounter for NoCycles {
+        fn cycles(&self) -> u32 {
+            0
+        }
+    }
+
+    /// A healthy vehicle around the monitor, which runs on the scheduler of the application.
+    /// Main answers every ping in drive, the VCM requests torque every 5 cycles and the accelerator is
+    /// held at half travel. The inverter reports its status every cycle and its temperatures
+    /// every 10 cycles. Main pushes its calibration, reports HV as connected and drives the high
+    /// side output, which turns on when it is enabled by the monitor.
+    struct Vehicle {
+        board: MockBoard,
+        scheduler: Scheduler<TASKS>,
+        cycle: usize,
+        counter: u8,
+        torque: i16,
+        main_alive: bool,
+        /// Main acknowledges the reaction required by the monitor
+        ack_reactions: bool,
+        gear: Gear,
+        /// Fault log request sent by main
+        fault_log: Option<FaultLogRequest>,
+        /// Calibration pushed by main
+        calibration: Option<CalibrationBlock>,
+        ev_can_alive: bool,
+        inverter_alive: bool,
+        millivolt: u32,
+        rpm: i16,
+        temperatures: (u8, u8),
+        /// Main drives the high side output while HV may be connected
+        high_side_on: bool,
+        high_side_drive: bool,
+        /// Main has closed the contactors
+        hv_connected: bool,
+        /// HIGH_SIDE_SENSE stuck at a level
+        high_side_stuck: Option<bool>,
+    }
+
+    impl Vehicle {
+        fn new() -> Self {
+            let mut board = MockBoard::new();
+            board.throttle_sensors = Ok(ACC_HALF);
+
+            Self {
+                board,
+                scheduler: schedule::scheduler(),
+                cycle: 0,
+                counter: 0,
+                torque: 200,
+                main_alive: true,
+                ack_reactions: true,
+                gear: Gear::Drive,
+                fault_log: None,
+                calibration: Some(CalibrationBlock::new(CALIBRATION)),
+                ev_can_alive: true,
+                inverter_alive: true,
+                millivolt: 360_000,
+                rpm: 2000,
+                temperatures: (60, 40),
+                high_side_on: true,
+                high_side_drive: false,
+                hv_connected: true,
+                high_side_stuck: None,
+            }
+        }
+
+        /// Run a number of cycles, checking that all checkpoints are completed whenever the
+        /// watchdog is serviced
+        fn run<const P: usize, const R: usize>(
+            &mut self,
+            monitor: &mut Monitor<'_, P, R>,
+            cycles: usize,
+        ) {
+            for _ in 0..cycles {
+                if self.main_alive {
+                    let state = self.board.sent.last().map(|sent| sent.state);
+                    self.high_side_drive = self.high_side_on
+                        && matches!(
+                            state,
+                            Some(MonitorState::Operational)
+                                | Some(MonitorState::Fault(_, Reaction::Warn))
+                                | Some(MonitorState::Fault(_, Reaction::TorqueLimited))
+                                | Some(MonitorState::Fault(_, Reaction::ZeroTorque))
+                        );
+                    let reaction_ack = match state {
+                        Some(MonitorState::Fault(_, reaction)) if self.ack_reactions => {
+                            Some(reaction)
+                        }
+                        _ => None,
+                    };
+                    self.board.received = self.board.sent.last().map(|sent| MainToMonitor {
+                        pong: sent.ping,
+                        state: MainState::Operational,
+                        accelerator: 0,
+                        high_side_on: Some(self.high_side_drive),
+                        hv_connected: self.hv_connected,
+                        gear: self.gear,
+                        reaction_ack,
+                        fault_log: self.fault_log,
+                        calibration: self
+                            .calibration
+                            .and_then(|block| block.pending(sent.calibration_crc)),
+                    });
+                }
+
+                if self.ev_can_alive && self.cycle.is_multiple_of(5) {
+                    self.board.ev_can.push_back(Ok(EvCanFrame::TorqueRequest {
+                        torque: self.torque,
+                        counter: self.counter,
+                    }));
+                    self.counter = (self.counter + 1) & 0x03;
+                }
+
+                if self.inverter_alive {
+                    self.board.ev_can.push_back(Ok(EvCanFrame::InverterStatus {
+                        millivolt: self.millivolt,
+                        rpm: self.rpm,
+                        current: 0,
+                        error: 0,
+                    }));
+                    if self.cycle.is_multiple_of(10) {
+                        let (motor_temperature, inverter_temperature) = self.temperatures;
+                        self.board
+                            .ev_can
+                            .push_back(Ok(EvCanFrame::InverterTemperature {
+                                motor_temperature,
+                                inverter_temperature,
+                            }));
+                    }
+                }
+
+                self.board.high_side_sense = self
+                    .high_side_stuck
+                    .unwrap_or(self.high_side_drive && self.board.high_side_enable);
+
+                schedule::tick(&mut self.scheduler, &NoCycles, monitor, &mut self.board).unwrap();
+                assert_eq!(self.board.missed_checkpoints, 0);
+                self.cycle += 1;
+            }
+        }
+
+        fn safe_state(&self) -> bool {
+            !self.board.high_side_enable && !self.board.led
+        }
+
+        /// Main applies a reaction with HV connected
+        fn degraded(&self) -> bool {
+            self.board.high_side_enable && !self.board.led
+        }
+    }
+
+    #[test]
+    fn operational() {
+        let envelope = envelope();
+        let mut monitor = monitor(&envelope);
+        let mut vehicle = Vehicle::new();
+
+        vehicle.run(&mut monitor, 1000);
+
+        assert_eq!(monitor.state(), MonitorState::Operational);
+        assert!(vehicle.board.high_side_enable);
+        assert_eq!(vehicle.board.sent.len(), 100);
+        assert_eq!(vehicle.board.sent[99].ping, 100);
+    }
+
+    #[test]
+    fn calibration() {
+        let envelope = envelope();
+        let mut monitor = monitor(&envelope);
+        let mut vehicle = Vehicle::new();
+        vehicle.calibration = None;
+
+        // Without a calibration the monitor doesn't check the throttle, but stays in the safe
+        // state
+        vehicle.board.throttle_sensors = Err(AdcError::Stuck);
+        vehicle.run(&mut monitor, 100);
+        assert_eq!(monitor.state(), MonitorState::Uncalibrated);
+        assert!(vehicle.safe_state());
+
+        // The block is sent until the monitor echoes its CRC
+        let block = CalibrationBlock::new(CALIBRATION);
+        vehicle.board.throttle_sensors = Ok(ACC_HALF);
+        vehicle.calibration = Some(block);
+        vehicle.run(&mut monitor, 20);
+        assert_eq!(monitor.state(), MonitorState::Operational);
+        assert_eq!(
+            vehicle.board.sent.last().unwrap().calibration_crc,
+            Some(block.crc)
+        );
+        assert!(vehicle.board.high_side_enable);
+
+        // A different block is ignored once calibrated
+        let mut recalibration = CALIBRATION;
+        recalibration.throttle_sensor1 = (500, 2000);
+        vehicle.calibration = Some(CalibrationBlock::new(recalibration));
+        vehicle.run(&mut monitor, 100);
+        assert_eq!(monitor.state(), MonitorState::Operational);
+        assert_eq!(
+            vehicle.board.sent.last().unwrap().calibration_crc,
+            Some(block.crc)
+        );
+    }
+
+    #[test]
+    fn bad_calibration() {
+        let mut implausible = CALIBRATION;
+        implausible.throttle_sensor2 = (1000, 2000);
+        let mut corrupt = CalibrationBlock::new(CALIBRATION);
+        corrupt.crc ^= 1;
+
+        for block in [CalibrationBlock::new(implausible), corrupt] {
+            let envelope = envelope();
+            let mut monitor = monitor(&envelope);
+            let mut vehicle = Vehicle::new();
+            vehicle.calibration = Some(block);
+
+            vehicle.run(&mut monitor, 100);
+
+            assert_eq!(
+                monitor.state(),
+                MonitorState::Fault(MonitorError::CalibrationError, Reaction::DisconnectHv)
+            );
+            assert_eq!(vehicle.board.sent.last().unwrap().calibration_crc, None);
+            assert!(vehicle.safe_state());
+        }
+    }
+
+    #[test]
+    fn startup_self_test() {
+        let envelope = envelope();
+        let mut monitor = Monitor::new(
+            &envelope,
+            InverterMonitor::new(&INVERTER_LIMITS),
+            HighSideMonitor::new(20),
+            DirectionMonitor::new(DIRECTION_LIMITS),
+            MainAppMonitor::new(10),
+            ResetCause::PowerOn,
+            Err(SelfTest::FlashCrc),
+        );
+        let mut vehicle = Vehicle::new();
+
+        vehicle.run(&mut monitor, 2);
+
+        let error = MonitorState::Fault(
+            MonitorError::SelfTestError(SelfTest::FlashCrc),
+            Reaction::DisconnectHv,
+        );
+        assert_eq!(vehicle.board.sent[0].state, error);
+        assert!(vehicle.safe_state());
+    }
+
+    #[test]
+    fn self_test() {
+        let envelope = envelope();
+        let mut monitor = monitor(&envelope);
+        let mut vehicle = Vehicle::new();
+
+        vehicle.run(&mut monitor, 10);
+        vehicle.board.self_test = Err(SelfTest::Ram);
+        vehicle.run(&mut monitor, 1);
+
+        assert_eq!(
+            monitor.state(),
+            MonitorState::Fault(
+                MonitorError::SelfTestError(SelfTest::Ram),
+                Reaction::DisconnectHv
+            )
+        );
+        assert!(vehicle.safe_state());
+    }
+
+    #[test]
+    fn sensor_fault() {
+        let envelope = envelope();
+        let mut monitor = monitor(&envelope);
+        let mut vehicle = Vehicle::new();
+
+        vehicle.run(&mut monitor, 10);
+
+        // A short glitch is tolerated
+        vehicle.board.throttle_sensors = Err(AdcError::Railed);
+        vehicle.run(&mut monitor, 5);
+        vehicle.board.throttle_sensors = Ok(ACC_HALF);
+        vehicle.run(&mut monitor, 10);
+        assert_eq!(monitor.state(), MonitorState::Operational);
+
+        // A stuck converter is not
+        vehicle.board.throttle_sensors = Err(AdcError::Stuck);
+        vehicle.run(&mut monitor, 10);
+        assert_eq!(monitor.state(), MonitorState::Operational);
+        vehicle.run(&mut monitor, 1);
+        assert_eq!(
+            monitor.state(),
+            MonitorState::Fault(MonitorError::AcceleratorError, Reaction::ZeroTorque)
+        );
+        assert!(vehicle.degraded());
+
+        // The fault is latched, main keeps acknowledging it
+        vehicle.board.throttle_sensors = Ok(ACC_HALF);
+        vehicle.run(&mut monitor, 100);
+        assert!(vehicle.degraded());
+        assert_eq!(
+            vehicle.board.sent.last().unwrap().state,
+            MonitorState::Fault(MonitorError::AcceleratorError, Reaction::ZeroTorque)
+        );
+    }
+
+    #[test]
+    fn sensor_mismatch() {
+        let envelope = envelope();
+        let mut monitor = monitor(&envelope);
+        let mut vehicle = Vehicle::new();
+
+        vehicle.run(&mut monitor, 20);
+        vehicle.board.throttle_sensors = Ok((1500, 3900));
+        vehicle.run(&mut monitor, 11);
+
+        assert_eq!(
+            monitor.state(),
+            MonitorState::Fault(MonitorError::AcceleratorError, Reaction::ZeroTorque)
+        );
+        assert!(vehicle.degraded());
+    }
+
+    #[test]
+    fn can_loss() {
+        let envelope = envelope();
+        let mut monitor = monitor(&envelope);
+        let mut vehicle = Vehicle::new();
+
+        // The last request is received in the 11th cycle
+        vehicle.run(&mut monitor, 11);
+        vehicle.ev_can_alive = false;
+        vehicle.run(&mut monitor, 10);
+        assert_eq!(monitor.state(), MonitorState::Operational);
+        vehicle.run(&mut monitor, 1);
+
+        assert_eq!(
+            monitor.state(),
+            MonitorState::Fault(MonitorError::TorqueRequestError, Reaction::ZeroTorque)
+        );
+        assert!(vehicle.degraded());
+    }
+
+    #[test]
+    fn bad_frames() {
+        let envelope = envelope();
+        let mut monitor = monitor(&envelope);
+        let mut vehicle = Vehicle::new();
+
+        vehicle.run(&mut monitor, 20);
+        for _ in 0..11 {
+            vehicle.board.ev_can.push_back(Err(EvCanError::BadCrc));
+        }
+        vehicle.run(&mut monitor, 1);
+
+        assert_eq!(
+            monitor.state(),
+            MonitorState::Fault(MonitorError::TorqueRequestError, Reaction::ZeroTorque)
+        );
+    }
+
+    #[test]
+    fn torque_above_envelope() {
+        let envelope = envelope();
+        let mut monitor = monitor(&envelope);
+        let mut vehicle = Vehicle::new();
+
+        vehicle.run(&mut monitor, 20);
+        vehicle.torque = 1000;
+        vehicle.run(&mut monitor, 15);
+        assert_eq!(monitor.state(), MonitorState::Operational);
+        vehicle.run(&mut monitor, 10);
+
+        assert_eq!(
+            monitor.state(),
+            MonitorState::Fault(MonitorError::TorqueRequestError, Reaction::ZeroTorque)
+        );
+        assert!(vehicle.degraded());
+    }
+
+    #[test]
+    fn link_loss() {
+        let envelope = envelope();
+        let mut monitor = monitor(&envelope);
+        let mut vehicle = Vehicle::new();
+
+        // The last answer is received in the second link cycle, the link is lost after 10
+        // more link cycles
+        vehicle.run(&mut monitor, 10);
+        vehicle.main_alive = false;
+        vehicle.run(&mut monitor, 101);
+        assert_eq!(monitor.state(), MonitorState::Operational);
+        vehicle.run(&mut monitor, 1);
+
+        assert_eq!(
+            monitor.state(),
+            MonitorState::Fault(MonitorError::PingError, Reaction::DisconnectHv)
+        );
+        assert!(vehicle.safe_state());
+
+        // The error is latched when main answers again
+        vehicle.main_alive = true;
+        vehicle.run(&mut monitor, 100);
+        assert!(vehicle.safe_state());
+    }
+
+    #[test]
+    fn first_error_is_reported() {
+        let envelope = envelope();
+        let mut monitor = monitor(&envelope);
+        let mut vehicle = Vehicle::new();
+
+        // Main is lost, the torque requests stop shortly before the link times out
+        vehicle.run(&mut monitor, 10);
+        vehicle.main_alive = false;
+        vehicle.run(&mut monitor, 100);
+        vehicle.ev_can_alive = false;
+        vehicle.run(&mut monitor, 30);
+
+        assert_eq!(
+            monitor.state(),
+            MonitorState::Fault(MonitorError::PingError, Reaction::DisconnectHv)
+        );
+    }
+
+    #[test]
+    fn overspeed() {
+        let envelope = envelope();
+        let mut monitor = monitor(&envelope);
+        let mut vehicle = Vehicle::new();
+
+        vehicle.run(&mut monitor, 10);
+        vehicle.rpm = -10500;
+        vehicle.run(&mut monitor, 10);
+        assert_eq!(monitor.state(), MonitorState::Operational);
+        vehicle.run(&mut monitor, 1);
+
+        assert_eq!(
+            monitor.state(),
+            MonitorState::Fault(MonitorError::OverspeedError, Reaction::ZeroTorque)
+        );
+        assert!(vehicle.degraded());
+    }
+
+    #[test]
+    fn dc_voltage() {
+        let envelope = envelope();
+        let mut monitor = monitor(&envelope);
+        let mut vehicle = Vehicle::new();
+
+        vehicle.run(&mut monitor, 10);
+        vehicle.millivolt = 410_000;
+        vehicle.run(&mut monitor, 11);
+
+        assert_eq!(
+            monitor.state(),
+            MonitorState::Fault(MonitorError::DcVoltageError, Reaction::DisconnectHv)
+        );
+        assert!(vehicle.safe_state());
+    }
+
+    #[test]
+    fn key_off() {
+        let envelope = envelope();
+        let mut monitor = monitor(&envelope);
+        let mut vehicle = Vehicle::new();
+        vehicle.run(&mut monitor, 100);
+
+        // The DC link discharges once main has opened the contactors
+        vehicle.hv_connected = false;
+        vehicle.run(&mut monitor, 10);
+        vehicle.millivolt = 12_000;
+        vehicle.run(&mut monitor, 1000);
+        assert_eq!(monitor.state(), MonitorState::Operational);
+
+        // A drop while HV is connected is an error
+        vehicle.hv_connected = true;
+        vehicle.millivolt = 360_000;
+        vehicle.run(&mut monitor, 100);
+        vehicle.millivolt = 150_000;
+        vehicle.run(&mut monitor, 11);
+        assert_eq!(
+            monitor.state(),
+            MonitorState::Fault(MonitorError::DcVoltageError, Reaction::DisconnectHv)
+        );
+    }
+
+    #[test]
+    fn overtemperature() {
+        let envelope = envelope();
+        let mut monitor = monitor(&envelope);
+        let mut vehicle = Vehicle::new();
+
+        vehicle.run(&mut monitor, 10);
+        vehicle.temperatures = (60, 85);
+        vehicle.run(&mut monitor, 100);
+        assert_eq!(monitor.state(), MonitorState::Operational);
+        vehicle.run(&mut monitor, 1);
+
+        assert_eq!(
+            monitor.state(),
+            MonitorState::Fault(
+                MonitorError::InverterTemperatureError,
+                Reaction::TorqueLimited
+            )
+        );
+        assert!(vehicle.degraded());
+    }
+
+    #[test]
+    fn inverter_silent() {
+        let envelope = envelope();
+        let mut monitor = monitor(&envelope);
+        let mut vehicle = Vehicle::new();
+
+        // The inverter may start reporting after the monitor
+        vehicle.inverter_alive = false;
+        vehicle.run(&mut monitor, 1000);
+        assert_eq!(monitor.state(), MonitorState::Operational);
+
+        vehicle.inverter_alive = true;
+        vehicle.run(&mut monitor, 10);
+        vehicle.inverter_alive = false;
+        vehicle.run(&mut monitor, 9);
+        assert_eq!(monitor.state(), MonitorState::Operational);
+        vehicle.run(&mut monitor, 1);
+
+        assert_eq!(
+            monitor.state(),
+            MonitorState::Fault(MonitorError::InverterTimeoutError, Reaction::DisconnectHv)
+        );
+    }
+
+    #[test]
+    fn high_side_off() {
+        let envelope = envelope();
+        let mut monitor = monitor(&envelope);
+        let mut vehicle = Vehicle::new();
+
+        // Main turns the output off and on again
+        vehicle.run(&mut monitor, 30);
+        vehicle.high_side_on = false;
+        vehicle.run(&mut monitor, 50);
+        vehicle.high_side_on = true;
+        vehicle.run(&mut monitor, 50);
+        assert_eq!(monitor.state(), MonitorState::Operational);
+
+        // The output fails while main claims it is on
+        vehicle.high_side_stuck = Some(false);
+        vehicle.run(&mut monitor, 20);
+        assert_eq!(monitor.state(), MonitorState::Operational);
+        vehicle.run(&mut monitor, 1);
+
+        assert_eq!(
+            monitor.state(),
+            MonitorState::Fault(MonitorError::HighSideError, Reaction::DisconnectHv)
+        );
+        assert!(vehicle.safe_state());
+    }
+
+    #[test]
+    fn high_side_stuck_on() {
+        let envelope = envelope();
+        let mut monitor = monitor(&envelope);
+        let mut vehicle = Vehicle::new();
+
+        // The output stays on after main has turned it off, main's claim is received in the
+        // next cycle
+        vehicle.run(&mut monitor, 50);
+        vehicle.high_side_on = false;
+        vehicle.high_side_stuck = Some(true);
+        vehicle.run(&mut monitor, 21);
+        assert_eq!(monitor.state(), MonitorState::Operational);
+        vehicle.run(&mut monitor, 1);
+
+        assert_eq!(
+            monitor.state(),
+            MonitorState::Fault(MonitorError::HighSideError, Reaction::DisconnectHv)
+        );
+        assert!(!vehicle.board.high_side_enable);
+    }
+
+    #[test]
+    fn wrong_direction() {
+        let envelope = envelope();
+        let mut monitor = monitor(&envelope);
+        let mut vehicle = Vehicle::new();
+
+        // Main selects reverse at standstill but the VCM keeps requesting forward torque,
+        // the gear is received in the next link cycle
+        vehicle.rpm = 0;
+        vehicle.run(&mut monitor, 20);
+        vehicle.gear = Gear::Reverse;
+        vehicle.run(&mut monitor, 21);
+        assert_eq!(monitor.state(), MonitorState::Operational);
+        vehicle.run(&mut monitor, 1);
+
+        assert_eq!(
+            monitor.state(),
+            MonitorState::Fault(MonitorError::DirectionError, Reaction::ZeroTorque)
+        );
+        assert!(vehicle.degraded());
+    }
+
+    #[test]
+    fn unintended_acceleration() {
+        let envelope = envelope();
+        let mut monitor = monitor(&envelope);
+        let mut vehicle = Vehicle::new();
+
+        // Standing on the brake, the VCM keeps requesting creep torque within the envelope
+        vehicle.rpm = 0;
+        vehicle.torque = 100;
+        vehicle.board.throttle_sensors = Ok(ACC_RELEASED);
+        vehicle.run(&mut monitor, 20);
+        vehicle.board.brake = true;
+        vehicle.run(&mut monitor, 20);
+        assert_eq!(monitor.state(), MonitorState::Operational);
+        vehicle.run(&mut monitor, 1);
+
+        assert_eq!(
+            monitor.state(),
+            MonitorState::Fault(
+                MonitorError::UnintendedAccelerationError,
+                Reaction::DisconnectHv
+            )
+        );
+        assert!(vehicle.safe_state());
+    }
+
+    #[test]
+    fn reaction_not_acknowledged() {
+        let envelope = envelope();
+        let mut monitor = monitor(&envelope);
+        let mut vehicle = Vehicle::new();
+        vehicle.ack_reactions = false;
+
+        // Main misses a bus off warning, the reaction is escalated every 5 link cycles
+        vehicle.run(&mut monitor, 20);
+        vehicle.board.ev_can_status.bus_off = true;
+        vehicle.run(&mut monitor, 1);
+        vehicle.board.ev_can_status.bus_off = false;
+        let fault = |reaction| MonitorState::Fault(MonitorError::EvCanBusOffError, reaction);
+        assert_eq!(monitor.state(), fault(Reaction::Warn));
+        vehicle.run(&mut monitor, 60);
+        assert_eq!(monitor.state(), fault(Reaction::TorqueLimited));
+        vehicle.run(&mut monitor, 60);
+        assert_eq!(monitor.state(), fault(Reaction::ZeroTorque));
+        assert!(vehicle.degraded());
+        vehicle.run(&mut monitor, 60);
+
+        assert_eq!(monitor.state(), fault(Reaction::DisconnectHv));
+        assert!(vehicle.safe_state());
+    }
+
+    #[test]
+    fn more_severe_fault() {
+        let envelope = envelope();
+        let mut monitor = monitor(&envelope);
+        let mut vehicle = Vehicle::new();
+
+        // The inverter runs hot and then stops reporting
+        vehicle.run(&mut monitor, 10);
+        vehicle.temperatures = (60, 85);
+        vehicle.run(&mut monitor, 200);
+        assert_eq!(
+            monitor.state(),
+            MonitorState::Fault(
+                MonitorError::InverterTemperatureError,
+                Reaction::TorqueLimited
+            )
+        );
+        vehicle.inverter_alive = false;
+        vehicle.run(&mut monitor, 20);
+
+        assert_eq!(
+            monitor.state(),
+            MonitorState::Fault(MonitorError::InverterTimeoutError, Reaction::DisconnectHv)
+        );
+        assert!(vehicle.safe_state());
+    }
+
+    #[test]
+    fn diagnostics() {
+        let envelope = envelope();
+        let mut monitor = monitor(&envelope);
+        let mut vehicle = Vehicle::new();
+
+        vehicle.run(&mut monitor, 30);
+
+        // Two short throttle glitches and a bus off
+        for _ in 0..2 {
+            vehicle.board.throttle_sensors = Err(AdcError::Railed);
+            vehicle.run(&mut monitor, 5);
+            vehicle.board.throttle_sensors = Ok(ACC_HALF);
+            vehicle.run(&mut monitor, 5);
+        }
+        vehicle.board.ev_can_status = CanStatus {
+            tec: 0,
+            rec: 130,
+            bus_off: true,
+        };
+        vehicle.run(&mut monitor, 5);
+        vehicle.board.ev_can_status.bus_off = false;
+        vehicle.board.serial_stats = SerialStats {
+            crc_errors: 3,
+            overruns: 1,
+        };
+        vehicle.run(&mut monitor, 40);
+        assert_eq!(
+            monitor.state(),
+            MonitorState::Fault(MonitorError::EvCanBusOffError, Reaction::Warn)
+        );
+
+        // The kinds of diagnostics take turns
+        let sent = &vehicle.board.sent;
+        let latest = &sent[sent.len() - 4..];
+        for expected in [
+            Diagnostics::Serial(SerialStats {
+                crc_errors: 3,
+                overruns: 1,
+            }),
+            Diagnostics::Can {
+                tec: 0,
+                rec: 130,
+                bus_off: 1,
+            },
+            Diagnostics::Loop {
+                max: 0,
+                jitter: 0,
+                overruns: 0,
+            },
+            // The torque request is too high while the throttle reads released, and the high
+            // side output turns on before main's claim arrives
+            Diagnostics::Qualifications {
+                throttle: 2,
+                torque: 2,
+                inverter: 0,
+                high_side: 1,
+                direction: 0,
+                acceleration: 0,
+            },
+        ] {
+            assert!(
+                latest.iter().any(|message| message.diagnostics == expected),
+                "{expected:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn fault_log() {
+        let envelope = envelope();
+        let mut monitor = Monitor::new(
+            &envelope,
+            InverterMonitor::new(&INVERTER_LIMITS),
+            HighSideMonitor::new(20),
+            DirectionMonitor::new(DIRECTION_LIMITS),
+            MainAppMonitor::new(10),
+            ResetCause::IndependentWatchdog,
+            Ok(()),
+        );
+        let mut vehicle = Vehicle::new();
+
+        vehicle.run(&mut monitor, 10);
+        vehicle.main_alive = false;
+        vehicle.run(&mut monitor, 102);
+        vehicle.main_alive = true;
+        // The link task writes one record of the entry every 10 ms
+        vehicle.run(&mut monitor, 3 * LINK_PERIOD as usize);
+
+        // Main reads the log through the serial link
+        let mut reader = FaultLogReader::new();
+        reader.read();
+        while reader.state() == FaultLogReaderState::Reading {
+            vehicle.fault_log = reader.request();
+            vehicle.run(&mut monitor, LINK_PERIOD as usize);
+            reader.response(vehicle.board.sent.last().unwrap().fault_log.as_ref());
+        }
+
+        let boot = vehicle.board.fault_log.as_ref().unwrap().boot();
+        assert_eq!(
+            reader.entries(),
+            [
+                FaultLogEntry {
+                    boot,
+                    millis: 111,
+                    event: FaultEvent::Error(MonitorError::PingError),
+                    count: 1,
+                },
+                FaultLogEntry {
+                    boot,
+                    millis: 0,
+                    event: FaultEvent::Reset(ResetCause::IndependentWatchdog),
+                    count: 1,
+                },
+            ]
+        );
+
+        // The error is only logged once
+        reader.clear();
+        while reader.state() == FaultLogReaderState::Clearing {
+            vehicle.fault_log = reader.request();
+            vehicle.run(&mut monitor, LINK_PERIOD as usize);
+            reader.response(vehicle.board.sent.last().unwrap().fault_log.as_ref());
+        }
+        vehicle.run(&mut monitor, 10);
+        assert_eq!(reader.state(), FaultLogReaderState::Done);
+        assert_eq!(vehicle.board.fault_log.as_ref().unwrap().entry(0), None);
+    }
+
+    #[test]
+    fn broken_fault_log() {
+        let envelope = envelope();
+        let mut monitor = monitor(&envelope);
+        let mut vehicle = Vehicle::new();
+        vehicle.board.fault_log = None;
+
+        vehicle.fault_log = Some(FaultLogRequest {
+            id: 1,
+            command: FaultLogCommand::Read(0),
+        });
+        vehicle.run(&mut monitor, 30);
+
+        assert_eq!(monitor.state(), MonitorState::Operational);
+        assert_eq!(
+            vehicle.board.sent.last().unwrap().fault_log,
+            Some(FaultLogResponse {
+                id: 1,
+                reply: FaultLogReply::Failed,
+            })
+        );
+    }
+}
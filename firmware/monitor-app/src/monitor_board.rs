@@ -0,0 +1,68 @@
+use common::{
+    adc::AdcError,
+    ev_can::{EvCanError, EvCanFrame},
+    monitor_message::{MainToMonitor, MonitorToMain, SelfTest, SerialStats},
+};
+
+use crate::{checkpoint::Checkpoint, eeprom::Flash, fault_log::FaultLog};
+
+/// Error state of a CAN controller
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct CanStatus {
+    /// Transmit error counter
+    pub tec: u8,
+    /// Receive error counter
+    pub rec: u8,
+    pub bus_off: bool,
+}
+
+/// Everything the monitor loop needs from the hardware. Implemented by the board of the
+/// monitor MCU and by a mock board for tests on the host.
+pub trait MonitorBoard {
+    /// Flash holding the fault log
+    type Flash: Flash;
+
+    /// Status LED
+    fn set_led(&mut self, on: bool);
+
+    /// Enable of the high side drive, low puts the vehicle in the safe state
+    fn set_high_side_enable(&mut self, enable: bool);
+
+    /// Read back the levels of the high side enable pin and the HIGH_SIDE_SENSE input as
+    /// `(enable, sense)`
+    fn read_high_side(&mut self) -> (bool, bool);
+
+    /// Read the brake switch, `true` while the brake is applied
+    fn read_brake(&mut self) -> bool;
+
+    /// Read the throttle sensors in millivolts
+    fn read_throttle_sensors(&mut self) -> Result<(u16, u16), AdcError>;
+
+    /// Receive the next frame from the EV CAN bus, if any. Frames that are received but
+    /// can't be decoded are returned as an error.
+    fn ev_can_receive(&mut self) -> Option<Result<EvCanFrame, EvCanError>>;
+
+    /// Error counters and bus off state of the EV CAN controller
+    fn ev_can_status(&mut self) -> CanStatus;
+
+    /// Send a message to the main MCU without blocking
+    fn serial_send(&mut self, message: &MonitorToMain);
+
+    /// Latest message received from the main MCU since the previous call, if any
+    fn serial_receive(&mut self) -> Option<MainToMonitor>;
+
+    /// Messages from main dropped by the serial receiver since startup
+    fn serial_stats(&self) -> SerialStats;
+
+    /// Run the next part of the self tests
+    fn self_test(&mut self) -> Result<(), SelfTest>;
+
+    /// Mark a part of the loop as completed for the watchdog
+    fn checkpoint(&mut self, checkpoint: Checkpoint);
+
+    /// Refresh the watchdogs if all checkpoints have been completed since the last refresh
+    fn service_watchdog(&mut self);
+
+    /// Fault log, `None` if it could not be opened
+    fn fault_log(&mut self) -> Option<&mut FaultLog<Self::Flash>>;
+}
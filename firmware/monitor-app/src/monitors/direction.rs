@@ -0,0 +1,198 @@
+use common::{ev_can::EvCanFrame, gear::Gear};
+
+use super::{MonitorError, Qualifier};
+
+/// Thresholds of the direction check, in the units of the EV CAN frames
+pub struct DirectionLimits {
+    /// Torque in either direction that is tolerated in any gear
+    pub torque_deadband: i16,
+    /// Lowest motor speed in the direction of the gear for torque against it, below it the
+    /// torque no longer brakes but drives the vehicle the wrong way
+    pub regen_rpm: i16,
+    /// Cycles a violation is tolerated, covers the delay between a gear change on the serial
+    /// link and the torque requests on CAN
+    pub debounce: usize,
+}
+
+/// Checks the sign of the torque requested from the inverter against the gear selected by
+/// main and the motor speed reported by the inverter. Positive torque and rpm are forward.
+///
+/// Park and neutral allow no torque. In drive and reverse, torque against the gear is only
+/// allowed as regen, that is while the motor turns in the direction of the gear.
+pub struct DirectionMonitor {
+    limits: DirectionLimits,
+    /// Latest gear from main, nothing is checked until the first message is received
+    gear: Option<Gear>,
+    torque: i16,
+    rpm: i16,
+    debounce: Qualifier,
+}
+
+impl DirectionMonitor {
+    pub fn new(limits: DirectionLimits) -> Self {
+        let debounce = Qualifier::new(limits.debounce);
+
+        Self {
+            limits,
+            gear: None,
+            torque: 0,
+            rpm: 0,
+            debounce,
+        }
+    }
+
+    /// Gear in the latest message from main
+    pub fn gear(&mut self, gear: Gear) {
+        self.gear = Some(gear);
+    }
+
+    /// Keep the latest torque request and motor speed, other frames are ignored
+    pub fn frame(&mut self, frame: &EvCanFrame) {
+        match frame {
+            EvCanFrame::TorqueRequest { torque, .. } => self.torque = *torque,
+            EvCanFrame::InverterStatus { rpm, .. } => self.rpm = *rpm,
+            _ => {}
+        }
+    }
+
+    /// Check the latest torque request, called once every cycle
+    pub fn tick(&mut self) -> Result<(), MonitorError> {
+        let violation = self.gear.is_some_and(|gear| self.violation(gear));
+
+        self.debounce
+            .check(violation)
+            .map_err(|_| MonitorError::Direction)
+    }
+
+    /// Number of times a violation started to qualify
+    pub fn qualifications(&self) -> u16 {
+        self.debounce.count()
+    }
+
+    fn violation(&self, gear: Gear) -> bool {
+        let direction = i32::from(gear.direction());
+        let deadband = i32::from(self.limits.torque_deadband);
+        let torque = i32::from(self.torque);
+
+        if direction == 0 {
+            return torque.abs() > deadband;
+        }
+
+        // Torque and speed in the direction of the gear
+        let drive = torque * direction;
+        let speed = i32::from(self.rpm) * direction;
+
+        drive < -deadband && speed < i32::from(self.limits.regen_rpm)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIMITS: DirectionLimits = DirectionLimits {
+        torque_deadband: 20,
+        regen_rpm: 100,
+        debounce: 3,
+    };
+
+    fn run(
+        monitor: &mut DirectionMonitor,
+        (torque, rpm): (i16, i16),
+        cycles: usize,
+    ) -> Result<(), MonitorError> {
+        monitor.frame(&EvCanFrame::TorqueRequest { torque, counter: 0 });
+        monitor.frame(&EvCanFrame::InverterStatus {
+            millivolt: 350_000,
+            rpm,
+            current: 0,
+            error: 0,
+        });
+
+        let mut result = Ok(());
+        for _ in 0..cycles {
+            result = monitor.tick();
+        }
+        result
+    }
+
+    #[test]
+    fn no_gear() {
+        let mut monitor = DirectionMonitor::new(LIMITS);
+
+        assert_eq!(run(&mut monitor, (-500, 0), 10), Ok(()));
+    }
+
+    #[test]
+    fn drive_and_regen() {
+        let mut monitor = DirectionMonitor::new(LIMITS);
+
+        monitor.gear(Gear::Drive);
+        assert_eq!(run(&mut monitor, (500, 0), 10), Ok(()));
+        // Regen while moving forward
+        assert_eq!(run(&mut monitor, (-500, 1000), 10), Ok(()));
+        // Holding against a roll back
+        assert_eq!(run(&mut monitor, (500, -300), 10), Ok(()));
+
+        monitor.gear(Gear::Reverse);
+        assert_eq!(run(&mut monitor, (-500, 0), 10), Ok(()));
+        assert_eq!(run(&mut monitor, (500, -1000), 10), Ok(()));
+        assert_eq!(run(&mut monitor, (-500, 300), 10), Ok(()));
+    }
+
+    #[test]
+    fn against_gear() {
+        // Negative torque in drive while standing still
+        let mut monitor = DirectionMonitor::new(LIMITS);
+        monitor.gear(Gear::Drive);
+        assert_eq!(run(&mut monitor, (-500, 0), 3), Ok(()));
+        assert_eq!(
+            run(&mut monitor, (-500, 0), 1),
+            Err(MonitorError::Direction)
+        );
+
+        // Positive torque in reverse while rolling forward slower than the regen speed
+        let mut monitor = DirectionMonitor::new(LIMITS);
+        monitor.gear(Gear::Reverse);
+        assert_eq!(
+            run(&mut monitor, (500, 50), 4),
+            Err(MonitorError::Direction)
+        );
+
+        // Regen that keeps going after the motor has reversed
+        let mut monitor = DirectionMonitor::new(LIMITS);
+        monitor.gear(Gear::Drive);
+        assert_eq!(run(&mut monitor, (-500, 1000), 10), Ok(()));
+        assert_eq!(
+            run(&mut monitor, (-500, -200), 4),
+            Err(MonitorError::Direction)
+        );
+    }
+
+    #[test]
+    fn no_torque_in_park_and_neutral() {
+        for gear in [Gear::Park, Gear::Neutral] {
+            let mut monitor = DirectionMonitor::new(LIMITS);
+            monitor.gear(gear);
+
+            assert_eq!(run(&mut monitor, (20, 0), 10), Ok(()));
+            assert_eq!(run(&mut monitor, (-20, 1000), 10), Ok(()));
+            assert_eq!(
+                run(&mut monitor, (-100, 1000), 4),
+                Err(MonitorError::Direction)
+            );
+        }
+    }
+
+    #[test]
+    fn debounce_gear_change() {
+        let mut monitor = DirectionMonitor::new(LIMITS);
+
+        // The torque request changes before the gear from main arrives
+        monitor.gear(Gear::Drive);
+        assert_eq!(run(&mut monitor, (-300, 0), 2), Ok(()));
+        monitor.gear(Gear::Reverse);
+        assert_eq!(run(&mut monitor, (-300, 0), 10), Ok(()));
+        assert_eq!(monitor.qualifications(), 1);
+    }
+}
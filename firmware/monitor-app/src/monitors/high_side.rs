@@ -0,0 +1,136 @@
+use super::{MonitorError, Qualifier};
+
+/// Compares the high side output that main claims to drive with the state read back by the
+/// monitor. The output is on when HIGH_SIDE_SENSE is high, which is only allowed while the
+/// HIGH_SIDE_ENABLE pin reads high. A mismatch has to be present for longer than the debounce
+/// time before it is reported, main's claim arrives with the messages on the serial link.
+/// While main doesn't claim a state, only the output being on without the enable is checked.
+pub struct HighSideMonitor {
+    /// Latest state claimed by main, off until the first message is received
+    claimed_on: Option<bool>,
+    debounce: Qualifier,
+}
+
+impl HighSideMonitor {
+    /// `debounce` is in cycles
+    pub fn new(debounce: usize) -> Self {
+        Self {
+            claimed_on: Some(false),
+            debounce: Qualifier::new(debounce),
+        }
+    }
+
+    /// State of the output claimed in the latest message from main, `None` while main doesn't
+    /// drive it
+    pub fn claim(&mut self, on: Option<bool>) {
+        self.claimed_on = on;
+    }
+
+    /// Check the levels read back from the enable pin and the sense input, called once every
+    /// cycle
+    pub fn tick(&mut self, enable: bool, sense: bool) -> Result<(), MonitorError> {
+        let mismatch = self.claimed_on.is_some_and(|on| on != sense) || (sense && !enable);
+
+        self.debounce
+            .check(mismatch)
+            .map_err(|_| MonitorError::HighSide)
+    }
+
+    /// Number of times a mismatch started to qualify
+    pub fn qualifications(&self) -> u16 {
+        self.debounce.count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(
+        monitor: &mut HighSideMonitor,
+        (enable, sense): (bool, bool),
+        cycles: usize,
+    ) -> Result<(), MonitorError> {
+        let mut result = Ok(());
+        for _ in 0..cycles {
+            result = monitor.tick(enable, sense);
+        }
+        result
+    }
+
+    #[test]
+    fn matching() {
+        let mut monitor = HighSideMonitor::new(3);
+
+        assert_eq!(run(&mut monitor, (true, false), 10), Ok(()));
+        monitor.claim(Some(true));
+        assert_eq!(run(&mut monitor, (true, true), 10), Ok(()));
+        monitor.claim(Some(false));
+        assert_eq!(run(&mut monitor, (false, false), 10), Ok(()));
+    }
+
+    #[test]
+    fn debounce() {
+        let mut monitor = HighSideMonitor::new(3);
+
+        // The output follows the claim within the debounce time
+        monitor.claim(Some(true));
+        assert_eq!(run(&mut monitor, (true, false), 3), Ok(()));
+        assert_eq!(run(&mut monitor, (true, true), 10), Ok(()));
+        monitor.claim(Some(false));
+        assert_eq!(run(&mut monitor, (true, true), 3), Ok(()));
+        assert_eq!(run(&mut monitor, (true, false), 10), Ok(()));
+
+        // Main claims the output is on but it never turns on
+        monitor.claim(Some(true));
+        assert_eq!(run(&mut monitor, (true, false), 3), Ok(()));
+        assert_eq!(
+            run(&mut monitor, (true, false), 1),
+            Err(MonitorError::HighSide)
+        );
+    }
+
+    #[test]
+    fn output_on_unclaimed() {
+        let mut monitor = HighSideMonitor::new(3);
+
+        assert_eq!(
+            run(&mut monitor, (true, true), 4),
+            Err(MonitorError::HighSide)
+        );
+    }
+
+    #[test]
+    fn output_on_while_disabled() {
+        let mut monitor = HighSideMonitor::new(3);
+
+        // Main is right about the output, but it is on although the enable pin is low
+        monitor.claim(Some(true));
+        assert_eq!(
+            run(&mut monitor, (false, true), 4),
+            Err(MonitorError::HighSide)
+        );
+
+        // Main claims the output is on while the monitor has disabled it
+        let mut monitor = HighSideMonitor::new(3);
+        monitor.claim(Some(true));
+        assert_eq!(
+            run(&mut monitor, (false, false), 4),
+            Err(MonitorError::HighSide)
+        );
+    }
+
+    #[test]
+    fn not_driven() {
+        let mut monitor = HighSideMonitor::new(3);
+        monitor.claim(None);
+
+        assert_eq!(run(&mut monitor, (true, false), 10), Ok(()));
+        assert_eq!(run(&mut monitor, (false, false), 10), Ok(()));
+        assert_eq!(run(&mut monitor, (true, true), 10), Ok(()));
+        assert_eq!(
+            run(&mut monitor, (false, true), 4),
+            Err(MonitorError::HighSide)
+        );
+    }
+}
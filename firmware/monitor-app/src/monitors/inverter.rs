@@ -0,0 +1,341 @@
+use common::{ev_can::EvCanFrame, timeout::Timeout};
+
+use super::{MonitorError, Qualifier};
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum InverterError {
+    Overspeed,
+    DcVoltage,
+    MotorTemperature,
+    InverterTemperature,
+    /// Status or temperature frames are no longer received
+    Stale,
+}
+
+/// Thresholds of the inverter supervision. All times are in cycles, a fault has to be present
+/// for longer than its qualification time before it is reported.
+pub struct InverterLimits {
+    /// Highest motor speed in either direction
+    pub max_rpm: u16,
+    pub overspeed_time: usize,
+    /// Allowed DC link voltage as `(min, max)` in millivolts
+    pub dc_millivolt: (u32, u32),
+    pub dc_voltage_time: usize,
+    pub max_motor_temperature: u8,
+    pub max_inverter_temperature: u8,
+    pub temperature_time: usize,
+    /// Longest time without a status frame, once the first one is received
+    pub status_timeout: usize,
+    /// Longest time without a temperature frame, once the first one is received
+    pub temperature_timeout: usize,
+}
+
+/// Supervision of the health reported by the inverter in its status and temperature frames.
+///
+/// The inverter starts reporting before main closes the contactors, and the DC link discharges
+/// after they open. Undervoltage is only checked while main reports HV as connected, once the
+/// voltage has been inside the window since. Overvoltage is always checked. The frames are
+/// only checked for timeouts once the first one of a kind has been received.
+pub struct InverterMonitor<'a> {
+    limits: &'a InverterLimits,
+    status: Option<(u32, i16)>,
+    temperature: Option<(u8, u8)>,
+    hv_connected: bool,
+    undervoltage_armed: bool,
+    status_timeout: Timeout,
+    temperature_timeout: Timeout,
+    overspeed: Qualifier,
+    dc_voltage: Qualifier,
+    motor_temperature: Qualifier,
+    inverter_temperature: Qualifier,
+}
+
+impl<'a> InverterMonitor<'a> {
+    pub fn new(limits: &'a InverterLimits) -> Self {
+        Self {
+            limits,
+            status: None,
+            temperature: None,
+            hv_connected: false,
+            undervoltage_armed: false,
+            status_timeout: Timeout::new(limits.status_timeout),
+            temperature_timeout: Timeout::new(limits.temperature_timeout),
+            overspeed: Qualifier::new(limits.overspeed_time),
+            dc_voltage: Qualifier::new(limits.dc_voltage_time),
+            motor_temperature: Qualifier::new(limits.temperature_time),
+            inverter_temperature: Qualifier::new(limits.temperature_time),
+        }
+    }
+
+    /// Keep the latest values from the inverter frames, other frames are ignored
+    pub fn frame(&mut self, frame: &EvCanFrame) {
+        match frame {
+            EvCanFrame::InverterStatus { millivolt, rpm, .. } => {
+                self.status = Some((*millivolt, *rpm));
+                self.status_timeout.reset();
+            }
+            EvCanFrame::InverterTemperature {
+                motor_temperature,
+                inverter_temperature,
+            } => {
+                self.temperature = Some((*motor_temperature, *inverter_temperature));
+                self.temperature_timeout.reset();
+            }
+            _ => {}
+        }
+    }
+
+    /// HV state reported by main, the DC link is only checked for undervoltage while connected
+    pub fn hv_connected(&mut self, connected: bool) {
+        self.hv_connected = connected;
+        if !connected {
+            self.undervoltage_armed = false;
+        }
+    }
+
+    /// Check the latest values, called once every cycle
+    pub fn tick(&mut self) -> Result<(), MonitorError> {
+        let limits = self.limits;
+
+        let status_stale = self.status.is_some() && self.status_timeout.tick().is_err();
+        let temperature_stale =
+            self.temperature.is_some() && self.temperature_timeout.tick().is_err();
+
+        let (overspeed, dc_voltage) = match self.status {
+            Some((millivolt, rpm)) => {
+                let (min, max) = limits.dc_millivolt;
+                if self.hv_connected && millivolt >= min {
+                    self.undervoltage_armed = true;
+                }
+                let out_of_window = millivolt > max || (self.undervoltage_armed && millivolt < min);
+
+                (rpm.unsigned_abs() > limits.max_rpm, out_of_window)
+            }
+            None => (false, false),
+        };
+
+        let (motor_hot, inverter_hot) = match self.temperature {
+            Some((motor, inverter)) => (
+                motor > limits.max_motor_temperature,
+                inverter > limits.max_inverter_temperature,
+            ),
+            None => (false, false),
+        };
+
+        let result = qualify(&mut self.overspeed, overspeed, InverterError::Overspeed)
+            .and(qualify(
+                &mut self.dc_voltage,
+                dc_voltage,
+                InverterError::DcVoltage,
+            ))
+            .and(qualify(
+                &mut self.motor_temperature,
+                motor_hot,
+                InverterError::MotorTemperature,
+            ))
+            .and(qualify(
+                &mut self.inverter_temperature,
+                inverter_hot,
+                InverterError::InverterTemperature,
+            ));
+
+        if status_stale || temperature_stale {
+            return Err(MonitorError::Inverter(InverterError::Stale));
+        }
+
+        result.map_err(MonitorError::Inverter)
+    }
+
+    /// Number of times any of the faults started to qualify
+    pub fn qualifications(&self) -> u16 {
+        [
+            &self.overspeed,
+            &self.dc_voltage,
+            &self.motor_temperature,
+            &self.inverter_temperature,
+        ]
+        .iter()
+        .fold(0u16, |sum, qualifier| sum.saturating_add(qualifier.count()))
+    }
+}
+
+fn qualify(
+    qualifier: &mut Qualifier,
+    fault: bool,
+    error: InverterError,
+) -> Result<(), InverterError> {
+    qualifier.check(fault).map_err(|_| error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIMITS: InverterLimits = InverterLimits {
+        max_rpm: 10000,
+        overspeed_time: 2,
+        dc_millivolt: (200_000, 400_000),
+        dc_voltage_time: 2,
+        max_motor_temperature: 140,
+        max_inverter_temperature: 80,
+        temperature_time: 2,
+        status_timeout: 5,
+        temperature_timeout: 20,
+    };
+
+    fn status(millivolt: u32, rpm: i16) -> EvCanFrame {
+        EvCanFrame::InverterStatus {
+            millivolt,
+            rpm,
+            current: 0,
+            error: 0,
+        }
+    }
+
+    fn temperature(motor_temperature: u8, inverter_temperature: u8) -> EvCanFrame {
+        EvCanFrame::InverterTemperature {
+            motor_temperature,
+            inverter_temperature,
+        }
+    }
+
+    /// Receive a status frame and tick a number of times
+    fn run(
+        monitor: &mut InverterMonitor,
+        frame: EvCanFrame,
+        cycles: usize,
+    ) -> Result<(), MonitorError> {
+        let mut result = Ok(());
+        for _ in 0..cycles {
+            monitor.frame(&frame);
+            result = monitor.tick();
+        }
+        result
+    }
+
+    #[test]
+    fn healthy() {
+        let mut monitor = InverterMonitor::new(&LIMITS);
+
+        monitor.frame(&temperature(60, 40));
+        assert_eq!(run(&mut monitor, status(360_000, 3000), 10), Ok(()));
+        assert_eq!(run(&mut monitor, status(360_000, -10000), 10), Ok(()));
+    }
+
+    #[test]
+    fn overspeed() {
+        let mut monitor = InverterMonitor::new(&LIMITS);
+        monitor.frame(&temperature(60, 40));
+
+        assert_eq!(run(&mut monitor, status(360_000, 10001), 2), Ok(()));
+        assert_eq!(
+            run(&mut monitor, status(360_000, -10001), 1),
+            Err(MonitorError::Inverter(InverterError::Overspeed))
+        );
+
+        // Qualification starts over once the speed is back
+        assert_eq!(run(&mut monitor, status(360_000, 9000), 1), Ok(()));
+        assert_eq!(run(&mut monitor, status(360_000, 10001), 2), Ok(()));
+    }
+
+    #[test]
+    fn dc_voltage() {
+        let mut monitor = InverterMonitor::new(&LIMITS);
+
+        // Not precharged yet
+        assert_eq!(run(&mut monitor, status(12_000, 0), 10), Ok(()));
+        assert_eq!(run(&mut monitor, status(360_000, 0), 10), Ok(()));
+        monitor.hv_connected(true);
+        assert_eq!(run(&mut monitor, status(12_000, 0), 10), Ok(()));
+        assert_eq!(run(&mut monitor, status(360_000, 0), 1), Ok(()));
+
+        assert_eq!(run(&mut monitor, status(199_500, 0), 2), Ok(()));
+        assert_eq!(
+            run(&mut monitor, status(199_500, 0), 1),
+            Err(MonitorError::Inverter(InverterError::DcVoltage))
+        );
+
+        // The DC link discharges after the contactors open
+        let mut monitor = InverterMonitor::new(&LIMITS);
+        monitor.hv_connected(true);
+        assert_eq!(run(&mut monitor, status(360_000, 0), 10), Ok(()));
+        monitor.hv_connected(false);
+        assert_eq!(run(&mut monitor, status(150_000, 0), 10), Ok(()));
+        assert_eq!(run(&mut monitor, status(12_000, 0), 10), Ok(()));
+
+        // And is checked again once precharged after the next connect
+        monitor.hv_connected(true);
+        assert_eq!(run(&mut monitor, status(150_000, 0), 10), Ok(()));
+        assert_eq!(run(&mut monitor, status(360_000, 0), 1), Ok(()));
+        assert_eq!(run(&mut monitor, status(150_000, 0), 2), Ok(()));
+        assert_eq!(
+            run(&mut monitor, status(150_000, 0), 1),
+            Err(MonitorError::Inverter(InverterError::DcVoltage))
+        );
+
+        // Overvoltage is always checked
+        let mut monitor = InverterMonitor::new(&LIMITS);
+        assert_eq!(run(&mut monitor, status(400_500, 0), 2), Ok(()));
+        assert_eq!(
+            run(&mut monitor, status(400_500, 0), 1),
+            Err(MonitorError::Inverter(InverterError::DcVoltage))
+        );
+    }
+
+    #[test]
+    fn overtemperature() {
+        let mut monitor = InverterMonitor::new(&LIMITS);
+
+        monitor.frame(&temperature(141, 40));
+        assert_eq!(run(&mut monitor, status(360_000, 0), 2), Ok(()));
+        assert_eq!(
+            run(&mut monitor, status(360_000, 0), 1),
+            Err(MonitorError::Inverter(InverterError::MotorTemperature))
+        );
+
+        monitor.frame(&temperature(140, 81));
+        assert_eq!(run(&mut monitor, status(360_000, 0), 2), Ok(()));
+        assert_eq!(
+            run(&mut monitor, status(360_000, 0), 1),
+            Err(MonitorError::Inverter(InverterError::InverterTemperature))
+        );
+    }
+
+    #[test]
+    fn stale() {
+        // Not checked before the inverter has started reporting
+        let mut monitor = InverterMonitor::new(&LIMITS);
+        for _ in 0..100 {
+            assert_eq!(monitor.tick(), Ok(()));
+        }
+        monitor.frame(&status(360_000, 0));
+        for _ in 0..5 {
+            assert_eq!(monitor.tick(), Ok(()));
+        }
+        assert_eq!(
+            monitor.tick(),
+            Err(MonitorError::Inverter(InverterError::Stale))
+        );
+
+        let mut monitor = InverterMonitor::new(&LIMITS);
+        monitor.frame(&temperature(60, 40));
+        monitor.frame(&status(360_000, 0));
+
+        for _ in 0..5 {
+            assert_eq!(monitor.tick(), Ok(()));
+        }
+        assert_eq!(
+            monitor.tick(),
+            Err(MonitorError::Inverter(InverterError::Stale))
+        );
+
+        // Temperature frames are sent less often
+        let mut monitor = InverterMonitor::new(&LIMITS);
+        monitor.frame(&temperature(60, 40));
+        assert_eq!(run(&mut monitor, status(360_000, 0), 20), Ok(()));
+        assert_eq!(
+            run(&mut monitor, status(360_000, 0), 1),
+            Err(MonitorError::Inverter(InverterError::Stale))
+        );
+    }
+}
@@ -2,9 +2,13 @@ use common::timeout::Timeout;
 
 use super::MonitorError;
 
+/// Supervises the serial link to the main MCU. Every message to main carries a new ping that
+/// main answers with a pong, the link is lost when no recent ping has been answered within
+/// the timeout.
 pub struct MainAppMonitor {
     timout: Timeout,
-    latest_ping: u32,
+    latest_ping: u64,
+    max_age: u64,
 }
 
 impl MainAppMonitor {
@@ -12,22 +16,60 @@ impl MainAppMonitor {
         MainAppMonitor {
             timout: Timeout::new(timeout),
             latest_ping: 0,
+            max_age: timeout as u64,
         }
     }
 
-    pub fn send_ping(&mut self) {
-        self.timout.reset();
-
-        self.latest_ping += 1;
+    /// Next ping to send to main
+    pub fn send_ping(&mut self) -> u64 {
+        self.latest_ping = self.latest_ping.wrapping_add(1);
+        self.latest_ping
+    }
 
-        // TODO: Send
+    /// Pong received from main, only an answer to one of the pings sent within the timeout
+    /// keeps the link alive.
+    pub fn pong(&mut self, pong: u64) {
+        if self.latest_ping.wrapping_sub(pong) <= self.max_age {
+            self.timout.reset();
+        }
     }
 
     pub fn tick(&mut self) -> Result<(), MonitorError> {
         self.timout.tick()?;
 
-        // TODO: Check for pong
-
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pong() {
+        let mut main_app_monitor = MainAppMonitor::new(2);
+
+        for _ in 0..10 {
+            let ping = main_app_monitor.send_ping();
+            main_app_monitor.pong(ping);
+            assert_eq!(main_app_monitor.tick(), Ok(()));
+        }
+    }
+
+    #[test]
+    fn link_lost() {
+        let mut main_app_monitor = MainAppMonitor::new(2);
+
+        let ping = main_app_monitor.send_ping();
+        assert_eq!(main_app_monitor.tick(), Ok(()));
+        main_app_monitor.send_ping();
+        assert_eq!(main_app_monitor.tick(), Ok(()));
+        main_app_monitor.send_ping();
+        assert_eq!(main_app_monitor.tick(), Err(MonitorError::Timout));
+
+        // An answer to a ping that is too old doesn't count
+        main_app_monitor.send_ping();
+        main_app_monitor.pong(ping);
+        assert_eq!(main_app_monitor.tick(), Err(MonitorError::Timout));
+    }
+}
@@ -1,9 +1,14 @@
-use common::{throttle::ThrottleError, timeout::TimeoutError};
+use common::{adc::AdcError, throttle::ThrottleError, timeout::TimeoutError};
 
 #[derive(Debug, PartialEq)]
 pub enum MonitorError {
     Throttle(ThrottleError),
+    Adc(AdcError),
+    Inverter(InverterError),
     Torque,
+    HighSide,
+    Direction,
+    UnintendedAcceleration,
     Main,
     Timout,
 }
@@ -14,11 +19,26 @@ impl From<TimeoutError> for MonitorError {
     }
 }
 
+mod direction;
+pub use direction::*;
+
+mod high_side;
+pub use high_side::*;
+
+mod inverter;
+pub use inverter::*;
+
 mod main_app;
 pub use main_app::*;
 
+mod qualifier;
+pub use qualifier::*;
+
 mod throttle;
 pub use throttle::*;
 
 mod torque;
 pub use torque::*;
+
+mod unintended_acceleration;
+pub use unintended_acceleration::*;
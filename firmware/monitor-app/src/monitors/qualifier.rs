@@ -0,0 +1,63 @@
+use common::timeout::{Timeout, TimeoutError};
+
+/// Qualification of a fault, the fault has to be present for longer than the qualification
+/// time before it is reported. Counts how often a fault started to qualify, which shows how
+/// close a monitor came to an error.
+pub struct Qualifier {
+    timeout: Timeout,
+    qualifying: bool,
+    count: u16,
+}
+
+impl Qualifier {
+    /// `time` is the number of checks a fault is tolerated
+    pub fn new(time: usize) -> Self {
+        Self {
+            timeout: Timeout::new(time),
+            qualifying: false,
+            count: 0,
+        }
+    }
+
+    /// Check whether the fault is present, fails once it has been present for longer than the
+    /// qualification time
+    pub fn check(&mut self, fault: bool) -> Result<(), TimeoutError> {
+        if !fault {
+            self.timeout.reset();
+            self.qualifying = false;
+            return Ok(());
+        }
+
+        if !self.qualifying {
+            self.qualifying = true;
+            self.count = self.count.saturating_add(1);
+        }
+        self.timeout.tick()
+    }
+
+    /// Number of times a fault started to qualify
+    pub fn count(&self) -> u16 {
+        self.count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn qualify() {
+        let mut qualifier = Qualifier::new(2);
+
+        assert_eq!(qualifier.check(false), Ok(()));
+        assert_eq!(qualifier.check(true), Ok(()));
+        assert_eq!(qualifier.check(true), Ok(()));
+        assert_eq!(qualifier.check(false), Ok(()));
+        assert_eq!(qualifier.check(true), Ok(()));
+        assert_eq!(qualifier.check(true), Ok(()));
+        assert_eq!(qualifier.check(true), Err(TimeoutError::Elapsed));
+        assert_eq!(qualifier.check(true), Err(TimeoutError::Elapsed));
+
+        assert_eq!(qualifier.count(), 2);
+    }
+}
@@ -1,34 +1,45 @@
-use super::MonitorError;
-use common::{
-    throttle::Throttle,
-    timeout::{Timeout, TimeoutError},
-};
-
-pub struct ThrottleMonitor<'a> {
-    throttle: &'a Throttle,
-    timeout: Timeout,
+use super::{MonitorError, Qualifier};
+use common::{adc::AdcError, throttle::Throttle, timeout::TimeoutError};
+
+pub struct ThrottleMonitor {
+    throttle: Throttle,
+    timeout: Qualifier,
 }
 
-impl<'a> ThrottleMonitor<'a> {
-    pub fn new(throttle: &'a Throttle, timeout: usize) -> Self {
+impl ThrottleMonitor {
+    pub fn new(throttle: Throttle, timeout: usize) -> Self {
         Self {
             throttle,
-            timeout: Timeout::new(timeout),
+            timeout: Qualifier::new(timeout),
         }
     }
 
     pub fn check(&mut self, sensor1: u16, sensor2: u16) -> Result<u16, MonitorError> {
         match self.throttle.position(sensor1, sensor2) {
-            Err(err) => match self.timeout.tick() {
+            Err(err) => match self.timeout.check(true) {
                 Err(TimeoutError::Elapsed) => Err(MonitorError::Throttle(err)),
                 Ok(_) => Ok(0),
             },
             Ok(position) => {
-                self.timeout.reset();
+                self.timeout.check(false).ok();
                 Ok(position)
             }
         }
     }
+
+    /// Report that the sensors could not be read, handled the same way as an invalid
+    /// sensor value.
+    pub fn sensor_fault(&mut self, err: AdcError) -> Result<u16, MonitorError> {
+        match self.timeout.check(true) {
+            Err(TimeoutError::Elapsed) => Err(MonitorError::Adc(err)),
+            Ok(_) => Ok(0),
+        }
+    }
+
+    /// Number of times invalid readings started to qualify
+    pub fn qualifications(&self) -> u16 {
+        self.timeout.count()
+    }
 }
 
 #[cfg(test)]
@@ -39,15 +50,30 @@ mod tests {
     #[test]
     fn monitor() {
         let throttle = Throttle::new((1000, 2000), (3000, 4000), 1500);
-        let mut throttle_monitor = ThrottleMonitor::new(&throttle, 2);
+        let mut throttle_monitor = ThrottleMonitor::new(throttle, 2);
 
         assert_eq!(throttle_monitor.check(1200, 3700), Ok(0));
         assert_eq!(throttle_monitor.check(1200, 3700), Ok(0));
         assert_eq!(
             throttle_monitor.check(1200, 3700),
-            Err(MonitorError::ThrottleError(ThrottleError::SensorMismatch))
+            Err(MonitorError::Throttle(ThrottleError::SensorMismatch))
+        );
+
+        assert_eq!(throttle_monitor.check(1500, 3500), Ok(32767));
+    }
+
+    #[test]
+    fn sensor_fault() {
+        let throttle = Throttle::new((1000, 2000), (3000, 4000), 1500);
+        let mut throttle_monitor = ThrottleMonitor::new(throttle, 1);
+
+        assert_eq!(throttle_monitor.sensor_fault(AdcError::Stuck), Ok(0));
+        assert_eq!(
+            throttle_monitor.sensor_fault(AdcError::Stuck),
+            Err(MonitorError::Adc(AdcError::Stuck))
         );
 
         assert_eq!(throttle_monitor.check(1500, 3500), Ok(32767));
+        assert_eq!(throttle_monitor.sensor_fault(AdcError::Railed), Ok(0));
     }
 }
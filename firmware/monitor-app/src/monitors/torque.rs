@@ -1,17 +1,31 @@
-use common::{ev_can::EvCanFrame, timeout::Timeout};
+use common::{
+    ev_can::EvCanFrame,
+    timeout::{Timeout, TimeoutError},
+    torque_envelope::TorqueEnvelope,
+};
 
-use super::MonitorError;
+use super::{MonitorError, Qualifier};
 
-pub struct TorqueMonitor {
+pub struct TorqueMonitor<'a, const P: usize, const R: usize> {
+    envelope: &'a TorqueEnvelope<P, R>,
     frame_timeout: Timeout,
-    error_timeout: Timeout,
+    error_timeout: Qualifier,
+    rpm: i16,
+    counter: Option<u8>,
 }
 
-impl TorqueMonitor {
-    pub fn new(frame_timeout: usize, error_timeout: usize) -> Self {
+impl<'a, const P: usize, const R: usize> TorqueMonitor<'a, P, R> {
+    pub fn new(
+        envelope: &'a TorqueEnvelope<P, R>,
+        frame_timeout: usize,
+        error_timeout: usize,
+    ) -> Self {
         Self {
+            envelope,
             frame_timeout: Timeout::new(frame_timeout),
-            error_timeout: Timeout::new(error_timeout),
+            error_timeout: Qualifier::new(error_timeout),
+            rpm: 0,
+            counter: None,
         }
     }
 
@@ -20,12 +34,170 @@ impl TorqueMonitor {
         Ok(())
     }
 
+    /// Report a received frame that could not be decoded because of a bad CRC. It is
+    /// handled as an invalid torque request.
+    pub fn bad_frame(&mut self) -> Result<(), MonitorError> {
+        self.counter = None;
+        self.invalid()
+    }
+
+    /// Check a received frame. `TorqueRequest` frames are checked for a consecutive counter and
+    /// against the torque envelope for the current accelerator position and the latest motor speed
+    /// reported by the inverter. An invalid request will only result in an error if it persists
+    /// longer than the error timeout.
     pub fn frame(&mut self, acc_position: u16, frame: &EvCanFrame) -> Result<(), MonitorError> {
-        if let EvCanFrame::TorqueRequest { torque, counter } = frame {
-            // TODO: Check that frame corresponds with accelerator position.
-            Ok(())
-        } else {
-            Err(MonitorError::Torque)
+        match frame {
+            EvCanFrame::TorqueRequest { torque, counter } => {
+                let expected = self.counter.map(|previous| (previous + 1) & 0x03);
+                self.counter = Some(*counter);
+
+                if expected.is_some_and(|expected| expected != *counter) {
+                    return self.invalid();
+                }
+
+                self.frame_timeout.reset();
+
+                match self.envelope.check(acc_position, self.rpm, *torque) {
+                    Err(_) => self.invalid(),
+                    Ok(_) => {
+                        self.error_timeout.check(false).ok();
+                        Ok(())
+                    }
+                }
+            }
+            EvCanFrame::InverterStatus { rpm, .. } => {
+                self.rpm = *rpm;
+                Ok(())
+            }
+            _ => Ok(()),
         }
     }
+
+    /// Number of times invalid requests started to qualify
+    pub fn qualifications(&self) -> u16 {
+        self.error_timeout.count()
+    }
+
+    fn invalid(&mut self) -> Result<(), MonitorError> {
+        match self.error_timeout.check(true) {
+            Err(TimeoutError::Elapsed) => Err(MonitorError::Torque),
+            Ok(_) => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn envelope() -> TorqueEnvelope<2, 2> {
+        TorqueEnvelope::new([0, 65535], [0, 10000], [[100, 0], [1000, 500]], 50)
+    }
+
+    fn request(counter: u8, torque: i16) -> EvCanFrame {
+        EvCanFrame::TorqueRequest { torque, counter }
+    }
+
+    fn status(rpm: i16) -> EvCanFrame {
+        EvCanFrame::InverterStatus {
+            millivolt: 350_000,
+            rpm,
+            current: 0,
+            error: 0,
+        }
+    }
+
+    #[test]
+    fn pass() {
+        let envelope = envelope();
+        let mut torque_monitor = TorqueMonitor::new(&envelope, 10, 0);
+
+        assert_eq!(torque_monitor.frame(0, &request(0, 0)), Ok(()));
+        assert_eq!(torque_monitor.frame(65535, &request(1, 1000)), Ok(()));
+        assert_eq!(torque_monitor.frame(0, &request(2, -500)), Ok(()));
+    }
+
+    #[test]
+    fn fail() {
+        let envelope = envelope();
+        let mut torque_monitor = TorqueMonitor::new(&envelope, 10, 2);
+
+        assert_eq!(torque_monitor.frame(0, &request(0, 1000)), Ok(()));
+        assert_eq!(torque_monitor.frame(0, &request(1, 1000)), Ok(()));
+        assert_eq!(
+            torque_monitor.frame(0, &request(2, 1000)),
+            Err(MonitorError::Torque)
+        );
+
+        // A valid request resets the qualification
+        let mut torque_monitor = TorqueMonitor::new(&envelope, 10, 2);
+        assert_eq!(torque_monitor.frame(0, &request(0, 1000)), Ok(()));
+        assert_eq!(torque_monitor.frame(0, &request(1, 1000)), Ok(()));
+        assert_eq!(torque_monitor.frame(0, &request(2, 0)), Ok(()));
+        assert_eq!(torque_monitor.frame(0, &request(3, 1000)), Ok(()));
+    }
+
+    #[test]
+    fn boundary() {
+        let envelope = envelope();
+        let mut torque_monitor = TorqueMonitor::new(&envelope, 10, 0);
+
+        assert_eq!(torque_monitor.frame(0, &request(0, 150)), Ok(()));
+        assert_eq!(
+            torque_monitor.frame(0, &request(1, 151)),
+            Err(MonitorError::Torque)
+        );
+
+        // The envelope follows the motor speed reported by the inverter
+        assert_eq!(torque_monitor.frame(65535, &status(10000)), Ok(()));
+        assert_eq!(torque_monitor.frame(65535, &request(2, 550)), Ok(()));
+        assert_eq!(
+            torque_monitor.frame(65535, &request(3, 551)),
+            Err(MonitorError::Torque)
+        );
+    }
+
+    #[test]
+    fn counter() {
+        let envelope = envelope();
+        let mut torque_monitor = TorqueMonitor::new(&envelope, 10, 1);
+
+        assert_eq!(torque_monitor.frame(0, &request(2, 0)), Ok(()));
+        assert_eq!(torque_monitor.frame(0, &request(3, 0)), Ok(()));
+        assert_eq!(torque_monitor.frame(0, &request(0, 0)), Ok(()));
+
+        // A skipped counter is an invalid request
+        assert_eq!(torque_monitor.frame(0, &request(2, 0)), Ok(()));
+        assert_eq!(torque_monitor.frame(0, &request(3, 0)), Ok(()));
+        assert_eq!(torque_monitor.frame(0, &request(3, 0)), Ok(()));
+        assert_eq!(
+            torque_monitor.frame(0, &request(3, 0)),
+            Err(MonitorError::Torque)
+        );
+    }
+
+    #[test]
+    fn bad_frame() {
+        let envelope = envelope();
+        let mut torque_monitor = TorqueMonitor::new(&envelope, 10, 1);
+
+        assert_eq!(torque_monitor.frame(0, &request(0, 0)), Ok(()));
+        assert_eq!(torque_monitor.bad_frame(), Ok(()));
+        // The counter sequence restarts after a lost frame
+        assert_eq!(torque_monitor.frame(0, &request(2, 0)), Ok(()));
+        assert_eq!(torque_monitor.bad_frame(), Ok(()));
+        assert_eq!(torque_monitor.bad_frame(), Err(MonitorError::Torque));
+    }
+
+    #[test]
+    fn frame_timeout() {
+        let envelope = envelope();
+        let mut torque_monitor = TorqueMonitor::new(&envelope, 1, 0);
+
+        assert_eq!(torque_monitor.tick(), Ok(()));
+        assert_eq!(torque_monitor.tick(), Err(MonitorError::Timout));
+
+        assert_eq!(torque_monitor.frame(0, &request(0, 0)), Ok(()));
+        assert_eq!(torque_monitor.tick(), Ok(()));
+    }
 }
@@ -0,0 +1,199 @@
+use common::ev_can::EvCanFrame;
+
+use super::{MonitorError, Qualifier};
+
+/// Thresholds of the unintended acceleration check, calibrated by main
+pub struct UnintendedAccelerationLimits {
+    /// Throttle position up to which the pedal counts as released
+    pub pedal_released: u16,
+    /// Rise of the motor speed above its lowest value since the check started
+    pub rpm_rise: u16,
+    /// Torque accelerating the motor, in the units of the torque request
+    pub drive_torque: u16,
+    /// Cycles a rising speed or drive torque is tolerated
+    pub time: usize,
+}
+
+/// Detects the motor accelerating while the driver brakes with the pedal released. The check
+/// runs from the monitor's own throttle reading and the brake switch, the motor speed comes
+/// from the inverter status frames.
+///
+/// While braking, the speed has to stay close to the lowest speed seen since the brake was
+/// applied, and the requested torque must not drive the motor in its direction of rotation.
+/// Near standstill torque in either direction counts as drive torque.
+pub struct UnintendedAccelerationMonitor {
+    limits: UnintendedAccelerationLimits,
+    torque: i16,
+    rpm: i16,
+    /// Lowest motor speed since the check started, `None` while not braking
+    min_speed: Option<u16>,
+    speed_rise: Qualifier,
+    drive_torque: Qualifier,
+}
+
+impl UnintendedAccelerationMonitor {
+    pub fn new(limits: UnintendedAccelerationLimits) -> Self {
+        let speed_rise = Qualifier::new(limits.time);
+        let drive_torque = Qualifier::new(limits.time);
+
+        Self {
+            limits,
+            torque: 0,
+            rpm: 0,
+            min_speed: None,
+            speed_rise,
+            drive_torque,
+        }
+    }
+
+    /// Keep the latest torque request and motor speed, other frames are ignored
+    pub fn frame(&mut self, frame: &EvCanFrame) {
+        match frame {
+            EvCanFrame::TorqueRequest { torque, .. } => self.torque = *torque,
+            EvCanFrame::InverterStatus { rpm, .. } => self.rpm = *rpm,
+            _ => {}
+        }
+    }
+
+    /// Check the motor against the throttle position and the brake switch, called once every
+    /// cycle
+    pub fn tick(&mut self, throttle_position: u16, brake: bool) -> Result<(), MonitorError> {
+        let braking = brake && throttle_position <= self.limits.pedal_released;
+        let speed = self.rpm.unsigned_abs();
+
+        let (rising, driving) = if braking {
+            let min_speed = self.min_speed.map_or(speed, |min| min.min(speed));
+            self.min_speed = Some(min_speed);
+
+            (
+                speed - min_speed > self.limits.rpm_rise,
+                self.drive_torque() > i32::from(self.limits.drive_torque),
+            )
+        } else {
+            self.min_speed = None;
+            (false, false)
+        };
+
+        let speed_rise = self.speed_rise.check(rising);
+        let drive_torque = self.drive_torque.check(driving);
+
+        speed_rise
+            .and(drive_torque)
+            .map_err(|_| MonitorError::UnintendedAcceleration)
+    }
+
+    /// Number of times a rising speed or drive torque started to qualify
+    pub fn qualifications(&self) -> u16 {
+        self.speed_rise
+            .count()
+            .saturating_add(self.drive_torque.count())
+    }
+
+    /// Requested torque in the direction of rotation
+    fn drive_torque(&self) -> i32 {
+        let torque = i32::from(self.torque);
+
+        if self.rpm.unsigned_abs() <= self.limits.rpm_rise {
+            torque.abs()
+        } else {
+            torque * i32::from(self.rpm.signum())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Synthetic traces of the throttle position, brake switch, motor speed and torque request
+    /// sampled every 10 ms, see `traces/README.md`
+    const BRAKE_TO_STOP: &str = include_str!("../../traces/brake_to_stop.csv");
+    const BRAKE_DOWNHILL: &str = include_str!("../../traces/brake_downhill.csv");
+    const TWO_FOOTED: &str = include_str!("../../traces/two_footed.csv");
+    const STUCK_TORQUE: &str = include_str!("../../traces/stuck_torque.csv");
+    const RUNAWAY: &str = include_str!("../../traces/runaway.csv");
+
+    const SAMPLE_CYCLES: usize = 10;
+
+    const LIMITS: UnintendedAccelerationLimits = UnintendedAccelerationLimits {
+        pedal_released: 3277,
+        rpm_rise: 200,
+        drive_torque: 50,
+        time: 100,
+    };
+
+    /// Replay a trace, returns the time in ms of the first error
+    fn replay(trace: &str) -> Option<u32> {
+        let mut monitor = UnintendedAccelerationMonitor::new(LIMITS);
+
+        for line in trace.lines().skip(1) {
+            let mut columns = line.split(',').map(str::trim);
+            let mut column = || columns.next().unwrap();
+            let millis: u32 = column().parse().unwrap();
+            let throttle_position: u16 = column().parse().unwrap();
+            let brake = column() == "1";
+            let rpm: i16 = column().parse().unwrap();
+            let torque: i16 = column().parse().unwrap();
+
+            monitor.frame(&EvCanFrame::TorqueRequest { torque, counter: 0 });
+            monitor.frame(&EvCanFrame::InverterStatus {
+                millivolt: 350_000,
+                rpm,
+                current: 0,
+                error: 0,
+            });
+
+            for cycle in 0..SAMPLE_CYCLES {
+                if monitor.tick(throttle_position, brake).is_err() {
+                    return Some(millis + cycle as u32);
+                }
+            }
+        }
+
+        None
+    }
+
+    #[test]
+    fn braking() {
+        // Regen down to standstill and holding on the brake
+        assert_eq!(replay(BRAKE_TO_STOP), None);
+        // The speed wavers while braking downhill
+        assert_eq!(replay(BRAKE_DOWNHILL), None);
+        // The pedal is pressed together with the brake
+        assert_eq!(replay(TWO_FOOTED), None);
+    }
+
+    #[test]
+    fn stuck_torque() {
+        // Drive torque is requested at standstill from 1000 ms
+        assert_eq!(replay(STUCK_TORQUE), Some(1100));
+    }
+
+    #[test]
+    fn runaway() {
+        // The motor speeds up from 1200 ms although no torque is requested, it has risen by
+        // more than 200 rpm at 1490 ms
+        assert_eq!(replay(RUNAWAY), Some(1590));
+    }
+
+    #[test]
+    fn brake_released() {
+        let mut monitor = UnintendedAccelerationMonitor::new(LIMITS);
+        monitor.frame(&EvCanFrame::TorqueRequest {
+            torque: 500,
+            counter: 0,
+        });
+
+        for _ in 0..200 {
+            assert_eq!(monitor.tick(0, false), Ok(()));
+        }
+        for _ in 0..100 {
+            assert_eq!(monitor.tick(0, true), Ok(()));
+        }
+        assert_eq!(
+            monitor.tick(0, true),
+            Err(MonitorError::UnintendedAcceleration)
+        );
+        assert_eq!(monitor.qualifications(), 1);
+    }
+}
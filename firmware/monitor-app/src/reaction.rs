@@ -0,0 +1,216 @@
+//! Graded reactions of the monitor. Every fault maps to a reaction that main has to apply and
+//! acknowledge, a reaction that isn't acknowledged in time is escalated one level at a time
+//! up to disconnecting HV, which the monitor does by itself.
+
+use common::{
+    monitor_message::{MonitorError, Reaction},
+    timeout::Timeout,
+};
+
+/// Link cycles main has to acknowledge a reaction, 50 ms
+pub const ACK_TIMEOUT: usize = 5;
+
+/// Reaction the monitor requires for a fault
+pub fn reaction(error: MonitorError) -> Reaction {
+    match error {
+        MonitorError::EvCanBusOffError => Reaction::Warn,
+        // Main can keep driving below the temperature limits of the inverter
+        MonitorError::MotorTemperatureError | MonitorError::InverterTemperatureError => {
+            Reaction::TorqueLimited
+        }
+        // The inverter is still reporting and follows a zero torque request
+        MonitorError::AcceleratorError
+        | MonitorError::TorqueRequestError
+        | MonitorError::DirectionError
+        | MonitorError::OverspeedError => Reaction::ZeroTorque,
+        // The monitor can't rely on main or the inverter to remove torque
+        MonitorError::PingError
+        | MonitorError::SelfTestError(_)
+        | MonitorError::DcVoltageError
+        | MonitorError::InverterTimeoutError
+        | MonitorError::HighSideError
+        | MonitorError::CalibrationError
+        | MonitorError::UnintendedAccelerationError => Reaction::DisconnectHv,
+    }
+}
+
+/// The fault with the most severe reaction so far and its escalation. Faults are latched until
+/// the next reset, a later fault only replaces the current one if it requires a more severe
+/// reaction.
+pub struct Escalation {
+    fault: Option<(MonitorError, Reaction)>,
+    acknowledged: bool,
+    ack_timeout: Timeout,
+}
+
+impl Escalation {
+    /// `ack_timeout` is the number of link cycles main has to acknowledge a reaction
+    pub fn new(ack_timeout: usize) -> Self {
+        Self {
+            fault: None,
+            acknowledged: false,
+            ack_timeout: Timeout::new(ack_timeout),
+        }
+    }
+
+    pub fn fault(&self) -> Option<(MonitorError, Reaction)> {
+        self.fault
+    }
+
+    /// Report a fault, returns `true` if it became the current fault
+    pub fn raise(&mut self, error: MonitorError) -> bool {
+        let reaction = reaction(error);
+        if self.fault.is_some_and(|(_, current)| current >= reaction) {
+            return false;
+        }
+
+        self.set(error, reaction);
+        true
+    }
+
+    /// Check the acknowledgement in a message from main, an acknowledgement of a more severe
+    /// reaction counts as well
+    pub fn ack(&mut self, ack: Option<Reaction>) {
+        if let (Some((_, reaction)), Some(ack)) = (self.fault, ack) {
+            if ack >= reaction {
+                self.acknowledged = true;
+            }
+        }
+    }
+
+    /// Escalate a reaction that has not been acknowledged in time, called once every link
+    /// cycle
+    pub fn tick(&mut self) {
+        let Some((error, reaction)) = self.fault else {
+            return;
+        };
+        if self.acknowledged || reaction == Reaction::DisconnectHv {
+            return;
+        }
+
+        if self.ack_timeout.tick().is_err() {
+            self.set(error, escalate(reaction));
+        }
+    }
+
+    fn set(&mut self, error: MonitorError, reaction: Reaction) {
+        self.fault = Some((error, reaction));
+        self.acknowledged = false;
+        self.ack_timeout.reset();
+    }
+}
+
+fn escalate(reaction: Reaction) -> Reaction {
+    match reaction {
+        Reaction::Warn => Reaction::TorqueLimited,
+        Reaction::TorqueLimited => Reaction::ZeroTorque,
+        Reaction::ZeroTorque | Reaction::DisconnectHv => Reaction::DisconnectHv,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn most_severe_fault() {
+        let mut escalation = Escalation::new(3);
+        assert_eq!(escalation.fault(), None);
+
+        assert!(escalation.raise(MonitorError::MotorTemperatureError));
+        assert!(!escalation.raise(MonitorError::InverterTemperatureError));
+        assert!(!escalation.raise(MonitorError::EvCanBusOffError));
+        assert_eq!(
+            escalation.fault(),
+            Some((MonitorError::MotorTemperatureError, Reaction::TorqueLimited))
+        );
+
+        assert!(escalation.raise(MonitorError::PingError));
+        assert!(!escalation.raise(MonitorError::HighSideError));
+        assert_eq!(
+            escalation.fault(),
+            Some((MonitorError::PingError, Reaction::DisconnectHv))
+        );
+    }
+
+    #[test]
+    fn acknowledged() {
+        let mut escalation = Escalation::new(3);
+
+        escalation.raise(MonitorError::EvCanBusOffError);
+        escalation.tick();
+        escalation.ack(None);
+        escalation.tick();
+        escalation.ack(Some(Reaction::Warn));
+        for _ in 0..10 {
+            escalation.tick();
+        }
+        assert_eq!(
+            escalation.fault(),
+            Some((MonitorError::EvCanBusOffError, Reaction::Warn))
+        );
+
+        // A new reaction has to be acknowledged again, a more severe acknowledgement will do
+        escalation.raise(MonitorError::DirectionError);
+        escalation.ack(Some(Reaction::TorqueLimited));
+        escalation.ack(Some(Reaction::DisconnectHv));
+        for _ in 0..10 {
+            escalation.tick();
+        }
+        assert_eq!(
+            escalation.fault(),
+            Some((MonitorError::DirectionError, Reaction::ZeroTorque))
+        );
+    }
+
+    #[test]
+    fn escalation_policy() {
+        let mut escalation = Escalation::new(3);
+        escalation.raise(MonitorError::EvCanBusOffError);
+
+        // Every level is held for the acknowledgement timeout and escalated in the next cycle
+        for (level, next) in [
+            (Reaction::Warn, Reaction::TorqueLimited),
+            (Reaction::TorqueLimited, Reaction::ZeroTorque),
+            (Reaction::ZeroTorque, Reaction::DisconnectHv),
+        ] {
+            for _ in 0..3 {
+                assert_eq!(
+                    escalation.fault(),
+                    Some((MonitorError::EvCanBusOffError, level))
+                );
+                escalation.tick();
+            }
+            escalation.tick();
+            assert_eq!(
+                escalation.fault(),
+                Some((MonitorError::EvCanBusOffError, next))
+            );
+        }
+
+        // HV stays disconnected
+        for _ in 0..10 {
+            escalation.tick();
+        }
+        assert_eq!(
+            escalation.fault(),
+            Some((MonitorError::EvCanBusOffError, Reaction::DisconnectHv))
+        );
+
+        // Escalation stops once main acknowledges the current level
+        let mut escalation = Escalation::new(3);
+        escalation.raise(MonitorError::MotorTemperatureError);
+        for _ in 0..4 {
+            escalation.tick();
+        }
+        escalation.ack(Some(Reaction::TorqueLimited));
+        escalation.ack(Some(Reaction::ZeroTorque));
+        for _ in 0..10 {
+            escalation.tick();
+        }
+        assert_eq!(
+            escalation.fault(),
+            Some((MonitorError::MotorTemperatureError, Reaction::ZeroTorque))
+        );
+    }
+}
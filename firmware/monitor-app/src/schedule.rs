@@ -0,0 +1,69 @@
+//! Task table of the monitor application. A tick is one millisecond, the monitors run every
+//! tick while the serial link and the watchdog run every 10 ticks in different ticks.
+
+use common::scheduler::{CycleCounter, Overrun, Scheduler, Task};
+
+use crate::{monitor::Monitor, monitor_board::MonitorBoard};
+
+/// Core clock cycles per tick at 48 MHz
+pub const TICK_CYCLES: u32 = 48_000;
+
+/// The link runs first so that a lost link disables the outputs in the same tick
+pub const LINK_TASK: usize = 0;
+pub const MONITOR_TASK: usize = 1;
+pub const WATCHDOG_TASK: usize = 2;
+pub const TASKS: usize = 3;
+
+/// Ticks between messages to main. At 115200 baud the largest message takes about 5 ms on the
+/// serial link and a full frame buffer about 7 ms.
+pub const LINK_PERIOD: u32 = 10;
+
+const TASK_TABLE: [Task; TASKS] = [
+    // Includes a record of the fault log, up to 0.7 ms
+    Task {
+        period: LINK_PERIOD,
+        offset: 1,
+        deadline: TICK_CYCLES * 3 / 4,
+    },
+    Task {
+        period: 1,
+        offset: 0,
+        deadline: TICK_CYCLES / 2,
+    },
+    // The WWDG window is 4.8 ms to 43.7 ms, the first refresh 8 ms after the start is well
+    // inside it
+    Task {
+        period: 10,
+        offset: 8,
+        deadline: TICK_CYCLES / 20,
+    },
+];
+
+pub fn scheduler() -> Scheduler<TASKS> {
+    Scheduler::new(TASK_TABLE, TICK_CYCLES)
+}
+
+/// Run the tasks of one tick. Overruns are counted in the statistics of the scheduler, the
+/// watchdogs take care of a loop that is too slow. The statistics are reported to main in the
+/// diagnostics of the monitor.
+pub fn tick<B: MonitorBoard, C: CycleCounter, const P: usize, const R: usize>(
+    scheduler: &mut Scheduler<TASKS>,
+    counter: &C,
+    monitor: &mut Monitor<'_, P, R>,
+    board: &mut B,
+) -> Result<(), Overrun> {
+    let result = scheduler.dispatch(counter, |task| match task {
+        LINK_TASK => monitor.link_step(board),
+        MONITOR_TASK => monitor.step(board),
+        _ => board.service_watchdog(),
+    });
+
+    // The monitor task is delayed by the link task in the ticks they share
+    let tick = scheduler.tick_stats();
+    let overruns = (0..TASKS).fold(tick.overruns, |overruns, task| {
+        overruns.saturating_add(scheduler.task_stats(task).overruns)
+    });
+    monitor.loop_timing(tick.max, scheduler.task_stats(LINK_TASK).max, overruns);
+
+    result
+}
@@ -0,0 +1,63 @@
+use common::monitor_message::SelfTest;
+
+/// Cross-check of the system clock against the independent LSI oscillator. A timer running
+/// on the system clock captures every `periods` LSI period, the number of timer ticks
+/// between two captures has to match the LSI frequency tolerance.
+pub struct ClockCheck {
+    min_ticks: u16,
+    max_ticks: u16,
+}
+
+impl ClockCheck {
+    /// Create a new check for a timer clock in Hz, the min/max LSI frequency in Hz and the
+    /// number of LSI periods between captures.
+    pub fn new(timer_hz: u32, lsi_hz: (u32, u32), periods: u32) -> Self {
+        let ticks = |lsi_hz: u32| (timer_hz as u64 * periods as u64 / lsi_hz as u64) as u16;
+
+        Self {
+            min_ticks: ticks(lsi_hz.1),
+            max_ticks: ticks(lsi_hz.0),
+        }
+    }
+
+    /// Check two consecutive captures of a free running 16 bit timer
+    pub fn check(&self, first: u16, second: u16) -> Result<(), SelfTest> {
+        let ticks = second.wrapping_sub(first);
+
+        if ticks < self.min_ticks || ticks > self.max_ticks {
+            return Err(SelfTest::Clock);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn range() {
+        let clock_check = ClockCheck::new(48_000_000, (30_000, 50_000), 8);
+
+        assert_eq!(clock_check.check(0, 7680), Ok(()));
+        assert_eq!(clock_check.check(0, 12800), Ok(()));
+        assert_eq!(clock_check.check(0, 7679), Err(SelfTest::Clock));
+        assert_eq!(clock_check.check(0, 12801), Err(SelfTest::Clock));
+    }
+
+    #[test]
+    fn wrapping() {
+        let clock_check = ClockCheck::new(48_000_000, (30_000, 50_000), 8);
+
+        assert_eq!(
+            clock_check.check(60000, 60000u16.wrapping_add(9600)),
+            Ok(())
+        );
+        // A system clock running at twice the frequency
+        assert_eq!(
+            clock_check.check(60000, 60000u16.wrapping_add(19200)),
+            Err(SelfTest::Clock)
+        );
+    }
+}
@@ -0,0 +1,93 @@
+use core::arch::asm;
+
+use common::monitor_message::SelfTest;
+
+const PATTERN: u32 = 0xaaaa_aaaa;
+
+/// Check that all core registers r0-r12 and lr can hold a checkerboard pattern and its
+/// inverse. All registers are written before any of them is compared, which also catches
+/// registers that are coupled to each other.
+pub fn registers() -> Result<(), SelfTest> {
+    if fill_and_compare(PATTERN) != 0 || fill_and_compare(!PATTERN) != 0 {
+        return Err(SelfTest::CpuRegisters);
+    }
+
+    Ok(())
+}
+
+/// Returns 0 if all registers held `pattern`. The callee saved registers, including the
+/// ones that can't be used as operands on this target, are saved and restored inside the
+/// assembly.
+fn fill_and_compare(pattern: u32) -> u32 {
+    let result: u32;
+
+    // Safety: Every register that is modified is either restored or marked as clobbered
+    unsafe {
+        asm!(
+            "push {{r4-r7}}",
+            "mov r4, r8",
+            "mov r5, r9",
+            "mov r6, r10",
+            "mov r7, r11",
+            "push {{r4-r7}}",
+            "mov r1, r0",
+            "mov r2, r0",
+            "mov r3, r0",
+            "mov r4, r0",
+            "mov r5, r0",
+            "mov r6, r0",
+            "mov r7, r0",
+            "mov r8, r0",
+            "mov r9, r0",
+            "mov r10, r0",
+            "mov r11, r0",
+            "mov r12, r0",
+            "mov lr, r0",
+            "cmp r1, r0",
+            "bne 1f",
+            "cmp r2, r0",
+            "bne 1f",
+            "cmp r3, r0",
+            "bne 1f",
+            "cmp r4, r0",
+            "bne 1f",
+            "cmp r5, r0",
+            "bne 1f",
+            "cmp r6, r0",
+            "bne 1f",
+            "cmp r7, r0",
+            "bne 1f",
+            "cmp r8, r0",
+            "bne 1f",
+            "cmp r9, r0",
+            "bne 1f",
+            "cmp r10, r0",
+            "bne 1f",
+            "cmp r11, r0",
+            "bne 1f",
+            "cmp r12, r0",
+            "bne 1f",
+            "cmp lr, r0",
+            "bne 1f",
+            "movs r0, #0",
+            "b 2f",
+            "1:",
+            "movs r0, #1",
+            "2:",
+            "pop {{r4-r7}}",
+            "mov r8, r4",
+            "mov r9, r5",
+            "mov r10, r6",
+            "mov r11, r7",
+            "pop {{r4-r7}}",
+            inout("r0") pattern => result,
+            out("r1") _,
+            out("r2") _,
+            out("r3") _,
+            out("r12") _,
+            out("lr") _,
+        );
+    }
+
+    result
+}
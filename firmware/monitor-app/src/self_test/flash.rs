@@ -0,0 +1,88 @@
+use common::{
+    crc32::{calc_crc32, generate_lookup, update_crc32, CRC32_INIT},
+    monitor_message::SelfTest,
+};
+
+const CRC32_LOOKUP: [u32; 256] = generate_lookup(0x04c1_1db7);
+
+/// CRC check of the application image against the CRC embedded after linking. The check
+/// can be done in one go or spread out over several steps.
+pub struct FlashCheck<'a> {
+    image: &'a [u8],
+    expected: u32,
+    offset: usize,
+    crc: u32,
+}
+
+impl<'a> FlashCheck<'a> {
+    pub fn new(image: &'a [u8], expected: u32) -> Self {
+        Self {
+            image,
+            expected,
+            offset: 0,
+            crc: CRC32_INIT,
+        }
+    }
+
+    /// Check the complete image
+    pub fn check(&self) -> Result<(), SelfTest> {
+        if calc_crc32(self.image, &CRC32_LOOKUP) != self.expected {
+            return Err(SelfTest::FlashCrc);
+        }
+
+        Ok(())
+    }
+
+    /// Add the next `chunk` bytes to the CRC, the result is checked once the end of the
+    /// image has been reached and the next step starts over.
+    pub fn step(&mut self, chunk: usize) -> Result<(), SelfTest> {
+        let end = (self.offset + chunk).min(self.image.len());
+        self.crc = update_crc32(self.crc, &self.image[self.offset..end], &CRC32_LOOKUP);
+        self.offset = end;
+
+        if self.offset < self.image.len() {
+            return Ok(());
+        }
+
+        let crc = self.crc;
+        self.offset = 0;
+        self.crc = CRC32_INIT;
+
+        if crc != self.expected {
+            return Err(SelfTest::FlashCrc);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IMAGE: &[u8] = b"123456789";
+
+    #[test]
+    fn check() {
+        assert_eq!(FlashCheck::new(IMAGE, 0x0376_e6e7).check(), Ok(()));
+        assert_eq!(
+            FlashCheck::new(IMAGE, 0xffff_ffff).check(),
+            Err(SelfTest::FlashCrc)
+        );
+    }
+
+    #[test]
+    fn step() {
+        let mut flash_check = FlashCheck::new(IMAGE, 0x0376_e6e7);
+
+        for _ in 0..2 {
+            assert_eq!(flash_check.step(4), Ok(()));
+            assert_eq!(flash_check.step(4), Ok(()));
+            assert_eq!(flash_check.step(4), Ok(()));
+        }
+
+        let mut flash_check = FlashCheck::new(IMAGE, 0x0376_e6e8);
+        assert_eq!(flash_check.step(8), Ok(()));
+        assert_eq!(flash_check.step(8), Err(SelfTest::FlashCrc));
+    }
+}
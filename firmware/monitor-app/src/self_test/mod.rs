@@ -0,0 +1,7 @@
+//! Hardware independent parts of the IEC 60730 class B self tests
+
+pub mod clock;
+#[cfg(target_arch = "arm")]
+pub mod cpu;
+pub mod flash;
+pub mod ram;
@@ -0,0 +1,141 @@
+use common::monitor_message::SelfTest;
+
+/// Data backgrounds for the word oriented March test. Each one is used as the "0" of the
+/// test with its inverse as the "1", which also covers faults between bits of a word.
+const BACKGROUNDS: [u32; 6] = [
+    0x0000_0000,
+    0x5555_5555,
+    0x3333_3333,
+    0x0f0f_0f0f,
+    0x00ff_00ff,
+    0x0000_ffff,
+];
+
+/// Number of words tested by every step of a `RamCheck`
+pub const RAM_BLOCK: usize = 16;
+
+/// March C- test of `len` words starting at `start`, the contents are destroyed.
+///
+/// ⇕(w0); ⇑(r0,w1); ⇑(r1,w0); ⇓(r0,w1); ⇓(r1,w0); ⇕(r0)
+///
+/// # Safety
+/// The memory must be valid for reads and writes and nothing else may use it during the test.
+pub unsafe fn march_c(start: *mut u32, len: usize) -> Result<(), SelfTest> {
+    for background in BACKGROUNDS {
+        let zero = background;
+        let one = !background;
+
+        for i in 0..len {
+            start.add(i).write_volatile(zero);
+        }
+        for i in 0..len {
+            read_write(start.add(i), zero, one)?;
+        }
+        for i in 0..len {
+            read_write(start.add(i), one, zero)?;
+        }
+        for i in (0..len).rev() {
+            read_write(start.add(i), zero, one)?;
+        }
+        for i in (0..len).rev() {
+            read_write(start.add(i), one, zero)?;
+        }
+        for i in 0..len {
+            if start.add(i).read_volatile() != zero {
+                return Err(SelfTest::Ram);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+unsafe fn read_write(word: *mut u32, expected: u32, value: u32) -> Result<(), SelfTest> {
+    if word.read_volatile() != expected {
+        return Err(SelfTest::Ram);
+    }
+    word.write_volatile(value);
+    Ok(())
+}
+
+/// Transparent March C- test of RAM that is in use. Every step saves a block of `RAM_BLOCK`
+/// words, tests it and restores the contents, after the last block it starts over.
+pub struct RamCheck {
+    start: *mut u32,
+    len: usize,
+    offset: usize,
+}
+
+impl RamCheck {
+    /// Create a check of `len` words starting at `start`
+    pub fn new(start: *mut u32, len: usize) -> Self {
+        Self {
+            start,
+            len,
+            offset: 0,
+        }
+    }
+
+    /// Number of steps needed to test the complete memory
+    pub fn blocks(&self) -> usize {
+        self.len.div_ceil(RAM_BLOCK)
+    }
+
+    /// Test the next block. A failing block is not restored.
+    ///
+    /// # Safety
+    /// The memory must be valid for reads and writes and nothing else may access it during
+    /// the step, including interrupts and DMA. The check itself must not live in the memory.
+    pub unsafe fn step(&mut self) -> Result<(), SelfTest> {
+        let block = self.start.add(self.offset);
+        let len = RAM_BLOCK.min(self.len - self.offset);
+
+        let mut backup = [0u32; RAM_BLOCK];
+        for (i, word) in backup.iter_mut().take(len).enumerate() {
+            *word = block.add(i).read_volatile();
+        }
+
+        march_c(block, len)?;
+
+        for (i, word) in backup.iter().take(len).enumerate() {
+            block.add(i).write_volatile(*word);
+        }
+
+        self.offset += len;
+        if self.offset >= self.len {
+            self.offset = 0;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn march() {
+        let mut memory = [0x1234_5678u32; 40];
+
+        assert_eq!(
+            unsafe { march_c(memory.as_mut_ptr(), memory.len()) },
+            Ok(())
+        );
+        assert!(memory.iter().all(|&word| word == BACKGROUNDS[5]));
+    }
+
+    #[test]
+    fn transparent() {
+        let mut memory: [u32; 40] = core::array::from_fn(|i| i as u32 * 0x0101_0101);
+        let expected = memory;
+
+        let mut check = RamCheck::new(memory.as_mut_ptr(), memory.len());
+        assert_eq!(check.blocks(), 3);
+
+        for _ in 0..check.blocks() + 1 {
+            assert_eq!(unsafe { check.step() }, Ok(()));
+        }
+        assert_eq!(memory, expected);
+    }
+}
@@ -0,0 +1,176 @@
+use core::{
+    ptr::{addr_of, addr_of_mut},
+    slice,
+};
+
+use common::monitor_message::SelfTest;
+use monitor_app::self_test::{
+    clock::ClockCheck,
+    cpu,
+    flash::FlashCheck,
+    ram::{self, RamCheck},
+};
+use stm32f0xx_hal::pac;
+
+/// TIM14 runs on PCLK
+const TIMER_HZ: u32 = 48_000_000;
+/// LSI frequency range from the datasheet
+const LSI_HZ: (u32, u32) = (30_000, 50_000);
+/// The input capture prescaler captures every 8th LSI period
+const LSI_PERIODS: u32 = 8;
+/// Number of polls to wait for a capture, about a millisecond at 48 MHz
+const CAPTURE_TIMEOUT: u32 = 5000;
+/// The clock is cross-checked every this many cycles
+const CLOCK_CHECK_PERIOD: u16 = 100;
+/// Number of flash bytes added to the CRC every cycle
+const FLASH_CHUNK: usize = 256;
+/// Stack that is left untouched below the current stack pointer during the startup RAM test
+const STACK_MARGIN: usize = 1024;
+
+/// Describes the application image, placed in flash by the linker. See `memory.x`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct FlashCrc {
+    start: u32,
+    end: u32,
+    crc: u32,
+}
+
+extern "C" {
+    static __flash_crc: FlashCrc;
+    static mut __sdata: u32;
+    static mut __ebss: u32;
+    static mut __sheap: u32;
+}
+
+/// IEC 60730 class B self tests. `startup()` runs the complete tests before the watchdogs
+/// are started, `cycle()` spreads the tests over the cycles of the main loop.
+///
+/// The RAM with statics (`.data` and `.bss`) is tested transparently, buffers written by DMA
+/// are placed in `.uninit` which is not tested at runtime.
+pub struct SelfTests {
+    tim14: pac::TIM14,
+    ram: RamCheck,
+    flash: FlashCheck<'static>,
+    clock: ClockCheck,
+    cycle: u16,
+}
+
+impl SelfTests {
+    pub fn new(tim14: pac::TIM14) -> Self {
+        // Safety: Only the LSI, MCO and TIM14 enable bits are modified
+        let rcc = unsafe { &*pac::RCC::ptr() };
+        rcc.csr.modify(|_, w| w.lsion().on());
+        while rcc.csr.read().lsirdy().is_not_ready() {}
+        rcc.cfgr.modify(|_, w| w.mco().lsi());
+        rcc.apb1enr.modify(|_, w| w.tim14en().enabled());
+
+        // Free running timer capturing every 8th period of the MCO output, which is
+        // connected to the input of TIM14 internally.
+        // Safety: Raw bits of the remap, capture selection and prescaler fields
+        unsafe {
+            tim14.or.write(|w| w.rmp().bits(0b11));
+            tim14
+                .ccmr1_input()
+                .write(|w| w.cc1s().bits(0b01).ic1psc().bits(0b11));
+            tim14.arr.write(|w| w.bits(0xffff));
+        }
+        tim14.psc.write(|w| w.psc().bits(0));
+        tim14.ccer.write(|w| w.cc1e().set_bit());
+        tim14.cr1.write(|w| w.cen().enabled());
+
+        // Safety: The symbols are provided by the linker, the image range is filled in by the
+        // linker as well
+        let (ram, flash) = unsafe {
+            let sdata = addr_of_mut!(__sdata);
+            let ebss = addr_of_mut!(__ebss);
+            let ram = RamCheck::new(sdata, ebss.offset_from(sdata) as usize);
+
+            let record = addr_of!(__flash_crc).read_volatile();
+            let image = slice::from_raw_parts(
+                record.start as *const u8,
+                (record.end - record.start) as usize,
+            );
+
+            (ram, FlashCheck::new(image, record.crc))
+        };
+
+        Self {
+            tim14,
+            ram,
+            flash,
+            clock: ClockCheck::new(TIMER_HZ, LSI_HZ, LSI_PERIODS),
+            cycle: 0,
+        }
+    }
+
+    /// Run all tests completely. The unused stack is tested destructively, this has to be
+    /// called early and takes too long to be done with the watchdogs running.
+    pub fn startup(&mut self) -> Result<(), SelfTest> {
+        cpu::registers()?;
+
+        // Safety: Everything between the heap start and the stack margin is unused
+        unsafe {
+            let sheap = addr_of_mut!(__sheap);
+            let stack = cortex_m::register::msp::read() as usize - STACK_MARGIN;
+            ram::march_c(sheap, (stack - sheap as usize) / 4)?;
+        }
+
+        for _ in 0..self.ram.blocks() {
+            self.ram_step()?;
+        }
+
+        self.flash.check()?;
+
+        self.clock()
+    }
+
+    /// Run the next part of all tests, called once every cycle
+    pub fn cycle(&mut self) -> Result<(), SelfTest> {
+        cpu::registers()?;
+        self.ram_step()?;
+        self.flash.step(FLASH_CHUNK)?;
+
+        self.cycle += 1;
+        if self.cycle >= CLOCK_CHECK_PERIOD {
+            self.cycle = 0;
+            self.clock()?;
+        }
+
+        Ok(())
+    }
+
+    fn ram_step(&mut self) -> Result<(), SelfTest> {
+        // Safety: The statics are only ever accessed from the main loop and interrupts are
+        // disabled, the test itself lives on the stack
+        cortex_m::interrupt::free(|_| unsafe { self.ram.step() })
+    }
+
+    /// Measure the number of timer ticks between two consecutive captures
+    fn clock(&mut self) -> Result<(), SelfTest> {
+        self.tim14
+            .sr
+            .write(|w| w.cc1if().clear_bit().cc1of().clear_bit());
+
+        let first = self.capture()?;
+        let second = self.capture()?;
+
+        // A capture was missed, skip this measurement
+        if self.tim14.sr.read().cc1of().bit_is_set() {
+            return Ok(());
+        }
+
+        self.clock.check(first, second)
+    }
+
+    /// Wait for the next capture, reading the capture register clears the flag
+    fn capture(&mut self) -> Result<u16, SelfTest> {
+        for _ in 0..CAPTURE_TIMEOUT {
+            if self.tim14.sr.read().cc1if().bit_is_set() {
+                return Ok(self.tim14.ccr1.read().ccr().bits());
+            }
+        }
+
+        Err(SelfTest::Clock)
+    }
+}
@@ -0,0 +1,56 @@
+use monitor_app::checkpoint::{Checkpoint, Checkpoints};
+use stm32f0xx_hal::{pac, prelude::*, watchdog};
+
+/// The WWDG counter starts at this value after every refresh
+const WWDG_COUNTER: u8 = 0x7f;
+/// The WWDG may only be refreshed when the counter is below the window, an earlier refresh
+/// resets the MCU. With PCLK at 48 MHz the counter decrements every 682.7 us, 7 decrements
+/// to the window and 64 to the reset give a refresh window of 4.8 ms to 43.7 ms.
+const WWDG_WINDOW: u8 = 0x78;
+
+/// Independent and window watchdog that are only refreshed when all monitors have completed
+/// their cycle. The IWDG runs on the LSI and catches a stopped main clock, the WWDG catches
+/// a loop that runs too fast as well as too slow.
+pub struct Watchdog {
+    iwdg: watchdog::Watchdog,
+    wwdg: pac::WWDG,
+    checkpoints: Checkpoints,
+}
+
+impl Watchdog {
+    /// Start both watchdogs, from here on `service()` has to be called every cycle
+    pub fn new(iwdg: pac::IWDG, wwdg: pac::WWDG) -> Self {
+        // Safety: Only the enable bit for WWDG is modified
+        let rcc = unsafe { &*pac::RCC::ptr() };
+        rcc.apb1enr.modify(|_, w| w.wwdgen().enabled());
+
+        let mut iwdg = watchdog::Watchdog::new(iwdg);
+        iwdg.start(10.hz());
+
+        wwdg.cfr.write(|w| w.wdgtb().div8().w().bits(WWDG_WINDOW));
+        wwdg.cr.write(|w| w.t().bits(WWDG_COUNTER).wdga().enabled());
+
+        Self {
+            iwdg,
+            wwdg,
+            checkpoints: Checkpoints::new(),
+        }
+    }
+
+    /// Mark a monitor as completed for this cycle
+    pub fn checkpoint(&mut self, checkpoint: Checkpoint) {
+        self.checkpoints.complete(checkpoint);
+    }
+
+    /// End the current cycle, the watchdogs are refreshed if all monitors have completed.
+    /// Called on the fixed schedule of the watchdog task, the WWDG resets the MCU if that
+    /// comes before its window opens.
+    pub fn service(&mut self) {
+        if !self.checkpoints.end_cycle() {
+            return;
+        }
+
+        self.wwdg.cr.write(|w| w.t().bits(WWDG_COUNTER));
+        self.iwdg.feed();
+    }
+}